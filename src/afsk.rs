@@ -0,0 +1,372 @@
+use crate::fsk::{FskConfig, FskModulator, SINE_TABLE, SINE_TABLE_SIZE};
+use crate::hdlc::{Ax25Frame, HdlcDecoder, HdlcEncoder};
+use crate::linecode::{EncodedBits, LineCode, LineDecoder};
+use crate::tnc::Message;
+use crate::whitening::Whitening;
+
+/// Bell 202 tones, as used by 1200 baud APRS.
+pub const BELL202_MARK_HZ: u32 = 1200;
+pub const BELL202_SPACE_HZ: u32 = 2200;
+pub const AFSK1200_BAUD: u32 = 1200;
+
+/// KISS `TXDELAY` and `TXTAIL` are both in units of 10 ms.
+const KISS_TIME_UNIT_MS: u32 = 10;
+const FLAG_BITS: u32 = 8;
+
+/// Lowest sample rate [AfskDemodulator] takes: anything slower cannot carry [BELL202_SPACE_HZ].
+pub const AFSK_MIN_SAMPLE_RATE: u32 = 2 * BELL202_SPACE_HZ + 1;
+/// Highest sample rate [AfskDemodulator] takes.
+pub const AFSK_MAX_SAMPLE_RATE: u32 = 96_000;
+/// Longest bit the correlators hold, in samples.
+const MAX_SAMPLES_PER_BIT: usize = (AFSK_MAX_SAMPLE_RATE / AFSK1200_BAUD) as usize;
+/// The fraction of the bit clock's phase kept at each transition, pulling it towards sampling
+/// halfway between transitions.
+const PLL_INERTIA: f32 = 0.74;
+
+/// Settings of an AFSK1200 transmitter. [AfskConfig::apply] takes the KISS parameters a host
+/// sends in [Message::SetTXDelay] and [Message::SetTXTail].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AfskConfig {
+    pub sample_rate: u32,
+    /// Time to send flags before the frame, for the receiver's squelch and clock to settle, in
+    /// units of 10 ms.
+    pub tx_delay: u8,
+    /// Time to keep sending flags after the frame, in units of 10 ms.
+    pub tx_tail: u8,
+}
+
+impl AfskConfig {
+    /// 300 ms of delay and 100 ms of tail, the usual TNC defaults.
+    pub const DEFAULT: Self = Self::new(48_000);
+
+    pub const fn new(_sample_rate: u32) -> Self {
+        Self {
+            sample_rate: _sample_rate,
+            tx_delay: 30,
+            tx_tail: 10,
+        }
+    }
+
+    /// Takes the timing from a KISS message. Returns whether the message was one that applies.
+    pub fn apply(&mut self, _message: &Message) -> bool {
+        match *_message {
+            Message::SetTXDelay(_tx_delay) => self.tx_delay = _tx_delay,
+            Message::SetTXTail(_tx_tail) => self.tx_tail = _tx_tail,
+            _ => return false,
+        }
+        true
+    }
+
+    pub const fn fsk_config(&self) -> FskConfig {
+        FskConfig {
+            mark_hz: BELL202_MARK_HZ,
+            space_hz: BELL202_SPACE_HZ,
+            baud: AFSK1200_BAUD,
+            sample_rate: self.sample_rate,
+            whitening: Whitening::None,
+        }
+    }
+
+    /// Number of whole flags that fill `_time` (in units of 10 ms), rounded up.
+    const fn flags_for(_time: u8) -> usize {
+        let _bits = _time as u32 * KISS_TIME_UNIT_MS * AFSK1200_BAUD / 1000;
+        _bits.div_ceil(FLAG_BITS) as usize
+    }
+
+    pub const fn preamble_flags(&self) -> usize {
+        Self::flags_for(self.tx_delay)
+    }
+
+    pub const fn tail_flags(&self) -> usize {
+        Self::flags_for(self.tx_tail)
+    }
+}
+
+/// Samples of one AX.25 frame, made by [modulate_ax25].
+pub type AfskModulator<'a> = FskModulator<EncodedBits<HdlcEncoder<'a>>>;
+
+/// Modulates an AX.25 frame (addresses through information field, without FCS) as AFSK1200:
+/// HDLC framing with the FCS appended, NRZI, then phase-continuous Bell 202 tones.
+pub fn modulate_ax25<'a>(_config: &AfskConfig, _frame: &'a [u8]) -> AfskModulator<'a> {
+    let _bits = HdlcEncoder::new(_frame, _config.preamble_flags(), _config.tail_flags());
+    FskModulator::new(_config.fsk_config(), LineCode::Nrzi.encode_bits(_bits))
+}
+
+/// One tone's cosine and sine over one bit, to correlate the last bit of samples against.
+#[derive(Debug, Copy, Clone)]
+struct Correlator {
+    cos: [f32; MAX_SAMPLES_PER_BIT],
+    sin: [f32; MAX_SAMPLES_PER_BIT],
+}
+
+impl Correlator {
+    fn new(_config: &FskConfig, _frequency: u32, _len: usize) -> Self {
+        let _step = _config.phase_increment(_frequency);
+        let _shift = u32::BITS - SINE_TABLE_SIZE.trailing_zeros();
+        let mut _correlator = Self {
+            cos: [0.0; MAX_SAMPLES_PER_BIT],
+            sin: [0.0; MAX_SAMPLES_PER_BIT],
+        };
+        let mut _phase: u32 = 0;
+        for i in 0.._len {
+            let _index = (_phase >> _shift) as usize;
+            _correlator.sin[i] = SINE_TABLE[_index] as f32 / i16::MAX as f32;
+            _correlator.cos[i] = SINE_TABLE[(_index + SINE_TABLE_SIZE / 4) % SINE_TABLE_SIZE]
+                as f32
+                / i16::MAX as f32;
+            _phase = _phase.wrapping_add(_step);
+        }
+        _correlator
+    }
+
+    /// Power of the tone in `_history`, a ring buffer whose oldest sample is at `_oldest`.
+    fn power(&self, _history: &[f32], _oldest: usize) -> f32 {
+        let (mut _re, mut _im) = (0.0f32, 0.0f32);
+        let _samples = _history[_oldest..].iter().chain(&_history[.._oldest]);
+        for ((_sample, _cos), _sin) in _samples.zip(&self.cos).zip(&self.sin) {
+            _re += _sample * _cos;
+            _im += _sample * _sin;
+        }
+        _re * _re + _im * _im
+    }
+}
+
+/// AFSK1200 receiver, the other end of [modulate_ax25]: audio samples in, AX.25 frames out.
+///
+/// Each tone is correlated against the last bit's worth of samples, and the stronger one is the
+/// line level. A digital PLL keeps the bit clock: it wraps once a bit, when the level is sampled,
+/// and every transition scales its phase by [PLL_INERTIA], pulling it towards being halfway
+/// between transitions. The levels are NRZI decoded and passed to a [HdlcDecoder], so only frames
+/// with a good FCS come out. Every frame heard is returned, digipeated copies included.
+#[derive(Clone, Copy)]
+pub struct AfskDemodulator {
+    config: AfskConfig,
+    mark: Correlator,
+    space: Correlator,
+    history: [f32; MAX_SAMPLES_PER_BIT],
+    window: usize,
+    oldest: usize,
+    /// Bit clock phase, wrapping from positive to negative once a bit.
+    pll: i32,
+    pll_step: i32,
+    level: bool,
+    nrzi: LineDecoder,
+    hdlc: HdlcDecoder,
+}
+
+impl AfskDemodulator {
+    /// `None` unless the sample rate is at least [AFSK_MIN_SAMPLE_RATE] and at most
+    /// [AFSK_MAX_SAMPLE_RATE].
+    pub fn new(_config: AfskConfig) -> Option<Self> {
+        let _fsk = _config.fsk_config();
+        if !(AFSK_MIN_SAMPLE_RATE..=AFSK_MAX_SAMPLE_RATE).contains(&_fsk.sample_rate) {
+            return None;
+        }
+        let _window = ((_fsk.sample_rate + _fsk.baud / 2) / _fsk.baud) as usize;
+        Some(Self {
+            config: _config,
+            mark: Correlator::new(&_fsk, _fsk.mark_hz, _window),
+            space: Correlator::new(&_fsk, _fsk.space_hz, _window),
+            history: [0.0; MAX_SAMPLES_PER_BIT],
+            window: _window,
+            oldest: 0,
+            pll: 0,
+            pll_step: _fsk.phase_increment(_fsk.baud) as i32,
+            level: false,
+            nrzi: LineDecoder::new(LineCode::Nrzi),
+            hdlc: HdlcDecoder::new(),
+        })
+    }
+
+    pub const fn config(&self) -> &AfskConfig {
+        &self.config
+    }
+
+    /// Frames heard but dropped for a bad FCS.
+    pub const fn fcs_errors(&self) -> u32 {
+        self.hdlc.fcs_errors()
+    }
+
+    /// Takes one sample, e.g. from a sound card. Returns a frame when one ends with a good FCS.
+    pub fn push_real(&mut self, _sample: f32) -> Option<Ax25Frame> {
+        self.history[self.oldest] = _sample;
+        self.oldest = (self.oldest + 1) % self.window;
+
+        let _history = &self.history[..self.window];
+        let _level =
+            self.mark.power(_history, self.oldest) > self.space.power(_history, self.oldest);
+        if _level != self.level {
+            self.level = _level;
+            self.pll = (self.pll as f32 * PLL_INERTIA) as i32;
+        }
+
+        let (_pll, _wrapped) = self.pll.overflowing_add(self.pll_step);
+        self.pll = _pll;
+        if !_wrapped {
+            return None;
+        }
+        let _bit = self.nrzi.push_symbol(self.level)?;
+        self.hdlc.push_bit(_bit)
+    }
+
+    /// Demodulates 16-bit samples, calling `_on_frame` with each good frame. Returns how many
+    /// there were.
+    pub fn demodulate(&mut self, _samples: &[i16], mut _on_frame: impl FnMut(&Ax25Frame)) -> usize {
+        let mut _frames: usize = 0;
+        for _sample in _samples {
+            if let Some(_frame) = self.push_real(*_sample as f32 / i16::MAX as f32) {
+                _on_frame(&_frame);
+                _frames += 1;
+            }
+        }
+        _frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdlc::{Ax25Address, AX25_ADDRESS_LEN};
+
+    #[test]
+    fn test_afsk_timing() {
+        let mut _config = AfskConfig::DEFAULT;
+        // 300 ms at 1200 baud is 360 bits
+        assert_eq!(_config.preamble_flags(), 45);
+        assert_eq!(_config.tail_flags(), 15);
+
+        assert!(_config.apply(&Message::SetTXDelay(1)));
+        assert!(_config.apply(&Message::SetTXTail(0)));
+        assert!(!_config.apply(&Message::SetP(63)));
+        assert_eq!(_config.preamble_flags(), 2);
+        // one flag is always sent, to close the frame
+        assert_eq!(_config.tail_flags(), 0);
+
+        let _frame = [0u8; 10];
+        // two flags, 80 frame bits and 16 FCS bits (none needing stuffing), and the closing flag
+        let _bits = HdlcEncoder::new(&_frame, 2, 0).count();
+        assert_eq!(_bits, 16 + 96 + 8);
+        assert_eq!(modulate_ax25(&_config, &_frame).count(), _bits * 40);
+    }
+
+    #[test]
+    fn test_afsk_tones() {
+        let mut _config = AfskConfig::new(12_000);
+        _config.tx_delay = 0;
+        _config.tx_tail = 0;
+
+        // the opening flag starts with a 0 (a transition from space to mark) and six 1s (no
+        // transition): 7 bits of 1200 Hz, 10 samples per bit, so 7 whole cycles
+        let mut _buffer = [0i16; 70];
+        let _len = modulate_ax25(&_config, &[0x00]).modulate_into(&mut _buffer);
+        assert_eq!(_len, _buffer.len());
+        let _crossings = _buffer
+            .windows(2)
+            .filter(|_pair| (_pair[0] < 0) != (_pair[1] < 0))
+            .count();
+        assert_eq!(_crossings, 13);
+    }
+
+    /// A UI frame from `KD9TFA-11` to `APZNEX`, via `WIDE1-1` once `_repeated`.
+    fn ui_frame(_repeated: bool) -> [u8; 3 * AX25_ADDRESS_LEN + 2 + 12] {
+        let _addresses = [
+            Ax25Address {
+                callsign: *b"APZNEX",
+                ssid: 0,
+                repeated: false,
+            },
+            Ax25Address {
+                callsign: *b"KD9TFA",
+                ssid: 11,
+                repeated: false,
+            },
+            Ax25Address {
+                callsign: *b"WIDE1 ",
+                ssid: 1,
+                repeated: _repeated,
+            },
+        ];
+        let mut _frame = [0u8; 3 * AX25_ADDRESS_LEN + 2 + 12];
+        for (_index, _address) in _addresses.iter().enumerate() {
+            let _start = _index * AX25_ADDRESS_LEN;
+            _frame[_start.._start + AX25_ADDRESS_LEN]
+                .copy_from_slice(&_address.to_bytes(_index == _addresses.len() - 1));
+        }
+        let _start = _addresses.len() * AX25_ADDRESS_LEN;
+        _frame[_start.._start + 2].copy_from_slice(&[0x03, 0xF0]);
+        _frame[_start + 2..].copy_from_slice(b"!4123.45N/0\xC0");
+        _frame
+    }
+
+    #[test]
+    fn test_afsk_roundtrip() {
+        let _config = AfskConfig::DEFAULT;
+        let _frame = ui_frame(false);
+        let mut _demodulator = AfskDemodulator::new(_config).unwrap();
+        assert!(AfskDemodulator::new(AfskConfig::new(AFSK_MIN_SAMPLE_RATE - 1)).is_none());
+        assert!(AfskDemodulator::new(AfskConfig::new(AFSK_MAX_SAMPLE_RATE + 1)).is_none());
+
+        let mut _received: Option<Ax25Frame> = None;
+        for _sample in modulate_ax25(&_config, &_frame) {
+            if let Some(_decoded) = _demodulator.push_real(_sample as f32 / i16::MAX as f32) {
+                assert!(_received.is_none());
+                _received = Some(_decoded);
+            }
+        }
+        let _received = _received.unwrap();
+        assert_eq!(_received.as_bytes(), _frame);
+        assert_eq!(_demodulator.fcs_errors(), 0);
+
+        // straight back out to the host, with the FEND in the information field escaped
+        let mut _kiss = [0u8; 128];
+        let mut _len: usize = 0;
+        for _byte in _received.kiss_bytes(0) {
+            _kiss[_len] = _byte;
+            _len += 1;
+        }
+        let _kiss = &_kiss[.._len];
+        assert_eq!(_kiss[..3], [0xC0, 0x00, _frame[0]]);
+        assert_eq!(_kiss[_kiss.len() - 3..], [0xDB, 0xDC, 0xC0]);
+        assert_eq!(_kiss.len(), _frame.len() + 4);
+    }
+
+    #[test]
+    fn test_afsk_impaired() {
+        // a sound card at 44.1 kHz hearing a transmitter whose clock is 0.2% slow, at a low
+        // level in noise, first direct and then through a digipeater
+        let mut _transmitter = AfskConfig::new(44_100 + 88);
+        _transmitter.tx_delay = 10;
+        _transmitter.tx_tail = 2;
+        let _direct = ui_frame(false);
+        let _digipeated = ui_frame(true);
+
+        let mut _demodulator = AfskDemodulator::new(AfskConfig::new(44_100)).unwrap();
+        let mut _received = [[0u8; 3 * AX25_ADDRESS_LEN + 2 + 12]; 2];
+        let mut _count: usize = 0;
+        let mut _seed: u32 = 7;
+
+        let _silence = core::iter::repeat_n(0i16, 5000);
+        let _samples = _silence
+            .clone()
+            .chain(modulate_ax25(&_transmitter, &_direct))
+            .chain(_silence.clone())
+            .chain(modulate_ax25(&_transmitter, &_digipeated))
+            .chain(_silence);
+        for _sample in _samples {
+            // cheap uniform noise
+            _seed = _seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let _noise = ((_seed >> 8) as f32 / (1 << 24) as f32 - 0.5) * 0.3;
+
+            let _input = _sample as f32 / i16::MAX as f32 * 0.2 + _noise;
+            if let Some(_frame) = _demodulator.push_real(_input) {
+                _received[_count].copy_from_slice(_frame.as_bytes());
+                _count += 1;
+            }
+        }
+        assert_eq!(_count, 2);
+        assert_eq!(_received, [_direct, _digipeated]);
+
+        let _frame = Ax25Frame::from_bytes(&_received[1]).unwrap();
+        assert!(_frame.digipeaters().all(|_address| _address.repeated));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::fec::{Fec, DEFAULT_FEC};
+use crate::parameters::*;
+use crate::telemetry::{recover_bare_message, CorrectionReport, DecodeError};
+
+/// Pre-shared key, known to the payload and the ground station.
+pub type AuthKey = [u8; 16];
+
+pub const AUTH_COUNTER_SIZE: usize = core::mem::size_of::<u32>();
+/// Bytes of SipHash output kept. 32 bits makes a blind forgery a one in four billion guess, which
+/// is plenty at our packet rate.
+pub const AUTH_TAG_SIZE: usize = 4;
+pub const AUTH_TRAILER_SIZE: usize = AUTH_COUNTER_SIZE + AUTH_TAG_SIZE;
+
+/// The counter, then the truncated tag, both big-endian.
+pub type AuthTrailer = [u8; AUTH_TRAILER_SIZE];
+
+pub const AUTHENTICATED_MESSAGE_LENGTH_BYTES: usize =
+    BARE_MESSAGE_LENGTH_BYTES + AUTH_TRAILER_SIZE + FEC_EXTRA_BYTES;
+pub type AuthenticatedMessage = [u8; AUTHENTICATED_MESSAGE_LENGTH_BYTES];
+
+/// SipHash-2-4, as specified by Aumasson and Bernstein. Only needs 64-bit adds, rotates and XORs,
+/// so it stays small on AVR, unlike HMAC-SHA256.
+#[derive(Debug, Copy, Clone)]
+struct SipHasher {
+    v: [u64; 4],
+    tail: u64,
+    tail_len: usize,
+    total_len: usize,
+}
+
+impl SipHasher {
+    const fn new(_key: &AuthKey) -> Self {
+        let mut _k0 = [0u8; 8];
+        let mut _k1 = [0u8; 8];
+        let mut i: usize = 0;
+        while i < 8 {
+            _k0[i] = _key[i];
+            _k1[i] = _key[i + 8];
+            i += 1;
+        }
+        let _k0 = u64::from_le_bytes(_k0);
+        let _k1 = u64::from_le_bytes(_k1);
+        Self {
+            v: [
+                _k0 ^ 0x736f6d6570736575,
+                _k1 ^ 0x646f72616e646f6d,
+                _k0 ^ 0x6c7967656e657261,
+                _k1 ^ 0x7465646279746573,
+            ],
+            tail: 0,
+            tail_len: 0,
+            total_len: 0,
+        }
+    }
+
+    const fn round(&mut self) {
+        let v = &mut self.v;
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+
+    const fn compress(&mut self, _word: u64) {
+        self.v[3] ^= _word;
+        self.round();
+        self.round();
+        self.v[0] ^= _word;
+    }
+
+    const fn write(&mut self, _data: &[u8]) {
+        let mut i: usize = 0;
+        while i < _data.len() {
+            self.tail |= (_data[i] as u64) << (8 * self.tail_len);
+            self.tail_len += 1;
+            if self.tail_len == 8 {
+                self.compress(self.tail);
+                self.tail = 0;
+                self.tail_len = 0;
+            }
+            i += 1;
+        }
+        self.total_len += _data.len();
+    }
+
+    const fn finish(mut self) -> u64 {
+        self.compress(self.tail | ((self.total_len as u64 & 0xFF) << 56));
+        self.v[2] ^= 0xFF;
+        self.round();
+        self.round();
+        self.round();
+        self.round();
+        self.v[0] ^ self.v[1] ^ self.v[2] ^ self.v[3]
+    }
+}
+
+/// SipHash-2-4 of `_data` under `_key`.
+pub const fn siphash24(_key: &AuthKey, _data: &[u8]) -> u64 {
+    let mut _hasher = SipHasher::new(_key);
+    _hasher.write(_data);
+    _hasher.finish()
+}
+
+/// The truncated tag for `_data` sent with `_counter`. The counter is hashed first, so that the
+/// same data sent twice gets two different tags.
+pub const fn auth_tag(_key: &AuthKey, _counter: u32, _data: &[u8]) -> [u8; AUTH_TAG_SIZE] {
+    let mut _hasher = SipHasher::new(_key);
+    _hasher.write(&_counter.to_be_bytes());
+    _hasher.write(_data);
+    let _hash = _hasher.finish().to_be_bytes();
+    [_hash[0], _hash[1], _hash[2], _hash[3]]
+}
+
+/// Flight-side signer. Every trailer uses the next value of a counter that must never go
+/// backwards; save [Authenticator::counter] somewhere that survives a reset (e.g. EEPROM), and
+/// pass it back to [Authenticator::new] on boot.
+#[derive(Debug, Copy, Clone)]
+pub struct Authenticator {
+    key: AuthKey,
+    counter: u32,
+}
+
+impl Authenticator {
+    pub const fn new(_key: AuthKey, _counter: u32) -> Self {
+        Self {
+            key: _key,
+            counter: _counter,
+        }
+    }
+
+    /// The counter the next trailer will carry.
+    pub const fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    /// Makes the trailer for any payload, e.g. a command frame, and advances the counter.
+    pub const fn trailer(&mut self, _data: &[u8]) -> AuthTrailer {
+        let _counter = self.counter.to_be_bytes();
+        let _tag = auth_tag(&self.key, self.counter, _data);
+        self.counter = self.counter.wrapping_add(1);
+        [
+            _counter[0],
+            _counter[1],
+            _counter[2],
+            _counter[3],
+            _tag[0],
+            _tag[1],
+            _tag[2],
+            _tag[3],
+        ]
+    }
+
+    /// Appends a trailer to `_bare_packet`, then encodes both with [DEFAULT_FEC]. The packet is
+    /// sent with the [PacketLayout::AuthenticatedTelemetry] ID, but the trailer covers
+    /// `_bare_packet` as given.
+    pub fn sign_packet(&mut self, _bare_packet: &BareMessage) -> AuthenticatedMessage {
+        let mut _signed = [0u8; BARE_MESSAGE_LENGTH_BYTES + AUTH_TRAILER_SIZE];
+        _signed[..BARE_MESSAGE_LENGTH_BYTES].copy_from_slice(_bare_packet);
+        _signed[START_HEADER_DATA.len()] = PacketLayout::AuthenticatedTelemetry.id();
+        _signed[BARE_MESSAGE_LENGTH_BYTES..].copy_from_slice(&self.trailer(_bare_packet));
+
+        let mut output: AuthenticatedMessage = [0u8; AUTHENTICATED_MESSAGE_LENGTH_BYTES];
+        DEFAULT_FEC.encode(&_signed[..], &mut output);
+        output
+    }
+}
+
+/// Whether a received trailer checks out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AuthStatus {
+    /// The tag is correct, and the counter is newer than any accepted so far.
+    Authentic(u32),
+    /// The tag is correct, but the counter has already been accepted: a replayed packet.
+    Replayed(u32),
+    /// The tag does not match; the packet was not signed with our key, or was altered.
+    Forged,
+}
+
+impl AuthStatus {
+    pub const fn is_authentic(&self) -> bool {
+        matches!(self, Self::Authentic(_))
+    }
+}
+
+/// Ground-side verifier. Remembers the highest counter accepted, and flags anything at or below it
+/// as a replay.
+#[derive(Debug, Copy, Clone)]
+pub struct AuthVerifier {
+    key: AuthKey,
+    last_counter: Option<u32>,
+}
+
+impl AuthVerifier {
+    pub const fn new(_key: AuthKey) -> Self {
+        Self {
+            key: _key,
+            last_counter: None,
+        }
+    }
+
+    /// Carries on from a counter accepted in an earlier session.
+    pub const fn with_last_counter(_key: AuthKey, _last_counter: u32) -> Self {
+        Self {
+            key: _key,
+            last_counter: Some(_last_counter),
+        }
+    }
+
+    pub const fn last_counter(&self) -> Option<u32> {
+        self.last_counter
+    }
+
+    /// Checks `_trailer` against any payload. Only an [AuthStatus::Authentic] trailer moves the
+    /// accepted counter forwards.
+    pub fn check(&mut self, _data: &[u8], _trailer: &AuthTrailer) -> AuthStatus {
+        let _counter = u32::from_be_bytes(_trailer[..AUTH_COUNTER_SIZE].try_into().unwrap());
+        if auth_tag(&self.key, _counter, _data) != _trailer[AUTH_COUNTER_SIZE..] {
+            return AuthStatus::Forged;
+        }
+
+        match self.last_counter {
+            Some(_last) if _counter <= _last => AuthStatus::Replayed(_counter),
+            _ => {
+                self.last_counter = Some(_counter);
+                AuthStatus::Authentic(_counter)
+            }
+        }
+    }
+
+    /// Corrects a packet made by [Authenticator::sign_packet] and checks its trailer.
+    ///
+    /// Packets that fail the check are still returned, flagged by their [AuthStatus], so the ground
+    /// station can decide what to do with them.
+    pub fn verify_packet(
+        &mut self,
+        _packet: &[u8],
+    ) -> Result<(BareMessage, AuthStatus, CorrectionReport), DecodeError> {
+        let (_recovered_packet, _trailer, _report) = decode_signed_packet(_packet)?;
+        let _status = self.check(&_recovered_packet, &_trailer);
+        Ok((_recovered_packet, _status, _report))
+    }
+}
+
+/// Corrects a packet made by [Authenticator::sign_packet] (restoring its static bytes, as in
+/// [crate::telemetry::decode_packet]) without checking its trailer, for receivers that do not hold
+/// the key. [AuthVerifier::check] can check the trailer later.
+pub fn decode_signed_packet(
+    _packet: &[u8],
+) -> Result<(BareMessage, AuthTrailer, CorrectionReport), DecodeError> {
+    if _packet.len() != AUTHENTICATED_MESSAGE_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut _trailer: AuthTrailer = [0u8; AUTH_TRAILER_SIZE];
+    let (_recovered_packet, _report) = recover_bare_message(
+        _packet,
+        &DEFAULT_FEC,
+        PacketLayout::AuthenticatedTelemetry,
+        None,
+        &[],
+        &mut _trailer,
+    )?;
+    Ok((_recovered_packet, _trailer, _report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::make_packet_skeleton_nofec;
+
+    const KEY: AuthKey = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    #[test]
+    fn test_siphash24_reference() {
+        // the test vector from the SipHash paper, appendix A
+        let _message: [u8; 15] = core::array::from_fn(|i| i as u8);
+        assert_eq!(siphash24(&KEY, &_message), 0xa129ca6149be45e5);
+        assert_eq!(siphash24(&KEY, &[]), 0x726fdb47dd0e0e31);
+    }
+
+    #[test]
+    fn test_authenticated_packet() {
+        let _packet = make_packet_skeleton_nofec(true);
+        let mut _authenticator = Authenticator::new(KEY, 41);
+        let _first = _authenticator.sign_packet(&_packet);
+        let _second = _authenticator.sign_packet(&_packet);
+        assert_eq!(_authenticator.counter(), 43);
+        assert_ne!(_first, _second);
+
+        let mut _verifier = AuthVerifier::new(KEY);
+        let mut _damaged = _first;
+        _damaged[BARE_MESSAGE_LENGTH_BYTES + 1] ^= 0xFF;
+        _damaged[20] ^= 0xFF;
+        let (_decoded, _status, _) = _verifier.verify_packet(&_damaged).unwrap();
+        assert_eq!(_decoded, _packet);
+        assert_eq!(_status, AuthStatus::Authentic(41));
+
+        assert_eq!(
+            _verifier.verify_packet(&_first).unwrap().1,
+            AuthStatus::Replayed(41)
+        );
+        assert_eq!(
+            _verifier.verify_packet(&_second).unwrap().1,
+            AuthStatus::Authentic(42)
+        );
+
+        let mut _wrong_key = KEY;
+        _wrong_key[0] ^= 1;
+        let _forged = Authenticator::new(_wrong_key, 100).sign_packet(&_packet);
+        assert_eq!(
+            _verifier.verify_packet(&_forged).unwrap().1,
+            AuthStatus::Forged
+        );
+        assert_eq!(_verifier.last_counter(), Some(42));
+    }
+}
@@ -0,0 +1,676 @@
+//! `apollo-ground decode`: finds packets in a recording, a bitstream, a byte stream or hex dumps,
+//! corrects them, and prints what they carry, one packet per line.
+
+use crate::{hex, open_input, parse_number, parse_whitening};
+use apollo::auth::AUTH_COUNTER_SIZE;
+use apollo::delta::DeltaDecoder;
+use apollo::demod::{hard_bit, FskDemodulator};
+use apollo::fsk::FskConfig;
+use apollo::layout::{decode_any_packet, decode_bare_packet, DecodedPacket};
+use apollo::parameters::*;
+use apollo::qpacket::{qpacket_callsign, qpacket_gps_time, qpacket_sequence, qpacket_values};
+use apollo::sequence::{Callsign, SequenceEvent, SequenceTracker};
+use apollo::sync::FrameSynchronizer;
+use apollo::telemetry::{typed_values_from_packet, BlockValue, CorrectionReport, DecodeError};
+use apollo::track::{json_number, json_string, number_text, TrackFormat, TrackPoint};
+use apollo::wav::WavReader;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const USAGE: &str = "\
+usage: apollo-ground decode [INPUT] [OPTIONS] [FILE]
+
+Decodes packets from FILE, or from stdin if FILE is missing or `-`.
+
+input:
+  --bytes            raw bytes, as from a serial port (default)
+  --bits             ASCII 0s and 1s, one per bit; anything else is ignored
+  --hex              one packet per line, in hex; spaces and colons are ignored. Packets
+                     sent without FEC (encode --no-fec) are read too; the other inputs
+                     only find packets with FEC
+  --wav              a 2-FSK recording, demodulated with the tones and baud below
+
+options:
+  --json             print JSON Lines instead of human-readable lines
+  --follow           wait for more input at the end of FILE, like `tail -f`
+  --track PATH       also write the flight track to PATH, as .csv, .geojson, .kml or .gpx;
+                     points are stamped with each packet's GPS time (or, when following,
+                     the time it arrived if the GPS has no time yet), and the file is
+                     rewritten after each position when following
+  --mark HZ          mark tone of a --wav recording
+  --space HZ         space tone of a --wav recording
+  --baud BAUD        symbol rate of a --wav recording
+  --sync-errors N    sync word bit errors tolerated (default 2)
+  --whitening NAME   packets were whitened with none (default), g3ruh or pn9
+";
+
+/// Callsigns whose sequence numbers are followed.
+const TRACKED_CALLSIGNS: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum InputFormat {
+    Bytes,
+    Bits,
+    Hex,
+    Wav,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    format: InputFormat,
+    /// `None` for stdin.
+    path: Option<String>,
+    json: bool,
+    follow: bool,
+    fsk: FskConfig,
+    sync_errors: u8,
+    /// Where to write the flight track, and in what format.
+    track: Option<(String, TrackFormat)>,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut _args: I) -> Result<Options, String> {
+    let mut _options = Options {
+        format: InputFormat::Bytes,
+        path: None,
+        json: false,
+        follow: false,
+        fsk: FskConfig::DEFAULT,
+        sync_errors: 2,
+        track: None,
+    };
+    while let Some(_arg) = _args.next() {
+        match _arg.as_str() {
+            "--bytes" => _options.format = InputFormat::Bytes,
+            "--bits" => _options.format = InputFormat::Bits,
+            "--hex" => _options.format = InputFormat::Hex,
+            "--wav" => _options.format = InputFormat::Wav,
+            "--json" => _options.json = true,
+            "--follow" | "-f" => _options.follow = true,
+            "--mark" => _options.fsk.mark_hz = parse_number(&_arg, _args.next())?,
+            "--space" => _options.fsk.space_hz = parse_number(&_arg, _args.next())?,
+            "--baud" => _options.fsk.baud = parse_number(&_arg, _args.next())?,
+            "--sync-errors" => _options.sync_errors = parse_number(&_arg, _args.next())?,
+            "--whitening" => _options.fsk.whitening = parse_whitening(&_arg, _args.next())?,
+            "--track" => {
+                let _path = _args.next().ok_or("--track needs a PATH")?;
+                let _format = TrackFormat::from_path(&_path).ok_or_else(|| {
+                    format!(
+                        "--track needs a .csv, .geojson, .kml or .gpx PATH: {}",
+                        _path
+                    )
+                })?;
+                _options.track = Some((_path, _format));
+            }
+            "-h" | "--help" => return Err(String::new()),
+            "-" => _options.path = None,
+            _ if _arg.starts_with('-') => return Err(format!("unknown option {}", _arg)),
+            _ if _options.path.is_some() => return Err(format!("more than one FILE: {}", _arg)),
+            _ => _options.path = Some(_arg),
+        }
+    }
+    if _options.fsk.baud == 0 {
+        return Err("--baud must not be 0".into());
+    }
+    Ok(_options)
+}
+
+/// Everything printed about one packet.
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    kind: &'static str,
+    callsign: Option<Callsign>,
+    sequence: Option<u16>,
+    sequence_event: Option<SequenceEvent>,
+    /// Seconds since the GPS epoch, kept exact rather than as an `f32` field.
+    gps_time: Option<u32>,
+    report: Option<CorrectionReport>,
+    /// Name, physical value and unit.
+    fields: Vec<(String, f64, &'static str)>,
+}
+
+impl Record {
+    fn new(_kind: &'static str) -> Self {
+        Self {
+            kind: _kind,
+            callsign: None,
+            sequence: None,
+            sequence_event: None,
+            gps_time: None,
+            report: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Fills in the callsign, sequence and fields of a telemetry packet.
+    fn with_telemetry(mut self, _packet: &BareMessage) -> Self {
+        let mut _callsign: Callsign = [0u8; CALLSIGN.len()];
+        _callsign.copy_from_slice(
+            &_packet[START_HEADER_DATA.len() - CALLSIGN.len()..START_HEADER_DATA.len()],
+        );
+        self.callsign = Some(_callsign);
+        for (_block, _block_type) in typed_values_from_packet(_packet)
+            .iter()
+            .zip(BLOCK_TYPE_STACK)
+        {
+            match (_block_type, _block.value) {
+                (_, BlockValue::None) => (),
+                (BlockType::SEQUENCE, BlockValue::U16(_sequence)) => {
+                    self.sequence = Some(_sequence)
+                }
+                (BlockType::GPSTIME, BlockValue::U32(_gps_time)) => self.gps_time = Some(_gps_time),
+                _ => self
+                    .fields
+                    .push((_block.name.into(), _block.physical(), _block.unit())),
+            }
+        }
+        self
+    }
+}
+
+/// The flight track collected from telemetry and delta packets.
+struct Track {
+    path: String,
+    format: TrackFormat,
+    /// Rewrite the file after each point, and stamp points without a GPS time with the time they
+    /// arrived.
+    live: bool,
+    points: Vec<TrackPoint>,
+}
+
+impl Track {
+    /// Writes the whole track to a temporary file beside [Track::path], then renames it into
+    /// place, so that whatever is watching the file never reads it half written.
+    fn write(&self) -> io::Result<()> {
+        let _temporary = format!("{}.tmp", self.path);
+        self.format.write(
+            BufWriter::new(File::create(&_temporary)?),
+            CALLSIGN_TEXT.trim_end(),
+            &self.points,
+        )?;
+        std::fs::rename(&_temporary, &self.path)
+    }
+}
+
+/// The flight is named after the balloon.
+const CALLSIGN_TEXT: &str = match std::str::from_utf8(CALLSIGN) {
+    Ok(_callsign) => _callsign,
+    Err(_) => "apollo",
+};
+
+/// Decodes each packet and prints it, keeping track of sequence numbers and delta keyframes.
+struct Decoder<W: Write> {
+    output: W,
+    json: bool,
+    packets: usize,
+    /// Telemetry (with its deltas) and QPackets are counted separately.
+    telemetry_sequences: SequenceTracker<TRACKED_CALLSIGNS>,
+    qpacket_sequences: SequenceTracker<TRACKED_CALLSIGNS>,
+    deltas: DeltaDecoder,
+    track: Option<Track>,
+}
+
+impl<W: Write> Decoder<W> {
+    fn new(_output: W, _json: bool) -> Self {
+        Self {
+            output: _output,
+            json: _json,
+            packets: 0,
+            telemetry_sequences: SequenceTracker::new(),
+            qpacket_sequences: SequenceTracker::new(),
+            deltas: DeltaDecoder::new(),
+            track: None,
+        }
+    }
+
+    fn push_track_point(&mut self, _packet: &BareMessage) -> io::Result<()> {
+        let Some(_track) = &mut self.track else {
+            return Ok(());
+        };
+        let mut _point = TrackPoint::from_packet(_packet);
+        if _point.time.is_none() && _track.live {
+            _point.time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|_elapsed| _elapsed.as_secs() as i64);
+        }
+        _track.points.push(_point);
+        if _track.live {
+            _track.write()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the track, if one is wanted.
+    fn finish(&self) -> io::Result<()> {
+        match &self.track {
+            Some(_track) => _track.write(),
+            None => Ok(()),
+        }
+    }
+
+    /// Also returns the telemetry the packet carried, if any, for the track.
+    fn record(
+        &mut self,
+        _packet: DecodedPacket,
+    ) -> Result<(Record, Option<BareMessage>), DecodeError> {
+        let mut _telemetry = None;
+        let mut _record = match _packet {
+            DecodedPacket::Telemetry(_bare, _report) => {
+                self.deltas.push_keyframe(&_bare);
+                _telemetry = Some(_bare);
+                let mut _record = Record::new("telemetry").with_telemetry(&_bare);
+                _record.report = Some(_report);
+                _record
+            }
+            DecodedPacket::ProfiledTelemetry(_bare, _, _report) => {
+                self.deltas.push_keyframe(&_bare);
+                _telemetry = Some(_bare);
+                let mut _record = Record::new("telemetry").with_telemetry(&_bare);
+                _record.report = Some(_report);
+                _record
+            }
+            // there is no key here to check the trailer with
+            DecodedPacket::AuthenticatedTelemetry(_bare, _trailer, _report) => {
+                self.deltas.push_keyframe(&_bare);
+                _telemetry = Some(_bare);
+                let mut _record = Record::new("signed telemetry").with_telemetry(&_bare);
+                _record.report = Some(_report);
+                _record.fields.push((
+                    "auth_counter".into(),
+                    u32::from_be_bytes(_trailer[..AUTH_COUNTER_SIZE].try_into().unwrap()) as f64,
+                    "",
+                ));
+                _record
+            }
+            DecodedPacket::CheckedTelemetry(_bare, _integrity, _report) => {
+                // a miscorrected packet is shown, but kept out of the track and delta keyframes
+                let _kind = if _integrity.is_verified() {
+                    self.deltas.push_keyframe(&_bare);
+                    _telemetry = Some(_bare);
+                    "telemetry"
+                } else {
+                    "telemetry (crc mismatch)"
+                };
+                let mut _record = Record::new(_kind).with_telemetry(&_bare);
+                _record.report = Some(_report);
+                _record
+            }
+            DecodedPacket::Delta(_delta) => {
+                let _bare = self.deltas.apply(&_delta)?;
+                _telemetry = Some(_bare);
+                Record::new("delta").with_telemetry(&_bare)
+            }
+            DecodedPacket::QPacket(_bare) => {
+                let mut _record = Record::new("qpacket");
+                _record.callsign = Some(qpacket_callsign(&_bare));
+                _record.sequence = qpacket_sequence(&_bare);
+                _record.gps_time = qpacket_gps_time(&_bare);
+                for (i, _value) in qpacket_values(&_bare).iter().enumerate() {
+                    _record.fields.push((format!("value{}", i), *_value, ""));
+                }
+                _record
+            }
+            DecodedPacket::Fragment(_fragment) => {
+                let mut _record = Record::new("fragment");
+                _record.fields = vec![
+                    ("transfer_id".into(), _fragment.transfer_id as f64, ""),
+                    ("index".into(), _fragment.index as f64, ""),
+                    ("count".into(), _fragment.count as f64, ""),
+                    ("payload_len".into(), _fragment.payload().len() as f64, "B"),
+                ];
+                _record
+            }
+        };
+        if let (Some(_callsign), Some(_sequence)) = (_record.callsign, _record.sequence) {
+            let _sequences = match _record.kind {
+                "qpacket" => &mut self.qpacket_sequences,
+                _ => &mut self.telemetry_sequences,
+            };
+            _record.sequence_event = Some(_sequences.track(&_callsign, _sequence));
+        }
+        Ok((_record, _telemetry))
+    }
+
+    fn push_packet(&mut self, _raw: &[u8]) -> io::Result<()> {
+        self.packets += 1;
+        let _record = decode_any_packet(_raw)
+            .or_else(|_error| match _error {
+                // a packet without FEC, as only hex input can carry
+                DecodeError::BadLength => decode_bare_packet(_raw).map_err(|_| _error),
+                _ => Err(_error),
+            })
+            .and_then(|_packet| self.record(_packet));
+        if let Ok((_, Some(_telemetry))) = &_record {
+            self.push_track_point(_telemetry)?;
+        }
+        let _record = _record.map(|(_record, _)| _record);
+        let _line = match (&_record, self.json) {
+            (Ok(_record), false) => format_human(self.packets, _record),
+            (Ok(_record), true) => format_json(self.packets, _record),
+            (Err(_error), false) => format!("#{} error: {} [{}]", self.packets, _error, hex(_raw)),
+            (Err(_error), true) => format!(
+                "{{\"packet\":{},\"error\":{},\"raw\":\"{}\"}}",
+                self.packets,
+                json_string(&_error.to_string()),
+                hex(_raw)
+            ),
+        };
+        writeln!(self.output, "{}", _line)?;
+        // someone may be watching live
+        self.output.flush()
+    }
+}
+
+/// Parses one line of a hex dump. Spaces, colons and a leading `0x` on each byte are ignored.
+fn parse_hex(_line: &str) -> Option<Vec<u8>> {
+    let _digits: Vec<u8> = _line
+        .split(|_char: char| _char.is_whitespace() || _char == ':' || _char == ',')
+        .map(|_word| _word.trim_start_matches("0x").trim_start_matches("0X"))
+        .flat_map(|_word| _word.bytes())
+        .collect();
+    if _digits.len() % 2 != 0 {
+        return None;
+    }
+    _digits
+        .chunks_exact(2)
+        .map(|_pair| u8::from_str_radix(std::str::from_utf8(_pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn callsign_text(_callsign: &Option<Callsign>) -> String {
+    match _callsign {
+        Some(_callsign) => String::from_utf8_lossy(_callsign).trim_end().to_string(),
+        None => "-".into(),
+    }
+}
+
+fn sequence_event_text(_event: SequenceEvent) -> String {
+    match _event {
+        SequenceEvent::First => "first".into(),
+        SequenceEvent::InOrder => "in order".into(),
+        SequenceEvent::Gap(_missed) => format!("{} missed", _missed),
+        SequenceEvent::Wrapped(_missed) => format!("wrapped, {} missed", _missed),
+        SequenceEvent::Duplicate => "duplicate".into(),
+        SequenceEvent::Reordered => "late".into(),
+        SequenceEvent::Untracked => "untracked".into(),
+    }
+}
+
+fn format_human(_index: usize, _record: &Record) -> String {
+    let mut _line = format!(
+        "#{} {} {}",
+        _index,
+        callsign_text(&_record.callsign),
+        _record.kind
+    );
+    if let Some(_sequence) = _record.sequence {
+        let _ = write!(_line, " seq={}", _sequence);
+        if let Some(_event) = _record.sequence_event {
+            let _ = write!(_line, " ({})", sequence_event_text(_event));
+        }
+    }
+    if let Some(_gps_time) = _record.gps_time {
+        let _ = write!(_line, " gps_time={}", _gps_time);
+    }
+    if let Some(_report) = &_record.report {
+        let _ = write!(_line, " corrected={}", _report.corrected);
+        if !_report.positions().is_empty() {
+            let _ = write!(_line, " at {:?}", _report.positions());
+        }
+    }
+    for (_name, _value, _unit) in &_record.fields {
+        let _ = write!(_line, " {}={}", _name, number_text(*_value));
+        if !_unit.is_empty() {
+            let _ = write!(_line, " {}", _unit);
+        }
+    }
+    _line
+}
+
+fn format_json(_index: usize, _record: &Record) -> String {
+    let mut _line = format!("{{\"packet\":{},\"kind\":\"{}\"", _index, _record.kind);
+    match &_record.callsign {
+        Some(_) => {
+            let _ = write!(
+                _line,
+                ",\"callsign\":{}",
+                json_string(&callsign_text(&_record.callsign))
+            );
+        }
+        None => _line.push_str(",\"callsign\":null"),
+    }
+    match _record.sequence {
+        Some(_sequence) => {
+            let _ = write!(_line, ",\"sequence\":{}", _sequence);
+        }
+        None => _line.push_str(",\"sequence\":null"),
+    }
+    if let Some(_event) = _record.sequence_event {
+        let _ = write!(
+            _line,
+            ",\"sequence_event\":{}",
+            json_string(&sequence_event_text(_event))
+        );
+    }
+    match _record.gps_time {
+        Some(_gps_time) => {
+            let _ = write!(_line, ",\"gps_time\":{}", _gps_time);
+        }
+        None => _line.push_str(",\"gps_time\":null"),
+    }
+    if let Some(_report) = &_record.report {
+        let _ = write!(
+            _line,
+            ",\"corrected\":{},\"corrected_positions\":{:?},\"damaged_static\":{},\"inferred_erasures\":{}",
+            _report.corrected,
+            _report.positions(),
+            _report.damaged_static,
+            _report.inferred_erasures
+        );
+    }
+    _line.push_str(",\"fields\":{");
+    for (i, (_name, _value, _unit)) in _record.fields.iter().enumerate() {
+        if i > 0 {
+            _line.push(',');
+        }
+        let _ = write!(
+            _line,
+            "{}:{{\"value\":{},\"unit\":{}}}",
+            json_string(_name),
+            json_number(*_value),
+            json_string(_unit)
+        );
+    }
+    _line.push_str("}}");
+    _line
+}
+
+pub fn run(_options: &Options) -> io::Result<()> {
+    let mut _input = open_input(&_options.path, _options.follow)?;
+    let mut _decoder = Decoder::new(io::stdout().lock(), _options.json);
+    _decoder.track = _options.track.clone().map(|(_path, _format)| Track {
+        path: _path,
+        format: _format,
+        live: _options.follow,
+        points: Vec::new(),
+    });
+    let mut _sync = FrameSynchronizer::with_whitening(_options.sync_errors, _options.fsk.whitening);
+
+    match _options.format {
+        InputFormat::Bytes => {
+            for _byte in _input.bytes() {
+                if let Some(_packet) = _sync.push_byte(_byte?) {
+                    _decoder.push_packet(_packet.as_bytes())?;
+                }
+            }
+        }
+        InputFormat::Bits => {
+            for _byte in _input.bytes() {
+                let _bit = match _byte? {
+                    b'0' => false,
+                    b'1' => true,
+                    _ => continue,
+                };
+                if let Some(_packet) = _sync.push_bit(_bit) {
+                    _decoder.push_packet(_packet.as_bytes())?;
+                }
+            }
+        }
+        InputFormat::Hex => {
+            for _line in _input.lines() {
+                let _line = _line?;
+                if _line.trim().is_empty() || _line.starts_with('#') {
+                    continue;
+                }
+                match parse_hex(&_line) {
+                    Some(mut _packet) => {
+                        _options.fsk.whitening.dewhiten(&mut _packet);
+                        _decoder.push_packet(&_packet)?
+                    }
+                    None => eprintln!("skipping a line that is not hex: {}", _line),
+                }
+            }
+        }
+        InputFormat::Wav => {
+            let mut _wav = WavReader::new(_input)
+                .map_err(|_error| io::Error::new(io::ErrorKind::InvalidData, _error))?;
+            let _config = FskConfig {
+                sample_rate: _wav.spec().sample_rate,
+                .._options.fsk
+            };
+            let mut _demodulator = FskDemodulator::new(_config);
+            while let Some(_sample) = _wav
+                .read_frame()
+                .map_err(|_error| io::Error::new(io::ErrorKind::InvalidData, _error))?
+            {
+                if let Some(_packet) = _demodulator
+                    .push_real(_sample)
+                    .and_then(|_bit| _sync.push_bit(hard_bit(_bit)))
+                {
+                    _decoder.push_packet(_packet.as_bytes())?;
+                }
+            }
+        }
+    }
+    _decoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apollo::qpacket::{QPacketEncoder, USER_DATA_BLOCKS};
+    use apollo::telemetry::TelemetryEncoder;
+    use apollo::track::gps_to_unix;
+    use apollo::whitening::Whitening;
+
+    fn args(_args: &[&str]) -> Result<Options, String> {
+        parse_args(_args.iter().map(|_arg| _arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let _options = args(&["--wav", "--json", "--baud", "300", "flight.wav"]).unwrap();
+        assert_eq!(_options.format, InputFormat::Wav);
+        assert_eq!(_options.path.as_deref(), Some("flight.wav"));
+        assert!(_options.json && !_options.follow);
+        assert_eq!(_options.fsk.baud, 300);
+
+        assert_eq!(args(&["-"]).unwrap().path, None);
+        assert!(args(&["--baud"]).is_err());
+        assert_eq!(
+            args(&["--whitening", "G3RUH"]).unwrap().fsk.whitening,
+            Whitening::G3ruh
+        );
+        assert!(args(&["--whitening"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+        assert!(args(&["a", "b"]).is_err());
+
+        let _options = args(&["--track", "flight.kml", "flight.bin"]).unwrap();
+        assert_eq!(
+            _options.track,
+            Some(("flight.kml".to_string(), TrackFormat::Kml))
+        );
+        assert!(args(&["--track", "flight.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("E4 1b:4B"), Some(vec![0xE4, 0x1B, 0x4B]));
+        assert_eq!(parse_hex("0xE4, 0x1B"), Some(vec![0xE4, 0x1B]));
+        assert_eq!(parse_hex("E41"), None);
+        assert_eq!(parse_hex("ZZ"), None);
+    }
+
+    #[test]
+    fn test_decoder_output() {
+        let mut _encoder = TelemetryEncoder::with_sequence(5);
+        let _data = [[0u8; 4]; BLOCK_STACK_DATA_COUNT];
+        let mut _telemetry = _encoder.generate_packet(1_400_000_000, _data);
+        _telemetry[20] ^= 0xFF;
+        _encoder.generate_packet(1_400_000_010, _data);
+        let _after_gap = _encoder.generate_packet(1_400_000_020, _data);
+        let _qpacket = QPacketEncoder::with_sequence(41)
+            .encode_values(1_400_000_030, &[0.0; USER_DATA_BLOCKS]);
+
+        let mut _decoder = Decoder::new(Vec::new(), false);
+        _decoder.push_packet(&_telemetry).unwrap();
+        _decoder.push_packet(&_after_gap).unwrap();
+        _decoder.push_packet(&_qpacket).unwrap();
+        _decoder.push_packet(&[0u8; 3]).unwrap();
+        let _text = String::from_utf8(_decoder.output).unwrap();
+        let _lines: Vec<&str> = _text.lines().collect();
+        assert!(_lines[0].starts_with(
+            "#1 KD9TFA telemetry seq=5 (first) gps_time=1400000000 corrected=1 at [20] status="
+        ));
+        assert!(_lines[1].starts_with("#2 KD9TFA telemetry seq=7 (1 missed) gps_time=1400000020"));
+        assert!(_lines[2].starts_with("#3 KD9TFA qpacket seq=41 (first) gps_time=1400000030"));
+        assert!(_lines[3].starts_with("#4 error: "));
+
+        let mut _decoder = Decoder::new(Vec::new(), true);
+        _decoder.push_packet(&_telemetry).unwrap();
+        let _text = String::from_utf8(_decoder.output).unwrap();
+        assert!(_text.starts_with(
+            "{\"packet\":1,\"kind\":\"telemetry\",\"callsign\":\"KD9TFA\",\"sequence\":5,\"sequence_event\":\"first\",\"gps_time\":1400000000,\"corrected\":1,\"corrected_positions\":[20],"
+        ));
+        assert!(_text.contains("\"altitude\":{\"value\":"));
+        assert!(_text.ends_with("}}\n"));
+
+        // only telemetry goes on the track
+        let mut _decoder = Decoder::new(Vec::new(), false);
+        _decoder.track = Some(Track {
+            path: String::new(),
+            format: TrackFormat::Csv,
+            live: false,
+            points: Vec::new(),
+        });
+        _decoder.push_packet(&_telemetry).unwrap();
+        _decoder.push_packet(&_qpacket).unwrap();
+        let _points = &_decoder.track.unwrap().points;
+        assert_eq!(_points.len(), 1);
+        assert_eq!(_points[0].time, Some(gps_to_unix(1_400_000_000)));
+    }
+
+    #[test]
+    fn test_decode_no_fec() {
+        let mut _encoder = TelemetryEncoder::with_sequence(5);
+        let _bare =
+            _encoder.generate_packet_no_fec(1_400_000_000, [[0u8; 4]; BLOCK_STACK_DATA_COUNT]);
+        let mut _truncated = _bare.to_vec();
+        _truncated.pop();
+
+        let mut _decoder = Decoder::new(Vec::new(), false);
+        _decoder
+            .push_packet(&parse_hex(&hex(&_bare)).unwrap())
+            .unwrap();
+        _decoder.push_packet(&_truncated).unwrap();
+        let _text = String::from_utf8(_decoder.output).unwrap();
+        let _lines: Vec<&str> = _text.lines().collect();
+        assert!(_lines[0].starts_with(
+            "#1 KD9TFA telemetry seq=5 (first) gps_time=1400000000 corrected=0 status="
+        ));
+        assert!(_lines[1].starts_with("#2 error: "));
+        assert!(_lines[1].contains(&DecodeError::BadLength.to_string()));
+    }
+}
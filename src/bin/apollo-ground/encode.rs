@@ -0,0 +1,552 @@
+//! `apollo-ground encode`: builds packets from field values given as arguments or in a CSV, and
+//! writes them as hex, binary, KISS frames or 2-FSK audio, to exercise the receive chain.
+
+use crate::{hex, open_input, parse_number, parse_whitening};
+use apollo::fsk::FskConfig;
+use apollo::parameters::*;
+use apollo::qpacket::{encode_qpacket, QPacketEncoder, USER_DATA_BLOCKS};
+use apollo::telemetry::{BlockStackData, BlockValue, TelemetryEncoder};
+use apollo::tnc::KissBytes;
+use apollo::wav::{write_packets_wav, SampleFormat};
+use std::fs::File;
+use std::io::{self, BufRead, Cursor, Write};
+
+pub const USAGE: &str = "\
+usage: apollo-ground encode [OPTIONS] [FIELD=VALUE...]
+
+Builds packets from field values. Fields not given are 0. A field name may be shortened as long
+as it is unambiguous (lat, lon). Integers may be given in hex (0x...).
+
+input:
+  FIELD=VALUE        a field value for every packet
+  --csv FILE         one packet per row, fields named in the header row; `-` for stdin
+  --count N          packets to send for each set of values (default 1)
+
+packet:
+  --layout NAME      telemetry (default) or qpacket
+  --no-fec           leave out the FEC; decode reads such packets only as --hex
+  --sequence N       first sequence number (default 0)
+  --whitening NAME   whiten each packet with none (default), g3ruh or pn9; decode needs
+                     the same --whitening
+
+output:
+  --hex              one packet per line, in hex (default)
+  --binary           the packets back to back
+  --kiss             KISS data frames, for a TNC
+  --port N           KISS port (default 0)
+  --wav              a 2-FSK recording, with the tones and rates below
+  --float            32-bit float samples instead of 16-bit
+  --mark HZ          mark tone
+  --space HZ         space tone
+  --baud BAUD        symbol rate
+  --sample-rate HZ   sample rate
+  -o FILE            write to FILE instead of stdout
+";
+
+const QPACKET_GPS_TIME_FIELD: &str = "gps_time";
+
+/// Telemetry blocks given as fields, leaving out the sequence number, which the encoder counts.
+fn telemetry_blocks() -> impl Iterator<Item = usize> {
+    (0..BLOCK_STACK_DATA_COUNT).filter(|i| BLOCK_TYPE_STACK[*i] != BlockType::SEQUENCE)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Layout {
+    Telemetry,
+    QPacket,
+}
+
+impl Layout {
+    fn field_names(&self) -> Vec<String> {
+        match self {
+            Self::Telemetry => telemetry_blocks()
+                .map(|i| BLOCK_NAME_STACK[i].to_string())
+                .collect(),
+            Self::QPacket => core::iter::once(QPACKET_GPS_TIME_FIELD.to_string())
+                .chain((0..USER_DATA_BLOCKS).map(|i| format!("value{}", i)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum OutputFormat {
+    Hex,
+    Binary,
+    Kiss,
+    Wav,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    layout: Layout,
+    fec: bool,
+    sequence: u16,
+    count: usize,
+    /// `FIELD=VALUE` arguments, in the order given.
+    fields: Vec<(String, String)>,
+    /// `Some(None)` for a CSV on stdin.
+    csv: Option<Option<String>>,
+    format: OutputFormat,
+    port: u8,
+    sample_format: SampleFormat,
+    fsk: FskConfig,
+    output: Option<String>,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut _args: I) -> Result<Options, String> {
+    let mut _options = Options {
+        layout: Layout::Telemetry,
+        fec: true,
+        sequence: 0,
+        count: 1,
+        fields: Vec::new(),
+        csv: None,
+        format: OutputFormat::Hex,
+        port: 0,
+        sample_format: SampleFormat::Pcm16,
+        fsk: FskConfig::DEFAULT,
+        output: None,
+    };
+    while let Some(_arg) = _args.next() {
+        match _arg.as_str() {
+            "--csv" => {
+                let _path = _args.next().ok_or("--csv needs a file")?;
+                _options.csv = Some(Some(_path).filter(|_path| _path != "-"));
+            }
+            "--count" => _options.count = parse_number(&_arg, _args.next())?,
+            "--layout" => {
+                _options.layout = match _args.next().as_deref() {
+                    Some("telemetry") => Layout::Telemetry,
+                    Some("qpacket") => Layout::QPacket,
+                    _ => return Err("--layout needs telemetry or qpacket".into()),
+                }
+            }
+            "--no-fec" => _options.fec = false,
+            "--sequence" => _options.sequence = parse_number(&_arg, _args.next())?,
+            "--whitening" => _options.fsk.whitening = parse_whitening(&_arg, _args.next())?,
+            "--hex" => _options.format = OutputFormat::Hex,
+            "--binary" => _options.format = OutputFormat::Binary,
+            "--kiss" => _options.format = OutputFormat::Kiss,
+            "--port" => _options.port = parse_number(&_arg, _args.next())?,
+            "--wav" => _options.format = OutputFormat::Wav,
+            "--float" => _options.sample_format = SampleFormat::Float32,
+            "--mark" => _options.fsk.mark_hz = parse_number(&_arg, _args.next())?,
+            "--space" => _options.fsk.space_hz = parse_number(&_arg, _args.next())?,
+            "--baud" => _options.fsk.baud = parse_number(&_arg, _args.next())?,
+            "--sample-rate" => _options.fsk.sample_rate = parse_number(&_arg, _args.next())?,
+            "-o" | "--output" => _options.output = Some(_args.next().ok_or("-o needs a file")?),
+            "-h" | "--help" => return Err(String::new()),
+            _ => match _arg.split_once('=') {
+                Some((_name, _value)) if !_arg.starts_with('-') => {
+                    _options.fields.push((_name.into(), _value.into()))
+                }
+                _ => return Err(format!("unknown argument {}", _arg)),
+            },
+        }
+    }
+    if _options.port > 15 {
+        return Err("--port must be between 0 and 15".into());
+    }
+    if _options.fsk.baud == 0 || _options.fsk.sample_rate == 0 {
+        return Err("--baud and --sample-rate must not be 0".into());
+    }
+    Ok(_options)
+}
+
+/// Index of the field `_name` stands for: an exact match, or else the only field it begins.
+fn resolve_field(_names: &[String], _name: &str) -> Result<usize, String> {
+    if let Some(_index) = _names.iter().position(|_field| _field == _name) {
+        return Ok(_index);
+    }
+    let mut _matches = _names
+        .iter()
+        .enumerate()
+        .filter(|(_, _field)| _field.starts_with(_name));
+    match (_matches.next(), _matches.next()) {
+        (Some((_index, _)), None) if !_name.is_empty() => Ok(_index),
+        (Some(_), Some(_)) => Err(format!("{} could be more than one field", _name)),
+        _ => Err(format!(
+            "no field called {}; fields are {}",
+            _name,
+            _names.join(", ")
+        )),
+    }
+}
+
+fn parse_integer(_text: &str) -> Option<i64> {
+    let (_negative, _digits) = match _text.strip_prefix('-') {
+        Some(_digits) => (true, _digits),
+        None => (false, _text),
+    };
+    let _magnitude = match _digits.strip_prefix("0x").or(_digits.strip_prefix("0X")) {
+        Some(_hex) => i64::from_str_radix(_hex, 16).ok()?,
+        None => _digits.parse().ok()?,
+    };
+    Some(if _negative { -_magnitude } else { _magnitude })
+}
+
+/// Converts `_text` to the raw value of a block. Integers for unscaled integer blocks (such as
+/// status flags) are taken exactly, rather than through a float; signed blocks also take
+/// unsigned values of the same width, as bit patterns.
+fn field_value(
+    _block_type: BlockType,
+    _scaling: &BlockScaling,
+    _text: &str,
+) -> Result<BlockValue, String> {
+    let _out_of_range = || format!("{} is out of range", _text);
+    if _scaling.scale == 1.0 && _scaling.offset == 0.0 {
+        if let Some(_integer) = parse_integer(_text) {
+            match _block_type {
+                BlockType::I32 => {
+                    return i32::try_from(_integer)
+                        .or_else(|_| u32::try_from(_integer).map(|_bits| _bits as i32))
+                        .map(BlockValue::I32)
+                        .map_err(|_| _out_of_range())
+                }
+                BlockType::U32 | BlockType::GPSTIME => {
+                    return u32::try_from(_integer)
+                        .map(BlockValue::U32)
+                        .map_err(|_| _out_of_range())
+                }
+                BlockType::I16 => {
+                    return i16::try_from(_integer)
+                        .or_else(|_| u16::try_from(_integer).map(|_bits| _bits as i16))
+                        .map(BlockValue::I16)
+                        .map_err(|_| _out_of_range())
+                }
+                BlockType::U16 | BlockType::SEQUENCE => {
+                    return u16::try_from(_integer)
+                        .map(BlockValue::U16)
+                        .map_err(|_| _out_of_range())
+                }
+                _ => (),
+            }
+        }
+    }
+    let _value: f64 = _text
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} is not a number", _text))?;
+    Ok(BlockValue::from_physical(_block_type, _scaling, _value))
+}
+
+/// Builds one packet from the text of each field, in [Layout::field_names] order.
+fn build_packet(
+    _options: &Options,
+    _telemetry: &mut TelemetryEncoder,
+    _qpackets: &mut QPacketEncoder,
+    _values: &[String],
+) -> Result<Vec<u8>, String> {
+    match _options.layout {
+        Layout::Telemetry => {
+            let mut _data: BlockStackData = [[0u8; 4]; BLOCK_STACK_DATA_COUNT];
+            let mut _gps_time: u32 = 0;
+            for (i, _text) in telemetry_blocks().zip(_values) {
+                let _value = field_value(BLOCK_TYPE_STACK[i], &BLOCK_SCALING_STACK[i], _text)
+                    .map_err(|_error| format!("{}: {}", BLOCK_NAME_STACK[i], _error))?;
+                if let (BlockType::GPSTIME, BlockValue::U32(_time)) = (BLOCK_TYPE_STACK[i], _value)
+                {
+                    _gps_time = _time;
+                }
+                _data[i] = _value.to_be_bytes();
+            }
+            Ok(if _options.fec {
+                _telemetry.generate_packet(_gps_time, _data).to_vec()
+            } else {
+                _telemetry.generate_packet_no_fec(_gps_time, _data).to_vec()
+            })
+        }
+        Layout::QPacket => {
+            let _gps_time = parse_integer(&_values[0])
+                .and_then(|_gps_time| u32::try_from(_gps_time).ok())
+                .ok_or_else(|| {
+                    format!("{}: {} is not a time", QPACKET_GPS_TIME_FIELD, _values[0])
+                })?;
+            let mut _physical = [0.0f64; USER_DATA_BLOCKS];
+            for i in 0..USER_DATA_BLOCKS {
+                _physical[i] = _values[i + 1]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("value{}: {} is not a number", i, _values[i + 1]))?;
+            }
+            let _bare = _qpackets.construct_packet_from_values(_gps_time, &_physical);
+            Ok(if _options.fec {
+                encode_qpacket(_bare).to_vec()
+            } else {
+                _bare.to_vec()
+            })
+        }
+    }
+}
+
+/// The text of every field for each packet: the `FIELD=VALUE` arguments, overridden by each row
+/// of the CSV if there is one.
+fn field_rows(
+    _options: &Options,
+    _csv: Option<Box<dyn BufRead>>,
+) -> Result<Vec<Vec<String>>, String> {
+    let _names = _options.layout.field_names();
+    let mut _defaults = vec!["0".to_string(); _names.len()];
+    for (_name, _value) in &_options.fields {
+        _defaults[resolve_field(&_names, _name)?] = _value.clone();
+    }
+    let _csv = match _csv {
+        Some(_csv) => _csv,
+        None => return Ok(vec![_defaults]),
+    };
+
+    let mut _rows = Vec::new();
+    let mut _columns: Option<Vec<usize>> = None;
+    for _line in _csv.lines() {
+        let _line = _line.map_err(|_error| _error.to_string())?;
+        if _line.trim().is_empty() || _line.starts_with('#') {
+            continue;
+        }
+        let _cells = _line.split(',').map(str::trim);
+        match &_columns {
+            None => {
+                _columns = Some(
+                    _cells
+                        .map(|_name| resolve_field(&_names, _name))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Some(_columns) => {
+                let mut _row = _defaults.clone();
+                for (_column, _cell) in _columns.iter().zip(_cells) {
+                    if !_cell.is_empty() {
+                        _row[*_column] = _cell.to_string();
+                    }
+                }
+                _rows.push(_row);
+            }
+        }
+    }
+    Ok(_rows)
+}
+
+/// Every packet to send, in order.
+fn build_packets(
+    _options: &Options,
+    _csv: Option<Box<dyn BufRead>>,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut _telemetry = TelemetryEncoder::with_sequence(_options.sequence);
+    let mut _qpackets = QPacketEncoder::with_sequence(_options.sequence);
+    let mut _packets = Vec::new();
+    for (_row_number, _row) in field_rows(_options, _csv)?.iter().enumerate() {
+        for _ in 0.._options.count {
+            _packets.push(
+                build_packet(_options, &mut _telemetry, &mut _qpackets, _row)
+                    .map_err(|_error| format!("packet {}: {}", _row_number + 1, _error))?,
+            );
+        }
+    }
+    Ok(_packets)
+}
+
+fn write_packets(
+    _options: &Options,
+    _packets: &[Vec<u8>],
+    _output: &mut dyn Write,
+) -> io::Result<()> {
+    // the modulator whitens the packets of a recording itself
+    let _whitened: Vec<Vec<u8>> = _packets
+        .iter()
+        .map(|_packet| {
+            let mut _packet = _packet.clone();
+            _options.fsk.whitening.whiten(&mut _packet);
+            _packet
+        })
+        .collect();
+    match _options.format {
+        OutputFormat::Hex => {
+            for _packet in &_whitened {
+                writeln!(_output, "{}", hex(_packet))?;
+            }
+        }
+        OutputFormat::Binary => {
+            for _packet in &_whitened {
+                _output.write_all(_packet)?;
+            }
+        }
+        OutputFormat::Kiss => {
+            for _packet in &_whitened {
+                let _frame: Vec<u8> = KissBytes::data_frame(_options.port, _packet).collect();
+                _output.write_all(&_frame)?;
+            }
+        }
+        OutputFormat::Wav => {
+            let _wav = write_packets_wav(
+                Cursor::new(Vec::new()),
+                _options.fsk,
+                _packets,
+                _options.sample_format,
+            )
+            .map_err(io::Error::other)?;
+            _output.write_all(_wav.get_ref())?;
+        }
+    }
+    _output.flush()
+}
+
+pub fn run(_options: &Options) -> io::Result<()> {
+    let _csv = match &_options.csv {
+        Some(_path) => Some(open_input(_path, false)?),
+        None => None,
+    };
+    let _packets = build_packets(_options, _csv)
+        .map_err(|_error| io::Error::new(io::ErrorKind::InvalidInput, _error))?;
+
+    match &_options.output {
+        Some(_path) => write_packets(_options, &_packets, &mut File::create(_path)?),
+        None => write_packets(_options, &_packets, &mut io::stdout().lock()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apollo::layout::{decode_any_packet, DecodedPacket};
+    use apollo::qpacket::{qpacket_sequence, qpacket_values};
+    use apollo::sync::FrameSynchronizer;
+    use apollo::telemetry::{telemetry_gps_time, telemetry_sequence, typed_values_from_packet};
+    use apollo::whitening::Whitening;
+
+    fn options(_args: &[&str]) -> Options {
+        parse_args(_args.iter().map(|_arg| _arg.to_string())).unwrap()
+    }
+
+    fn csv(_text: &'static str) -> Option<Box<dyn BufRead>> {
+        Some(Box::new(_text.as_bytes()))
+    }
+
+    #[test]
+    fn test_resolve_field() {
+        let _names = Layout::Telemetry.field_names();
+        assert_eq!(resolve_field(&_names, "altitude"), Ok(1));
+        assert_eq!(resolve_field(&_names, "lat"), Ok(4));
+        assert_eq!(resolve_field(&_names, "lon"), Ok(5));
+        assert!(resolve_field(&_names, "l").is_err());
+        assert!(resolve_field(&_names, "pressure").is_err());
+    }
+
+    #[test]
+    fn test_field_value() {
+        let _status = field_value(BlockType::I32, &BlockScaling::IDENTITY, "0xFFFF0001").unwrap();
+        assert_eq!(_status, BlockValue::I32(0xFFFF0001u32 as i32));
+        let _latitude = field_value(BlockType::I32, &BLOCK_SCALING_STACK[4], "41.5").unwrap();
+        assert_eq!(_latitude, BlockValue::I32(415_000_000));
+        assert!(field_value(BlockType::U16, &BlockScaling::IDENTITY, "70000").is_err());
+        assert!(field_value(BlockType::F32, &BlockScaling::IDENTITY, "high").is_err());
+    }
+
+    #[test]
+    fn test_encode_telemetry() {
+        let _options = options(&[
+            "--sequence",
+            "3",
+            "--count",
+            "2",
+            "status=0x10",
+            "alt=1337",
+            "lat=41.5",
+            "lon=-87.25",
+            "gps=1400000000",
+        ]);
+        let _packets = build_packets(&_options, None).unwrap();
+        assert_eq!(_packets.len(), 2);
+
+        let _bare = match decode_any_packet(&_packets[1]) {
+            Ok(DecodedPacket::Telemetry(_bare, _report)) => {
+                assert_eq!(_report.corrected, 0);
+                _bare
+            }
+            _other => panic!("{:?}", _other),
+        };
+        assert_eq!(telemetry_sequence(&_bare), Some(4));
+        assert_eq!(telemetry_gps_time(&_bare), Some(1_400_000_000));
+        let _blocks = typed_values_from_packet(&_bare);
+        assert_eq!(_blocks[0].value, BlockValue::I32(0x10));
+        assert_eq!(_blocks[1].physical(), 1337.0);
+        assert_eq!(_blocks[2].physical(), 0.0);
+        assert_eq!(_blocks[4].value, BlockValue::I32(415_000_000));
+        assert_eq!(_blocks[5].value, BlockValue::I32(-872_500_000));
+
+        let _bare = build_packets(&options(&["--no-fec"]), None).unwrap();
+        assert_eq!(_bare[0].len(), BARE_MESSAGE_LENGTH_BYTES);
+    }
+
+    #[test]
+    fn test_encode_csv() {
+        let _options = options(&[
+            "--layout",
+            "qpacket",
+            "--sequence",
+            "7",
+            "--count",
+            "2",
+            "value1=-87.2345678",
+        ]);
+        let _packets = build_packets(
+            &_options,
+            csv("gps_time, value0\n# a comment\n1400000000, 1\n\n1400000010,\n"),
+        )
+        .unwrap();
+        assert_eq!(_packets.len(), 4);
+
+        let _decoded: Vec<_> = _packets
+            .iter()
+            .map(|_packet| match decode_any_packet(_packet) {
+                Ok(DecodedPacket::QPacket(_bare)) => _bare,
+                _other => panic!("{:?}", _other),
+            })
+            .collect();
+        assert_eq!(qpacket_sequence(&_decoded[0]), Some(7));
+        assert_eq!(qpacket_sequence(&_decoded[3]), Some(10));
+        assert_eq!(qpacket_values(&_decoded[1])[..2], [1.0, -87.2345678]);
+        // an empty cell keeps the value from the arguments
+        assert_eq!(qpacket_values(&_decoded[2])[..2], [0.0, -87.2345678]);
+
+        assert!(build_packets(&_options, csv("pressure\n1\n")).is_err());
+        assert!(build_packets(&_options, csv("value0\nlots\n")).is_err());
+    }
+
+    #[test]
+    fn test_encode_outputs() {
+        let _packets = build_packets(&options(&[]), None).unwrap();
+
+        let mut _kiss = Vec::new();
+        write_packets(&options(&["--kiss", "--port", "1"]), &_packets, &mut _kiss).unwrap();
+        assert_eq!(_kiss[..2], [0xC0, 0x10]);
+        assert_eq!(_kiss.last(), Some(&0xC0));
+
+        let mut _hex = Vec::new();
+        write_packets(&options(&[]), &_packets, &mut _hex).unwrap();
+        assert_eq!(String::from_utf8(_hex).unwrap(), hex(&_packets[0]) + "\n");
+
+        let mut _wav = Vec::new();
+        write_packets(
+            &options(&["--wav", "--baud", "300", "--sample-rate", "9600"]),
+            &_packets,
+            &mut _wav,
+        )
+        .unwrap();
+        assert_eq!(&_wav[..4], b"RIFF");
+
+        // a whitened byte stream, as decode --whitening pn9 reads it
+        let _options = options(&["--binary", "--whitening", "pn9"]);
+        assert_eq!(_options.fsk.whitening, Whitening::Pn9);
+        let mut _binary = Vec::new();
+        write_packets(&_options, &_packets, &mut _binary).unwrap();
+        assert_ne!(_binary, _packets[0]);
+        let mut _sync = FrameSynchronizer::with_whitening(0, Whitening::Pn9);
+        let _frames: Vec<_> = _binary
+            .iter()
+            .filter_map(|_byte| _sync.push_byte(*_byte))
+            .collect();
+        assert_eq!(_frames.len(), 1);
+        assert_eq!(_frames[0].as_bytes(), &_packets[0][..]);
+        assert!(parse_args(["--whitening", "rot13"].map(String::from).into_iter()).is_err());
+    }
+}
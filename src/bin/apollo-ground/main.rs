@@ -0,0 +1,115 @@
+//! Ground station tools. `decode` prints the packets found in received data; `encode` builds
+//! packets from field values, for bench tests and test fixtures.
+
+mod decode;
+mod encode;
+
+use apollo::whitening::Whitening;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::time::Duration;
+use std::{env, process, thread};
+
+const USAGE: &str = "\
+usage: apollo-ground [decode] [OPTIONS] [FILE]
+       apollo-ground encode [OPTIONS] [FIELD=VALUE...]
+
+Decoding is the default. Give a subcommand --help for its options.
+";
+
+/// How often to look for more input in follow mode.
+const FOLLOW_POLL: Duration = Duration::from_millis(200);
+
+pub fn parse_number<T: std::str::FromStr>(
+    _flag: &str,
+    _value: Option<String>,
+) -> Result<T, String> {
+    _value
+        .as_deref()
+        .and_then(|_value| _value.parse().ok())
+        .ok_or_else(|| format!("{} needs a number", _flag))
+}
+
+pub fn parse_whitening(_flag: &str, _value: Option<String>) -> Result<Whitening, String> {
+    _value
+        .as_deref()
+        .and_then(Whitening::from_name)
+        .ok_or_else(|| format!("{} needs none, g3ruh or pn9", _flag))
+}
+
+pub fn hex(_bytes: &[u8]) -> String {
+    _bytes.iter().fold(String::new(), |mut _hex, _byte| {
+        let _ = write!(_hex, "{:02X}", _byte);
+        _hex
+    })
+}
+
+/// Waits for more data at the end of the input instead of stopping, like `tail -f`.
+struct Follow<R: Read>(R);
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let _read = self.0.read(_buffer)?;
+            if _read > 0 || _buffer.is_empty() {
+                return Ok(_read);
+            }
+            thread::sleep(FOLLOW_POLL);
+        }
+    }
+}
+
+/// Opens `_path`, or stdin if it is `None`.
+pub fn open_input(_path: &Option<String>, _follow: bool) -> io::Result<Box<dyn BufRead>> {
+    let _input: Box<dyn Read> = match _path {
+        Some(_path) => Box::new(File::open(_path)?),
+        None => Box::new(io::stdin()),
+    };
+    Ok(if _follow {
+        Box::new(BufReader::new(Follow(_input)))
+    } else {
+        Box::new(BufReader::new(_input))
+    })
+}
+
+/// Parses a subcommand's arguments and runs it, exiting with 2 on bad arguments and 1 on errors.
+fn run_subcommand<O>(
+    _usage: &str,
+    _options: Result<O, String>,
+    _run: impl FnOnce(&O) -> io::Result<()>,
+) {
+    let _options = match _options {
+        Ok(_options) => _options,
+        Err(_message) => {
+            if !_message.is_empty() {
+                eprintln!("apollo-ground: {}", _message);
+            }
+            eprint!("{}", _usage);
+            process::exit(2);
+        }
+    };
+    if let Err(_error) = _run(&_options) {
+        // a closed pipe, e.g. into `head`, is not worth complaining about
+        if _error.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("apollo-ground: {}", _error);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let mut _args = env::args().skip(1).peekable();
+    match _args.peek().map(String::as_str) {
+        Some("encode") => {
+            _args.next();
+            run_subcommand(encode::USAGE, encode::parse_args(_args), encode::run)
+        }
+        Some("decode") => {
+            _args.next();
+            run_subcommand(decode::USAGE, decode::parse_args(_args), decode::run)
+        }
+        Some("help") => eprint!("{}", USAGE),
+        _ => run_subcommand(decode::USAGE, decode::parse_args(_args), decode::run),
+    }
+}
@@ -0,0 +1,340 @@
+use crate::parameters::*;
+use crate::telemetry::DecodeError;
+
+/// Constraint length of the code: each output depends on the current and 6 previous input bits.
+pub const CONSTRAINT_LENGTH: usize = 7;
+/// Zero bits appended after the data so that the encoder ends in state 0, which lets the decoder
+/// trace back from a known state.
+pub const TAIL_BITS: usize = CONSTRAINT_LENGTH - 1;
+/// Coded bits sent per data bit.
+pub const CODE_RATE_INVERSE: usize = 2;
+
+const STATE_COUNT: usize = 1 << TAIL_BITS;
+const STATE_MASK: u8 = (STATE_COUNT - 1) as u8;
+
+/// The usual (171, 133) octal generator polynomials, as used by CCSDS and most amateur and HAB
+/// links, bit-reversed because our shift register keeps the newest bit in the LSB.
+const POLYNOMIALS: [u8; CODE_RATE_INVERSE] = [0x4F, 0x6D];
+
+/// The two coded bits for each 7-bit shift register value, first coded bit in bit 1.
+const BRANCH_OUTPUTS: [u8; 1 << CONSTRAINT_LENGTH] = {
+    let mut output = [0u8; 1 << CONSTRAINT_LENGTH];
+    let mut i: usize = 0;
+    while i < output.len() {
+        let _a = (i as u8 & POLYNOMIALS[0]).count_ones() as u8 & 1;
+        let _b = (i as u8 & POLYNOMIALS[1]).count_ones() as u8 & 1;
+        output[i] = (_a << 1) | _b;
+        i += 1;
+    }
+    output
+};
+
+/// A received bit with its confidence: 0 is a certain 0, 255 a certain 1, and [SOFT_ERASURE] no
+/// information at all. A hard-decision demodulator gives only [SOFT_ZERO] and [SOFT_ONE].
+pub type SoftBit = u8;
+
+pub const SOFT_ZERO: SoftBit = 0;
+pub const SOFT_ONE: SoftBit = 255;
+pub const SOFT_ERASURE: SoftBit = 128;
+
+/// Number of coded bits for `_data_len` bytes of data, including the tail.
+pub const fn encoded_len_bits(_data_len: usize) -> usize {
+    (_data_len * 8 + TAIL_BITS) * CODE_RATE_INVERSE
+}
+
+/// Number of bytes [convolutional_encode] writes for `_data_len` bytes of data.
+pub const fn encoded_len_bytes(_data_len: usize) -> usize {
+    encoded_len_bits(_data_len).div_ceil(8)
+}
+
+/// Flight-side convolutional encoder, usable one bit or byte at a time from a transmit loop.
+#[derive(Debug, Copy, Clone)]
+pub struct ConvolutionalEncoder {
+    state: u8,
+}
+
+impl ConvolutionalEncoder {
+    pub const fn new() -> Self {
+        Self { state: 0 }
+    }
+
+    /// Encodes one data bit into two coded bits, sent in order.
+    pub const fn encode_bit(&mut self, _bit: bool) -> [bool; CODE_RATE_INVERSE] {
+        let _register = (self.state << 1) | _bit as u8;
+        self.state = _register & STATE_MASK;
+        let _output = BRANCH_OUTPUTS[_register as usize];
+        [_output & 0b10 != 0, _output & 0b01 != 0]
+    }
+
+    /// Encodes one data byte, most significant bit first. The first coded bit is the MSB of the result.
+    pub const fn encode_byte(&mut self, _byte: u8) -> u16 {
+        let mut output: u16 = 0;
+        let mut i: u32 = 0;
+        while i < 8 {
+            let _coded = self.encode_bit((_byte >> (7 - i)) & 1 != 0);
+            output = (output << 2) | ((_coded[0] as u16) << 1) | _coded[1] as u16;
+            i += 1;
+        }
+        output
+    }
+
+    /// Encodes the [TAIL_BITS] zero bits that end a frame, and resets the encoder for the next one.
+    /// The `2 * TAIL_BITS` coded bits are in the most significant bits of the result.
+    pub const fn flush(&mut self) -> u16 {
+        let mut output: u16 = 0;
+        let mut i: usize = 0;
+        while i < TAIL_BITS {
+            let _coded = self.encode_bit(false);
+            output = (output << 2) | ((_coded[0] as u16) << 1) | _coded[1] as u16;
+            i += 1;
+        }
+        output << (u16::BITS as usize - TAIL_BITS * CODE_RATE_INVERSE)
+    }
+}
+
+/// Convolutionally encodes a whole frame (e.g. the output of [crate::telemetry::encode_packet] or
+/// [crate::qpacket::encode_qpacket]) into `_output`, coded bits packed most significant bit first.
+///
+/// `_output` must be at least [encoded_len_bytes] long. Returns the number of coded bits written.
+pub fn convolutional_encode(_data: &[u8], _output: &mut [u8]) -> usize {
+    debug_assert!(_output.len() >= encoded_len_bytes(_data.len()));
+    let mut _encoder = ConvolutionalEncoder::new();
+    let mut i: usize = 0;
+    for _byte in _data {
+        _output[i..i + 2].copy_from_slice(&_encoder.encode_byte(*_byte).to_be_bytes());
+        i += 2;
+    }
+    let _tail = _encoder.flush().to_be_bytes();
+    _output[i..i + 2].copy_from_slice(&_tail[..encoded_len_bytes(0)]);
+    encoded_len_bits(_data.len())
+}
+
+/// Largest frame the default [ViterbiDecoder] can decode, which is the largest packet we send.
+pub const MAX_CONVOLUTIONAL_FRAME: usize = PROFILED_MESSAGE_MAX_LENGTH_BYTES;
+/// Decoded bits (data and tail) the default [ViterbiDecoder] has room for.
+pub const MAX_VITERBI_BITS: usize = MAX_CONVOLUTIONAL_FRAME * 8 + TAIL_BITS;
+
+/// Result of a successful [ViterbiDecoder] run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ViterbiOutput {
+    /// Number of data bytes written.
+    pub len: usize,
+    /// Total distance between what was received and the coded bits of the decoded frame, in
+    /// [SoftBit] units. 0 for a perfect signal; useful as a link quality figure.
+    pub path_metric: u32,
+}
+
+/// Ground-side maximum-likelihood decoder for [ConvolutionalEncoder] frames, with hard or soft input.
+///
+/// Works on whole, tail-terminated frames. Survivor decisions for up to `MAX_BITS` decoded bits
+/// are kept in the struct, so it is large and best kept in a `static` or on the heap.
+pub struct ViterbiDecoder<const MAX_BITS: usize = MAX_VITERBI_BITS> {
+    decisions: [u64; MAX_BITS],
+}
+
+impl<const MAX_BITS: usize> ViterbiDecoder<MAX_BITS> {
+    pub const fn new() -> Self {
+        Self {
+            decisions: [0u64; MAX_BITS],
+        }
+    }
+
+    /// Decodes a frame of soft bits, two per data bit, into `_output`.
+    pub fn decode_soft(
+        &mut self,
+        _soft_bits: &[SoftBit],
+        _output: &mut [u8],
+    ) -> Result<ViterbiOutput, DecodeError> {
+        self.decode_with(_soft_bits.len(), |i| _soft_bits[i], _output)
+    }
+
+    /// Decodes a frame of `_coded_bits` hard bits, packed most significant bit first as written by
+    /// [convolutional_encode], into `_output`.
+    pub fn decode_hard(
+        &mut self,
+        _coded: &[u8],
+        _coded_bits: usize,
+        _output: &mut [u8],
+    ) -> Result<ViterbiOutput, DecodeError> {
+        if _coded_bits > _coded.len() * 8 {
+            return Err(DecodeError::BadLength);
+        }
+        self.decode_with(
+            _coded_bits,
+            |i| {
+                if _coded[i / 8] & (0x80 >> (i % 8)) != 0 {
+                    SOFT_ONE
+                } else {
+                    SOFT_ZERO
+                }
+            },
+            _output,
+        )
+    }
+
+    fn decode_with(
+        &mut self,
+        _coded_bits: usize,
+        _soft_bit: impl Fn(usize) -> SoftBit,
+        _output: &mut [u8],
+    ) -> Result<ViterbiOutput, DecodeError> {
+        if _coded_bits % CODE_RATE_INVERSE != 0 {
+            return Err(DecodeError::BadLength);
+        }
+        let _steps = _coded_bits / CODE_RATE_INVERSE;
+        if _steps < TAIL_BITS || _steps > MAX_BITS {
+            return Err(DecodeError::BadLength);
+        }
+        let _data_bits = _steps - TAIL_BITS;
+        if _output.len() < _data_bits.div_ceil(8) {
+            return Err(DecodeError::BadLength);
+        }
+
+        // the encoder always starts in state 0; an unreachable start can never win
+        const UNREACHABLE: u32 = u32::MAX / 2;
+        let mut _metrics = [UNREACHABLE; STATE_COUNT];
+        _metrics[0] = 0;
+
+        for _step in 0.._steps {
+            let _received = [_soft_bit(_step * 2) as u32, _soft_bit(_step * 2 + 1) as u32];
+            // cost of each possible pair of coded bits, indexed as in BRANCH_OUTPUTS
+            let mut _branch_costs = [0u32; 4];
+            for _pair in 0.._branch_costs.len() {
+                for x in 0..CODE_RATE_INVERSE {
+                    let _expected_one = _pair & (0b10 >> x) != 0;
+                    _branch_costs[_pair] += if _expected_one {
+                        SOFT_ONE as u32 - _received[x]
+                    } else {
+                        _received[x]
+                    };
+                }
+            }
+
+            let mut _next_metrics = [0u32; STATE_COUNT];
+            let mut _decisions: u64 = 0;
+            for _next_state in 0..STATE_COUNT {
+                // the two states that lead here differ only in their oldest bit
+                let _paths = [0usize, 1].map(|_oldest| {
+                    let _register = (_oldest << TAIL_BITS) | _next_state;
+                    let _state = _register >> 1;
+                    _metrics[_state] + _branch_costs[BRANCH_OUTPUTS[_register] as usize]
+                });
+                if _paths[1] < _paths[0] {
+                    _next_metrics[_next_state] = _paths[1];
+                    _decisions |= 1 << _next_state;
+                } else {
+                    _next_metrics[_next_state] = _paths[0];
+                }
+            }
+            _metrics = _next_metrics;
+            self.decisions[_step] = _decisions;
+        }
+
+        // the tail brings the encoder back to state 0, so trace back from there
+        let mut _state: usize = 0;
+        _output[.._data_bits.div_ceil(8)].fill(0);
+        for _step in (0.._steps).rev() {
+            if _step < _data_bits && _state & 1 != 0 {
+                _output[_step / 8] |= 0x80 >> (_step % 8);
+            }
+            let _oldest = ((self.decisions[_step] >> _state) & 1) as usize;
+            _state = (_state >> 1) | (_oldest << (TAIL_BITS - 1));
+        }
+
+        Ok(ViterbiOutput {
+            len: _data_bits / 8,
+            path_metric: _metrics[0],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{decode_packet, make_packet_skeleton};
+
+    #[test]
+    fn test_impulse_response() {
+        let mut _encoder = ConvolutionalEncoder::new();
+        // a single 1 bit gives the generator polynomials, interleaved: 11 10 11 11 00 01 11
+        assert_eq!(_encoder.encode_byte(0x80), 0b11_10_11_11_00_01_11_00);
+        assert_eq!(_encoder.flush(), 0);
+
+        // the rest of it is pushed out by the tail
+        assert_eq!(_encoder.encode_byte(0x01), 0b11);
+        assert_eq!(_encoder.flush(), 0b10_11_11_00_01_11 << 4);
+        assert_eq!(_encoder.encode_byte(0x00), 0);
+    }
+
+    #[test]
+    fn test_viterbi_hard_errors() {
+        let _packet = make_packet_skeleton(true);
+        let mut _coded = [0u8; encoded_len_bytes(TOTAL_MESSAGE_LENGTH_BYTES)];
+        let _coded_bits = convolutional_encode(&_packet, &mut _coded);
+        assert_eq!(
+            _coded_bits,
+            (TOTAL_MESSAGE_LENGTH_BYTES * 8 + TAIL_BITS) * 2
+        );
+
+        let mut _decoder: ViterbiDecoder = ViterbiDecoder::new();
+        let mut _decoded = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+        let _clean = _decoder
+            .decode_hard(&_coded, _coded_bits, &mut _decoded)
+            .unwrap();
+        assert_eq!(_decoded, _packet);
+        assert_eq!(_clean.path_metric, 0);
+
+        // scattered bit errors, as from a weak signal rather than a burst
+        for i in (3.._coded_bits).step_by(37) {
+            _coded[i / 8] ^= 0x80 >> (i % 8);
+        }
+        let _noisy = _decoder
+            .decode_hard(&_coded, _coded_bits, &mut _decoded)
+            .unwrap();
+        assert_eq!(_noisy.len, TOTAL_MESSAGE_LENGTH_BYTES);
+        assert_eq!(_decoded, _packet);
+        assert!(_noisy.path_metric > 0);
+        assert!(decode_packet(&_decoded, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_viterbi_soft_bits() {
+        let _packet = make_packet_skeleton(false);
+        let mut _coded = [0u8; encoded_len_bytes(TOTAL_MESSAGE_LENGTH_BYTES)];
+        let _coded_bits = convolutional_encode(&_packet, &mut _coded);
+
+        let mut _soft = [SOFT_ERASURE; encoded_len_bits(TOTAL_MESSAGE_LENGTH_BYTES)];
+        for i in 0.._coded_bits {
+            let _bit = _coded[i / 8] & (0x80 >> (i % 8)) != 0;
+            _soft[i] = match i % 11 {
+                // a run of pairs that are wrong, but only weakly so
+                0 => {
+                    if _bit {
+                        100
+                    } else {
+                        155
+                    }
+                }
+                // lost entirely
+                5 => SOFT_ERASURE,
+                _ => {
+                    if _bit {
+                        220
+                    } else {
+                        30
+                    }
+                }
+            };
+        }
+
+        let mut _decoder: ViterbiDecoder = ViterbiDecoder::new();
+        let mut _decoded = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+        _decoder.decode_soft(&_soft, &mut _decoded).unwrap();
+        assert_eq!(_decoded, _packet);
+
+        assert_eq!(
+            _decoder.decode_soft(&_soft[..101], &mut _decoded),
+            Err(DecodeError::BadLength)
+        );
+    }
+}
@@ -0,0 +1,546 @@
+use crate::fec::{Fec, DEFAULT_FEC};
+use crate::parameters::*;
+use crate::telemetry::{
+    decode_packet, encode_packet, values_from_packet, DecodeError, PacketDecodedData,
+};
+
+/// Bytes of each delta packet taken up by [START_HEADER_DATA] and [DELTA_MARKER].
+const DELTA_PREFIX_SIZE: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE;
+/// Bytes of each delta packet taken up by its width field and its keyframe reference.
+const DELTA_HEADER_SIZE: usize = 4;
+/// Bits of the width field used by each block.
+const DELTA_WIDTH_BITS: usize = 2;
+
+/// Offset just past the width field. The width field comes straight after [DELTA_MARKER], so that
+/// [crate::sync::FrameSynchronizer] can read it, and from it the packet's length, before the rest
+/// of the packet arrives.
+pub const DELTA_WIDTH_FIELD_END: usize = DELTA_PREFIX_SIZE + 2;
+/// Offset of the first block's delta.
+const DELTA_DATA_START: usize = DELTA_PREFIX_SIZE + DELTA_HEADER_SIZE;
+/// Length of a delta packet with every block unchanged, without FEC.
+pub const DELTA_MIN_BARE_LEN: usize = DELTA_DATA_START;
+/// Length of a delta packet with every block at its widest, without FEC.
+pub const DELTA_MAX_BARE_LEN: usize = DELTA_DATA_START + BLOCK_STACK_DATA_COUNT * I32_DATA_SIZE;
+pub const DELTA_MAX_TOTAL_LEN: usize = DELTA_MAX_BARE_LEN + FEC_EXTRA_BYTES;
+
+const _: () = assert!(
+    DELTA_MAX_TOTAL_LEN < TOTAL_MESSAGE_LENGTH_BYTES,
+    "delta_packet_longer_than_keyframe"
+);
+
+/// An encoded delta packet. Its length depends on how much each block changed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeltaMessage {
+    data: [u8; DELTA_MAX_TOTAL_LEN],
+    len: usize,
+}
+
+impl DeltaMessage {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Number of delta packets [DeltaEncoder] sends between keyframes, unless told otherwise.
+pub const DEFAULT_KEYFRAME_INTERVAL: u8 = 10;
+
+const _: () = assert!(
+    BLOCK_STACK_DATA_COUNT * DELTA_WIDTH_BITS <= u16::BITS as usize,
+    "delta_width_field_overflow"
+);
+
+/// On-air bytes for each width code.
+const DELTA_WIDTHS: [usize; 4] = [0, 1, 2, 4];
+
+/// Length of the delta packet a width field describes, with FEC.
+const fn delta_total_len(_widths: u16) -> usize {
+    let mut output: usize = DELTA_MIN_BARE_LEN + FEC_EXTRA_BYTES;
+    let mut _block: usize = 0;
+    while _block < BLOCK_STACK_DATA_COUNT {
+        let _code = (_widths >> (u16::BITS as usize - DELTA_WIDTH_BITS * (_block + 1))) & 0b11;
+        output += DELTA_WIDTHS[_code as usize];
+        _block += 1;
+    }
+    output
+}
+
+/// Length of a received delta packet with FEC, going by its width field as received. `_packet`
+/// needs only to reach [DELTA_WIDTH_FIELD_END].
+///
+/// The width field is read before correction, so a bit error in it gives the wrong length, and
+/// the packet is lost; [decode_delta_packet] then fails rather than misreading it.
+pub const fn delta_packet_len(_packet: &[u8]) -> usize {
+    delta_total_len(u16::from_be_bytes([
+        _packet[DELTA_PREFIX_SIZE],
+        _packet[DELTA_PREFIX_SIZE + 1],
+    ]))
+}
+
+/// Returns `true` if a received packet is a delta packet, going by whichever of [DELTA_MARKER],
+/// [FRAGMENT_MARKER] and [BLOCK_DELIMITER] is closest to what follows the header.
+pub const fn is_delta(_packet: &[u8]) -> bool {
+    if _packet.len() < DELTA_PREFIX_SIZE {
+        return false;
+    }
+    let _marker = u16::from_le_bytes([
+        _packet[START_HEADER_DATA.len()],
+        _packet[START_HEADER_DATA.len() + 1],
+    ]);
+    let _distance = (_marker ^ DELTA_MARKER).count_ones();
+    _distance < (_marker ^ BLOCK_DELIMITER).count_ones()
+        && _distance < (_marker ^ FRAGMENT_MARKER).count_ones()
+}
+
+/// Identifies a keyframe, so that a delta packet can say which keyframe it was taken against.
+/// This is a Fletcher-16 checksum of the whole [BareMessage].
+pub const fn keyframe_reference(_keyframe: &BareMessage) -> u16 {
+    let mut _sum1: u16 = 0;
+    let mut _sum2: u16 = 0;
+    let mut i: usize = 0;
+    while i < _keyframe.len() {
+        _sum1 = (_sum1 + _keyframe[i] as u16) % 255;
+        _sum2 = (_sum2 + _sum1) % 255;
+        i += 1;
+    }
+    (_sum2 << 8) | _sum1
+}
+
+/// The raw data of block `_block`, as laid out by [BLOCK_IDENT_STACK], read as a big-endian integer.
+const fn block_raw(_packet: &BareMessage, _block: usize) -> u32 {
+    let _ident = &BLOCK_IDENT_STACK[_block];
+    let mut output: u32 = 0;
+    let mut i: usize = _ident.beginning_location;
+    while i < _ident.end_location {
+        output = (output << 8) | _packet[i] as u32;
+        i += 1;
+    }
+    output
+}
+
+const fn set_block_raw(_packet: &mut BareMessage, _block: usize, _raw: u32) {
+    let _ident = &BLOCK_IDENT_STACK[_block];
+    let mut _raw = _raw;
+    let mut i: usize = _ident.end_location;
+    while i > _ident.beginning_location {
+        i -= 1;
+        _packet[i] = _raw as u8;
+        _raw >>= 8;
+    }
+}
+
+/// The difference between two raw block values, wrapped to the block's size and sign-extended,
+/// so that a small change is a small number whatever the [BlockType].
+///
+/// [BlockType::F32] blocks are differenced on their bit patterns, which is lossless and stays
+/// small as long as the sign and exponent do not change.
+const fn block_delta(_block: usize, _old: u32, _new: u32) -> i32 {
+    let _shift = 32 - 8 * BLOCK_IDENT_STACK[_block].size as u32;
+    ((_new.wrapping_sub(_old) << _shift) as i32) >> _shift
+}
+
+/// Smallest width code whose [DELTA_WIDTHS] can hold `_delta`.
+const fn width_code(_delta: i32) -> usize {
+    if _delta == 0 {
+        0
+    } else if _delta >= i8::MIN as i32 && _delta <= i8::MAX as i32 {
+        1
+    } else if _delta >= i16::MIN as i32 && _delta <= i16::MAX as i32 {
+        2
+    } else {
+        3
+    }
+}
+
+/// The parsed contents of a delta packet: which keyframe it applies to, and each block's delta.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeltaPacket {
+    /// The [keyframe_reference] of the keyframe these deltas were taken against.
+    pub reference: u16,
+    pub deltas: [i32; BLOCK_STACK_DATA_COUNT],
+}
+
+impl DeltaPacket {
+    /// Takes the difference between `_packet` and `_keyframe`, block by block.
+    pub const fn new(_keyframe: &BareMessage, _packet: &BareMessage) -> Self {
+        let mut _deltas = [0i32; BLOCK_STACK_DATA_COUNT];
+        let mut i: usize = 0;
+        while i < BLOCK_STACK_DATA_COUNT {
+            _deltas[i] = block_delta(i, block_raw(_keyframe, i), block_raw(_packet, i));
+            i += 1;
+        }
+        Self {
+            reference: keyframe_reference(_keyframe),
+            deltas: _deltas,
+        }
+    }
+
+    /// Rebuilds the absolute packet from `_keyframe`. Fails with [DecodeError::MissingKeyframe] if
+    /// this delta was not taken against `_keyframe`.
+    pub const fn apply(&self, _keyframe: &BareMessage) -> Result<BareMessage, DecodeError> {
+        if keyframe_reference(_keyframe) != self.reference {
+            return Err(DecodeError::MissingKeyframe);
+        }
+        let mut output: BareMessage = *_keyframe;
+        let mut i: usize = 0;
+        while i < BLOCK_STACK_DATA_COUNT {
+            set_block_raw(
+                &mut output,
+                i,
+                block_raw(_keyframe, i).wrapping_add(self.deltas[i] as u32),
+            );
+            i += 1;
+        }
+        Ok(output)
+    }
+
+    /// Builds this delta into a packet, without FEC. Returns the buffer and how much of it is used.
+    pub fn to_bytes(&self) -> ([u8; DELTA_MAX_BARE_LEN], usize) {
+        let mut output = [0u8; DELTA_MAX_BARE_LEN];
+        let mut i: usize = 0;
+        output[i..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
+        i += START_HEADER_DATA.len();
+        output[i..i + BLOCK_DELIMITER_SIZE].copy_from_slice(&DELTA_MARKER.to_le_bytes());
+        i += BLOCK_DELIMITER_SIZE;
+        output[DELTA_WIDTH_FIELD_END..DELTA_DATA_START]
+            .copy_from_slice(&self.reference.to_be_bytes());
+        i += DELTA_HEADER_SIZE;
+
+        // block 0's width code is in the two most significant bits
+        let mut _widths: u16 = 0;
+        for _block in 0..BLOCK_STACK_DATA_COUNT {
+            let _code = width_code(self.deltas[_block]);
+            let _width = DELTA_WIDTHS[_code];
+            _widths |= (_code as u16) << (u16::BITS as usize - DELTA_WIDTH_BITS * (_block + 1));
+            output[i..i + _width]
+                .copy_from_slice(&self.deltas[_block].to_be_bytes()[I32_DATA_SIZE - _width..]);
+            i += _width;
+        }
+        output[DELTA_PREFIX_SIZE..DELTA_WIDTH_FIELD_END].copy_from_slice(&_widths.to_be_bytes());
+
+        (output, i)
+    }
+
+    fn from_bytes(_packet: &[u8]) -> Result<Self, DecodeError> {
+        let _widths =
+            u16::from_be_bytes([_packet[DELTA_PREFIX_SIZE], _packet[DELTA_PREFIX_SIZE + 1]]);
+        let _reference = u16::from_be_bytes([
+            _packet[DELTA_WIDTH_FIELD_END],
+            _packet[DELTA_WIDTH_FIELD_END + 1],
+        ]);
+        // the corrected width field must agree with the length the packet was framed at
+        if delta_total_len(_widths) - FEC_EXTRA_BYTES != _packet.len() {
+            return Err(DecodeError::BadLength);
+        }
+
+        let mut i: usize = DELTA_DATA_START;
+        let mut _deltas = [0i32; BLOCK_STACK_DATA_COUNT];
+        for _block in 0..BLOCK_STACK_DATA_COUNT {
+            let _code = (_widths >> (u16::BITS as usize - DELTA_WIDTH_BITS * (_block + 1))) & 0b11;
+            let _width = DELTA_WIDTHS[_code as usize];
+            // sign-extend from the top byte
+            let mut _delta: i32 = if _width > 0 && _packet[i] & 0x80 != 0 {
+                -1
+            } else {
+                0
+            };
+            for x in 0.._width {
+                _delta = (_delta << 8) | _packet[i + x] as i32;
+            }
+            _deltas[_block] = _delta;
+            i += _width;
+        }
+
+        Ok(Self {
+            reference: _reference,
+            deltas: _deltas,
+        })
+    }
+}
+
+/// Encodes the given delta packet with [DEFAULT_FEC].
+pub fn encode_delta_packet(_delta: &DeltaPacket) -> DeltaMessage {
+    let (_bare, _bare_len) = _delta.to_bytes();
+    let mut output = DeltaMessage {
+        data: [0u8; DELTA_MAX_TOTAL_LEN],
+        len: _bare_len + FEC_EXTRA_BYTES,
+    };
+    DEFAULT_FEC.encode(&_bare[.._bare_len], &mut output.data[..output.len]);
+    output
+}
+
+/// Corrects a received delta packet and parses it.
+///
+/// Like [crate::fragment::decode_fragment], only [START_HEADER_DATA] and [DELTA_MARKER] are
+/// restored before correction. The packet's length must be known from the framing it arrived in,
+/// e.g. from [delta_packet_len].
+pub fn decode_delta_packet(_packet: &[u8]) -> Result<DeltaPacket, DecodeError> {
+    if _packet.len() < DELTA_MIN_BARE_LEN + FEC_EXTRA_BYTES || _packet.len() > DELTA_MAX_TOTAL_LEN {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut _buffer = [0u8; DELTA_MAX_TOTAL_LEN];
+    let _buffer = &mut _buffer[.._packet.len()];
+    _buffer.copy_from_slice(_packet);
+    _buffer[..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
+    _buffer[START_HEADER_DATA.len()..DELTA_PREFIX_SIZE]
+        .copy_from_slice(&DELTA_MARKER.to_le_bytes());
+
+    let mut _recovered = [0u8; DELTA_MAX_BARE_LEN];
+    let _recovered = &mut _recovered[.._packet.len() - FEC_EXTRA_BYTES];
+    DEFAULT_FEC
+        .decode(_buffer, &[], _recovered)
+        .map_err(|_| DecodeError::Uncorrectable)?;
+
+    DeltaPacket::from_bytes(_recovered)
+}
+
+/// A packet as sent by [DeltaEncoder]: either a full keyframe or a delta against one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeltaFrame {
+    Keyframe(TotalMessage),
+    Delta(DeltaMessage),
+}
+
+impl DeltaFrame {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Keyframe(_packet) => _packet,
+            Self::Delta(_packet) => _packet.as_bytes(),
+        }
+    }
+}
+
+/// Flight-side delta encoder. Sends a full keyframe, then up to `interval` delta packets against
+/// it, then another keyframe.
+#[derive(Debug, Copy, Clone)]
+pub struct DeltaEncoder {
+    keyframe: Option<BareMessage>,
+    interval: u8,
+    since_keyframe: u8,
+}
+
+impl DeltaEncoder {
+    pub const fn new(_interval: u8) -> Self {
+        Self {
+            keyframe: None,
+            interval: _interval,
+            since_keyframe: 0,
+        }
+    }
+
+    /// Makes the next packet a keyframe, e.g. after a long gap in transmission.
+    pub const fn force_keyframe(&mut self) {
+        self.keyframe = None;
+    }
+
+    /// Encodes `_packet` as either a keyframe or a delta, whichever is due.
+    pub fn encode(&mut self, _packet: &BareMessage) -> DeltaFrame {
+        match self.keyframe {
+            Some(_keyframe) if self.since_keyframe < self.interval => {
+                self.since_keyframe += 1;
+                DeltaFrame::Delta(encode_delta_packet(&DeltaPacket::new(&_keyframe, _packet)))
+            }
+            _ => {
+                self.keyframe = Some(*_packet);
+                self.since_keyframe = 0;
+                DeltaFrame::Keyframe(encode_packet(_packet))
+            }
+        }
+    }
+}
+
+/// Ground-side delta decoder. Keeps the two most recent keyframes, so that a delta taken against
+/// the previous keyframe can still be rebuilt if it arrives after the next one.
+///
+/// Deltas whose keyframe was lost fail with [DecodeError::MissingKeyframe] until the next keyframe
+/// arrives; they are never applied to the wrong keyframe.
+#[derive(Debug, Copy, Clone)]
+pub struct DeltaDecoder {
+    keyframes: [Option<BareMessage>; 2],
+}
+
+impl DeltaDecoder {
+    pub const fn new() -> Self {
+        Self {
+            keyframes: [None; 2],
+        }
+    }
+
+    pub const fn has_keyframe(&self) -> bool {
+        self.keyframes[0].is_some()
+    }
+
+    /// Remembers a keyframe that has already been decoded.
+    pub const fn push_keyframe(&mut self, _keyframe: &BareMessage) {
+        self.keyframes[1] = self.keyframes[0];
+        self.keyframes[0] = Some(*_keyframe);
+    }
+
+    /// Rebuilds the absolute packet a delta stands for, from whichever keyframe it was taken against.
+    pub fn apply(&self, _delta: &DeltaPacket) -> Result<BareMessage, DecodeError> {
+        for _keyframe in self.keyframes.iter().flatten() {
+            if let Ok(_packet) = _delta.apply(_keyframe) {
+                return Ok(_packet);
+            }
+        }
+        Err(DecodeError::MissingKeyframe)
+    }
+
+    /// Decodes a received keyframe or delta packet into absolute values.
+    pub fn push(&mut self, _packet: &[u8]) -> Result<PacketDecodedData, DecodeError> {
+        let _absolute = if is_delta(_packet) {
+            self.apply(&decode_delta_packet(_packet)?)?
+        } else {
+            let (_keyframe, _) = decode_packet(_packet, &[])?;
+            self.push_keyframe(&_keyframe);
+            _keyframe
+        };
+        Ok(values_from_packet(_absolute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{blocks_from_values, construct_blocks, construct_packet};
+
+    fn make_packet(_values: PacketDecodedData) -> BareMessage {
+        construct_packet(construct_blocks(&blocks_from_values(&_values)))
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let _keyframe = make_packet([
+            1.0,
+            1337.69,
+            3.3,
+            -21.5,
+            41.9876543,
+            -87.2345678,
+            7.0,
+            1_400_000_000.0,
+        ]);
+        let _packet = make_packet([
+            1.0,
+            1340.0,
+            3.29,
+            -22.0,
+            41.9876600,
+            -87.2345678,
+            8.0,
+            1_400_000_010.0,
+        ]);
+
+        let _delta = DeltaPacket::new(&_keyframe, &_packet);
+        assert_eq!(_delta.deltas[0], 0);
+        assert_eq!(_delta.deltas[3], -50);
+        assert_eq!(_delta.deltas[5], 0);
+
+        let _encoded = encode_delta_packet(&_delta);
+        assert!(_encoded.as_bytes().len() < TOTAL_MESSAGE_LENGTH_BYTES);
+        assert!(is_delta(_encoded.as_bytes()));
+        assert_eq!(
+            delta_packet_len(_encoded.as_bytes()),
+            _encoded.as_bytes().len()
+        );
+
+        let mut _damaged = [0u8; DELTA_MAX_TOTAL_LEN];
+        let _damaged = &mut _damaged[.._encoded.as_bytes().len()];
+        _damaged.copy_from_slice(_encoded.as_bytes());
+        _damaged[3] ^= 0xFF;
+        _damaged[14] ^= 0xFF;
+        let _decoded = decode_delta_packet(_damaged).unwrap();
+        assert_eq!(_decoded, _delta);
+        assert_eq!(_decoded.apply(&_keyframe), Ok(_packet));
+
+        let _other_keyframe = make_packet([2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(
+            _decoded.apply(&_other_keyframe),
+            Err(DecodeError::MissingKeyframe)
+        );
+    }
+
+    #[test]
+    fn test_delta_length_on_air() {
+        let _keyframe = make_packet([
+            1.0,
+            1337.69,
+            3.3,
+            -21.5,
+            41.9876543,
+            -87.2345678,
+            7.0,
+            1_400_000_000.0,
+        ]);
+        let _unchanged = encode_delta_packet(&DeltaPacket::new(&_keyframe, &_keyframe));
+        assert_eq!(
+            _unchanged.as_bytes().len(),
+            DELTA_MIN_BARE_LEN + FEC_EXTRA_BYTES
+        );
+
+        let _widest = DeltaPacket {
+            reference: keyframe_reference(&_keyframe),
+            deltas: [i32::MIN; BLOCK_STACK_DATA_COUNT],
+        };
+        let _widest = encode_delta_packet(&_widest);
+        assert_eq!(_widest.as_bytes().len(), DELTA_MAX_TOTAL_LEN);
+        assert!(_unchanged.as_bytes().len() < _widest.as_bytes().len());
+
+        for _encoded in [_unchanged, _widest] {
+            assert_eq!(
+                delta_packet_len(_encoded.as_bytes()),
+                _encoded.as_bytes().len()
+            );
+        }
+        assert_eq!(
+            decode_delta_packet(_unchanged.as_bytes())
+                .unwrap()
+                .apply(&_keyframe),
+            Ok(_keyframe)
+        );
+        // framed at the wrong length, the packet is rejected rather than misread
+        assert!(decode_delta_packet(&_widest.as_bytes()[.._widest.as_bytes().len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_delta_stream_lost_keyframe() {
+        let mut _encoder = DeltaEncoder::new(2);
+        let mut _frames = [None; 6];
+        for i in 0.._frames.len() {
+            let _altitude = 1000.0 + 5.0 * i as f64;
+            _frames[i] = Some(_encoder.encode(&make_packet([
+                0.0,
+                _altitude,
+                3.3,
+                -20.0,
+                41.98,
+                -87.23,
+                i as f64,
+                1_400_000_000.0,
+            ])));
+        }
+        let _frames = _frames.map(Option::unwrap);
+        assert!(matches!(_frames[0], DeltaFrame::Keyframe(_)));
+        assert!(matches!(_frames[2], DeltaFrame::Delta(_)));
+        assert!(matches!(_frames[3], DeltaFrame::Keyframe(_)));
+
+        let mut _decoder = DeltaDecoder::new();
+        // the first keyframe never arrives
+        assert_eq!(
+            _decoder.push(_frames[1].as_bytes()),
+            Err(DecodeError::MissingKeyframe)
+        );
+        assert_eq!(_decoder.push(_frames[3].as_bytes()).unwrap()[1], 1015.0);
+        assert_eq!(_decoder.push(_frames[5].as_bytes()).unwrap()[1], 1025.0);
+        assert_eq!(
+            _decoder.push(_frames[2].as_bytes()),
+            Err(DecodeError::MissingKeyframe)
+        );
+
+        // late keyframe, then a reordered delta against the keyframe before it
+        let mut _decoder = DeltaDecoder::new();
+        _decoder.push(_frames[0].as_bytes()).unwrap();
+        _decoder.push(_frames[3].as_bytes()).unwrap();
+        assert_eq!(_decoder.push(_frames[2].as_bytes()).unwrap()[1], 1010.0);
+    }
+}
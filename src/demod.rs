@@ -0,0 +1,321 @@
+use crate::convolutional::{SoftBit, SOFT_ERASURE};
+use crate::fsk::{FskConfig, SINE_TABLE, SINE_TABLE_SIZE};
+
+/// Clock recovery loop gain: the fraction of the timing error removed at each transition.
+const CLOCK_GAIN: f32 = 0.1;
+/// Frequency tracking loop gain, per sample.
+const FREQUENCY_GAIN: f32 = 0.0005;
+/// How much stronger one tone must be than the other before it is used to track frequency, so
+/// that symbol transitions do not pull the estimate around.
+const TRACKING_POWER_RATIO: f32 = 8.0;
+
+const TAU: f32 = 6.2831855;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const ZERO: Self = Self { re: 0.0, im: 0.0 };
+
+    fn norm_sqr(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// `self * e^(-j * 2pi * _phase)`, with `_phase` in cycles, looked up in [SINE_TABLE].
+    fn mix_down(&self, _phase: f32) -> Self {
+        let _index = (_phase * SINE_TABLE_SIZE as f32) as usize % SINE_TABLE_SIZE;
+        let _sin = SINE_TABLE[_index] as f32 / i16::MAX as f32;
+        let _cos =
+            SINE_TABLE[(_index + SINE_TABLE_SIZE / 4) % SINE_TABLE_SIZE] as f32 / i16::MAX as f32;
+        Self {
+            re: self.re * _cos + self.im * _sin,
+            im: self.im * _cos - self.re * _sin,
+        }
+    }
+}
+
+/// Mixes one tone down to DC and low-passes it with two one-pole stages.
+#[derive(Debug, Copy, Clone)]
+struct ToneFilter {
+    frequency: f32,
+    phase: f32,
+    stages: [Complex; 2],
+    previous: Complex,
+}
+
+impl ToneFilter {
+    const fn new(_frequency: f32) -> Self {
+        Self {
+            frequency: _frequency,
+            phase: 0.0,
+            stages: [Complex::ZERO; 2],
+            previous: Complex::ZERO,
+        }
+    }
+
+    fn push(&mut self, _sample: Complex, _alpha: f32, _step: f32) {
+        let mut _mixed = _sample.mix_down(self.phase);
+        self.phase += _step;
+        self.phase -= (self.phase as i32) as f32;
+        if self.phase < 0.0 {
+            self.phase += 1.0;
+        }
+
+        self.previous = self.stages[1];
+        for _stage in self.stages.iter_mut() {
+            _stage.re += _alpha * (_mixed.re - _stage.re);
+            _stage.im += _alpha * (_mixed.im - _stage.im);
+            _mixed = *_stage;
+        }
+    }
+
+    fn power(&self) -> f32 {
+        self.stages[1].norm_sqr()
+    }
+
+    /// Rotation of the filtered tone since the previous sample, in cycles. Close to zero when the
+    /// tone is exactly where it was expected.
+    fn residual(&self) -> f32 {
+        let _now = self.stages[1];
+        let _cross = _now.im * self.previous.re - _now.re * self.previous.im;
+        _cross / (self.power() * TAU + f32::MIN_POSITIVE)
+    }
+}
+
+/// Non-coherent 2-FSK demodulator, the receiving end of [crate::fsk::FskModulator].
+///
+/// Each tone is mixed to DC and low-passed, with the cutoff at the baud rate. The normalised
+/// difference of their powers is the soft decision. Clock recovery nudges the sampling point to
+/// half a symbol away from each transition. Frequency tracking follows whichever tone is on, and
+/// shifts both mixers by the same amount, as an off-tune transmitter or receiver would.
+///
+/// Samples go in one at a time; out comes a [SoftBit] per symbol, mark being a 1.
+#[derive(Debug, Copy, Clone)]
+pub struct FskDemodulator {
+    config: FskConfig,
+    mark: ToneFilter,
+    space: ToneFilter,
+    alpha: f32,
+    /// Symbols per sample.
+    clock_step: f32,
+    /// Position within the current symbol, from 0 to 1; a bit is decided on wrapping.
+    clock: f32,
+    /// Estimated offset of both tones, in cycles per sample.
+    offset: f32,
+    last_decision: f32,
+}
+
+impl FskDemodulator {
+    pub fn new(_config: FskConfig) -> Self {
+        let _sample_rate = _config.sample_rate as f32;
+        Self {
+            config: _config,
+            mark: ToneFilter::new(_config.mark_hz as f32 / _sample_rate),
+            space: ToneFilter::new(_config.space_hz as f32 / _sample_rate),
+            alpha: (TAU * _config.baud as f32 / _sample_rate).min(1.0),
+            clock_step: _config.baud as f32 / _sample_rate,
+            clock: 0.0,
+            offset: 0.0,
+            last_decision: 0.0,
+        }
+    }
+
+    pub const fn config(&self) -> &FskConfig {
+        &self.config
+    }
+
+    /// How far the received tones are above where [FskConfig] says they should be.
+    pub fn frequency_offset_hz(&self) -> f32 {
+        self.offset * self.config.sample_rate as f32
+    }
+
+    /// Takes one real sample, e.g. from a sound card.
+    pub fn push_real(&mut self, _sample: f32) -> Option<SoftBit> {
+        self.push_complex(_sample, 0.0)
+    }
+
+    /// Takes one complex baseband sample, with the tones at their (possibly negative) frequencies.
+    pub fn push_complex(&mut self, _re: f32, _im: f32) -> Option<SoftBit> {
+        let _sample = Complex { re: _re, im: _im };
+        self.mark
+            .push(_sample, self.alpha, self.mark.frequency + self.offset);
+        self.space
+            .push(_sample, self.alpha, self.space.frequency + self.offset);
+
+        let _mark_power = self.mark.power();
+        let _space_power = self.space.power();
+        let _decision =
+            (_mark_power - _space_power) / (_mark_power + _space_power + f32::MIN_POSITIVE);
+
+        if _mark_power > TRACKING_POWER_RATIO * _space_power {
+            self.offset += FREQUENCY_GAIN * self.mark.residual();
+        } else if _space_power > TRACKING_POWER_RATIO * _mark_power {
+            self.offset += FREQUENCY_GAIN * self.space.residual();
+        }
+
+        // transitions should fall halfway between decisions
+        if (_decision < 0.0) != (self.last_decision < 0.0) {
+            self.clock -= CLOCK_GAIN * (self.clock - 0.5);
+        }
+        self.last_decision = _decision;
+
+        self.clock += self.clock_step;
+        if self.clock < 1.0 {
+            return None;
+        }
+        self.clock -= 1.0;
+        Some(to_soft_bit(_decision))
+    }
+
+    /// Demodulates 16-bit samples (as produced by [crate::fsk::FskModulator]) into `_output`.
+    /// Stops as soon as `_output` is full, and returns how many samples were consumed and how many
+    /// soft bits were written; the rest of `_samples` can then be passed in again.
+    pub fn demodulate(&mut self, _samples: &[i16], _output: &mut [SoftBit]) -> (usize, usize) {
+        let mut _consumed: usize = 0;
+        let mut _written: usize = 0;
+        while _consumed < _samples.len() && _written < _output.len() {
+            if let Some(_bit) = self.push_real(_samples[_consumed] as f32 / i16::MAX as f32) {
+                _output[_written] = _bit;
+                _written += 1;
+            }
+            _consumed += 1;
+        }
+        (_consumed, _written)
+    }
+}
+
+/// Maps a decision from -1 (space) to 1 (mark) onto a [SoftBit].
+fn to_soft_bit(_decision: f32) -> SoftBit {
+    let _scaled = SOFT_ERASURE as f32 + _decision * 127.5;
+    _scaled.clamp(0.0, u8::MAX as f32) as SoftBit
+}
+
+/// Hard decision on a soft bit.
+pub const fn hard_bit(_bit: SoftBit) -> bool {
+    _bit >= SOFT_ERASURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsk::modulate_packet;
+    use crate::parameters::*;
+    use crate::sync::FrameSynchronizer;
+    use crate::telemetry::{decode_packet, make_packet_skeleton, make_packet_skeleton_nofec};
+    use crate::whitening::Whitening;
+
+    const TEST_CONFIG: FskConfig = FskConfig {
+        mark_hz: 1200,
+        space_hz: 2200,
+        baud: 300,
+        sample_rate: 9600,
+        whitening: Whitening::None,
+    };
+
+    const PREAMBLE_BYTES: usize = 8;
+    const FRAME_BYTES: usize = PREAMBLE_BYTES + TOTAL_MESSAGE_LENGTH_BYTES + 2;
+
+    /// A packet with an alternating preamble for the clock to lock onto, and a little tail.
+    fn frame() -> [u8; FRAME_BYTES] {
+        let mut _frame = [0x55u8; FRAME_BYTES];
+        _frame[PREAMBLE_BYTES..PREAMBLE_BYTES + TOTAL_MESSAGE_LENGTH_BYTES]
+            .copy_from_slice(&make_packet_skeleton(true));
+        _frame
+    }
+
+    /// Runs `_frame`, sent with `_transmitter`, through a [FskDemodulator] expecting
+    /// [TEST_CONFIG] and on into [FrameSynchronizer].
+    fn receive(_transmitter: FskConfig, _noise: f32) -> (Option<TotalMessage>, FskDemodulator) {
+        let _frame = frame();
+        let mut _demodulator = FskDemodulator::new(TEST_CONFIG);
+        let mut _sync = FrameSynchronizer::new(2);
+        let mut _received: Option<TotalMessage> = None;
+        let mut _seed: u32 = 1;
+
+        for _sample in modulate_packet(_transmitter, &_frame) {
+            // cheap uniform noise
+            _seed = _seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let _noise_sample = ((_seed >> 8) as f32 / (1 << 24) as f32 - 0.5) * 2.0 * _noise;
+
+            let _input = _sample as f32 / i16::MAX as f32 * 0.5 + _noise_sample;
+            if let Some(_bit) = _demodulator.push_real(_input) {
+                if let Some(_packet) = _sync.push_bit(hard_bit(_bit)) {
+                    _received = <TotalMessage>::try_from(_packet.as_bytes()).ok();
+                }
+            }
+        }
+        (_received, _demodulator)
+    }
+
+    #[test]
+    fn test_demodulate_clean() {
+        let (_received, _) = receive(TEST_CONFIG, 0.0);
+        let _received = _received.unwrap();
+        assert_eq!(_received, make_packet_skeleton(true));
+    }
+
+    #[test]
+    fn test_demodulate_impaired() {
+        // off-tune by 60 Hz, with a transmitter clock 0.3% fast, in noise
+        let _transmitter = FskConfig {
+            mark_hz: TEST_CONFIG.mark_hz + 60,
+            space_hz: TEST_CONFIG.space_hz + 60,
+            baud: 301,
+            ..TEST_CONFIG
+        };
+        let (_received, _demodulator) = receive(_transmitter, 0.4);
+        let (_decoded, _) = decode_packet(&_received.unwrap(), &[]).unwrap();
+        assert_eq!(_decoded, make_packet_skeleton_nofec(true));
+        assert!(
+            (_demodulator.frequency_offset_hz() - 60.0).abs() < 15.0,
+            "{}",
+            _demodulator.frequency_offset_hz()
+        );
+    }
+
+    #[test]
+    fn test_soft_bits() {
+        let mut _demodulator = FskDemodulator::new(TEST_CONFIG);
+        let mut _samples = [0i16; 32 * 8];
+        let _len = modulate_packet(TEST_CONFIG, &[0xF0]).modulate_into(&mut _samples);
+        let mut _bits = [SOFT_ERASURE; 8];
+        assert_eq!(
+            _demodulator.demodulate(&_samples[.._len], &mut _bits),
+            (_len, 8)
+        );
+        // confident in the middle of each run, less so next to the transition
+        assert!(_bits[1] > 230 && _bits[2] > 230);
+        assert!(_bits[5] < 25 && _bits[6] < 25);
+    }
+
+    #[test]
+    fn test_demodulate_default_link() {
+        // 200 Hz apart at a single baud, with an output buffer much smaller than the message
+        let _message = [0x55, 0xA5, 0x3C];
+        let mut _frame = [0x55u8; 4];
+        _frame[.._message.len()].copy_from_slice(&_message);
+        let mut _modulator = modulate_packet(FskConfig::DEFAULT, &_frame);
+        let mut _demodulator = FskDemodulator::new(FskConfig::DEFAULT);
+        let mut _samples = [0i16; 4096];
+        let mut _bits = [SOFT_ERASURE; 24];
+        let mut _received: usize = 0;
+        while _received < _bits.len() {
+            let _len = _modulator.modulate_into(&mut _samples);
+            assert!(_len > 0);
+            let mut _position: usize = 0;
+            while _position < _len && _received < _bits.len() {
+                let _end = (_received + 5).min(_bits.len());
+                let (_consumed, _written) = _demodulator
+                    .demodulate(&_samples[_position.._len], &mut _bits[_received.._end]);
+                _position += _consumed;
+                _received += _written;
+            }
+        }
+        for (i, _bit) in _bits.iter().enumerate() {
+            assert_eq!(hard_bit(*_bit), (_message[i / 8] >> (7 - i % 8)) & 1 != 0);
+        }
+    }
+}
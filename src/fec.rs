@@ -0,0 +1,499 @@
+use crate::parameters::*;
+use crate::telemetry::DecodeError;
+
+use reed_solomon::{Decoder, Encoder};
+
+/// Longest codeword any [Fec] here will be asked to decode. Reed-Solomon over GF(2^8) cannot go
+/// past 255 symbols, and the other codes fit a [BareMessage] with room to spare.
+pub const MAX_FEC_CODEWORD_LENGTH_BYTES: usize = 255;
+
+/// A forward error correction code that packets can be encoded with.
+///
+/// The packet builders ([crate::telemetry::encode_packet_with],
+/// [crate::qpacket::encode_qpacket_with] and their decoders) are generic over this, so a build
+/// can trade code size and RAM against coding gain without touching the packet code.
+///
+/// The receive path ([crate::sync::FrameSynchronizer], [crate::layout::LAYOUT_REGISTRY] and
+/// [crate::layout::decode_any_packet]) only knows the Reed-Solomon lengths the packet modules send
+/// by default. A packet encoded with any other code must be found and cut to length by an outer
+/// layer (e.g. its own sync word and length field ahead of the codeword), then handed to the
+/// matching `decode_*_with`. For codes that are not [Fec::is_systematic], such as [Golay24] and
+/// [InterleavedHamming74], there is no way around this: [START_HEADER_DATA] never appears on air
+/// as it is, so the synchronizer cannot find the packet at all.
+pub trait Fec {
+    /// Number of bytes the code adds to `_data_len` bytes of data.
+    fn overhead(&self, _data_len: usize) -> usize;
+
+    /// Length on air of `_data_len` bytes of data.
+    fn encoded_len(&self, _data_len: usize) -> usize {
+        _data_len + self.overhead(_data_len)
+    }
+
+    /// Whether the data is sent unchanged at the start of the codeword. Only then can decoders
+    /// restore known bytes (see [crate::telemetry::find_packet_similarities]) before correcting.
+    fn is_systematic(&self) -> bool {
+        false
+    }
+
+    /// Most erasures [Fec::decode] can fill in. Codes that cannot make use of erasures ignore them,
+    /// and have no limit.
+    fn max_erasures(&self, _data_len: usize) -> usize {
+        usize::MAX
+    }
+
+    /// Encodes `_data` into `_output`, which must be exactly [Fec::encoded_len] long.
+    fn encode(&self, _data: &[u8], _output: &mut [u8]);
+
+    /// Corrects `_received` and writes its data to `_output`, whose length is the data length.
+    /// `_erasures` are indices into `_received` known to be bad.
+    ///
+    /// Returns how many errors were corrected, in the code's own unit: bytes for Reed-Solomon,
+    /// bits for the binary codes.
+    fn decode(
+        &self,
+        _received: &[u8],
+        _erasures: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, DecodeError>;
+}
+
+fn check_lengths<F: Fec + ?Sized>(
+    _fec: &F,
+    _received: &[u8],
+    _data_len: usize,
+) -> Result<(), DecodeError> {
+    if _received.len() != _fec.encoded_len(_data_len)
+        || _received.len() > MAX_FEC_CODEWORD_LENGTH_BYTES
+    {
+        return Err(DecodeError::BadLength);
+    }
+    Ok(())
+}
+
+/// Reed-Solomon over GF(2^8) with `parity` bytes of parity, as packets have always been sent.
+/// Corrects up to `parity / 2` damaged bytes, or `parity` erasures.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReedSolomon {
+    parity: usize,
+}
+
+impl ReedSolomon {
+    pub const fn new(_parity: usize) -> Self {
+        Self { parity: _parity }
+    }
+
+    pub const fn parity(&self) -> usize {
+        self.parity
+    }
+}
+
+/// The code [crate::telemetry::encode_packet] uses.
+pub const DEFAULT_FEC: ReedSolomon = ReedSolomon::new(FEC_EXTRA_BYTES);
+
+impl Fec for ReedSolomon {
+    fn overhead(&self, _data_len: usize) -> usize {
+        self.parity
+    }
+
+    fn is_systematic(&self) -> bool {
+        true
+    }
+
+    fn max_erasures(&self, _data_len: usize) -> usize {
+        self.parity
+    }
+
+    fn encode(&self, _data: &[u8], _output: &mut [u8]) {
+        let _encoded = Encoder::new(self.parity).encode(_data);
+        _output.copy_from_slice(&_encoded[..]);
+    }
+
+    fn decode(
+        &self,
+        _received: &[u8],
+        _erasures: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, DecodeError> {
+        check_lengths(self, _received, _output.len())?;
+        if _erasures.len() > self.parity {
+            return Err(DecodeError::TooManyErasures);
+        }
+
+        let mut _buffer = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+        let _buffer = &mut _buffer[.._received.len()];
+        _buffer.copy_from_slice(_received);
+        let recovery_buffer = Decoder::new(self.parity)
+            .correct(_buffer, Some(_erasures))
+            .map_err(|_| DecodeError::Uncorrectable)?;
+
+        _output.copy_from_slice(recovery_buffer.data());
+        Ok(_received
+            .iter()
+            .zip(recovery_buffer[..].iter())
+            .filter(|(_sent, _corrected)| _sent != _corrected)
+            .count())
+    }
+}
+
+/// Parity half of the extended Golay code's generator matrix `[I | B]`. `B` is symmetric and its
+/// own inverse, which the decoder relies on. Row `i` is stored with column 0 in bit 11.
+const GOLAY_B: [u16; 12] = [
+    0b1101_1100_0101,
+    0b1011_1000_1011,
+    0b0111_0001_0111,
+    0b1110_0010_1101,
+    0b1100_0101_1011,
+    0b1000_1011_0111,
+    0b0001_0110_1111,
+    0b0010_1101_1101,
+    0b0101_1011_1001,
+    0b1011_0111_0001,
+    0b0110_1110_0011,
+    0b1111_1111_1110,
+];
+
+/// `_word * B`, for a 12-bit row vector with element 0 in bit 11.
+const fn golay_mul_b(_word: u16) -> u16 {
+    let mut output: u16 = 0;
+    let mut i: usize = 0;
+    while i < 12 {
+        if _word & (1 << (11 - i)) != 0 {
+            output ^= GOLAY_B[i];
+        }
+        i += 1;
+    }
+    output
+}
+
+const _: () = {
+    let mut i: usize = 0;
+    while i < 12 {
+        assert!(
+            golay_mul_b(GOLAY_B[i]) == 1 << (11 - i),
+            "GOLAY_B_is_not_its_own_inverse"
+        );
+        i += 1;
+    }
+};
+
+/// Corrects one 24-bit codeword (message in the top 12 bits), returning the message and the number
+/// of bits corrected. Up to 3 bad bits are corrected, and 4 are detected.
+const fn golay_decode_word(_codeword: u32) -> Option<(u16, usize)> {
+    let _message = ((_codeword >> 12) & 0xFFF) as u16;
+    let _parity = (_codeword & 0xFFF) as u16;
+
+    // error pattern (message half, parity half)
+    let mut _error: Option<(u16, u16)> = None;
+    let _syndrome = golay_mul_b(_message) ^ _parity;
+    if _syndrome.count_ones() <= 3 {
+        _error = Some((0, _syndrome));
+    }
+    let mut i: usize = 0;
+    while _error.is_none() && i < 12 {
+        if (_syndrome ^ GOLAY_B[i]).count_ones() <= 2 {
+            _error = Some((1 << (11 - i), _syndrome ^ GOLAY_B[i]));
+        }
+        i += 1;
+    }
+
+    let _syndrome_b = golay_mul_b(_syndrome);
+    if _error.is_none() && _syndrome_b.count_ones() <= 3 {
+        _error = Some((_syndrome_b, 0));
+    }
+    let mut i: usize = 0;
+    while _error.is_none() && i < 12 {
+        if (_syndrome_b ^ GOLAY_B[i]).count_ones() <= 2 {
+            _error = Some((_syndrome_b ^ GOLAY_B[i], 1 << (11 - i)));
+        }
+        i += 1;
+    }
+
+    match _error {
+        Some((_message_error, _parity_error)) => Some((
+            _message ^ _message_error,
+            (_message_error.count_ones() + _parity_error.count_ones()) as usize,
+        )),
+        None => None,
+    }
+}
+
+/// Nibble `_index` of `_data`, high nibble first.
+const fn nibble(_data: &[u8], _index: usize) -> u8 {
+    match _index % 2 {
+        0 => _data[_index / 2] >> 4,
+        _ => _data[_index / 2] & 0x0F,
+    }
+}
+
+const fn set_nibble(_data: &mut [u8], _index: usize, _value: u8) {
+    match _index % 2 {
+        0 => _data[_index / 2] = (_data[_index / 2] & 0x0F) | (_value << 4),
+        _ => _data[_index / 2] = (_data[_index / 2] & 0xF0) | (_value & 0x0F),
+    }
+}
+
+/// The extended binary Golay code (24,12): every 12 bits of data are sent as 24, of which any 3 can
+/// be flipped. Rate 1/2, and only a 12-entry table, so it suits small AVRs that cannot afford RS.
+///
+/// Each codeword is 3 bytes, message then parity. The last codeword is padded with zero nibbles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Golay24;
+
+impl Golay24 {
+    const fn codewords(_data_len: usize) -> usize {
+        (2 * _data_len).div_ceil(3)
+    }
+}
+
+impl Fec for Golay24 {
+    fn overhead(&self, _data_len: usize) -> usize {
+        3 * Self::codewords(_data_len) - _data_len
+    }
+
+    fn encode(&self, _data: &[u8], _output: &mut [u8]) {
+        assert_eq!(_output.len(), self.encoded_len(_data.len()));
+        let _nibbles = 2 * _data.len();
+        for k in 0..Self::codewords(_data.len()) {
+            let mut _message: u16 = 0;
+            for j in 3 * k..3 * k + 3 {
+                let _nibble = if j < _nibbles { nibble(_data, j) } else { 0 };
+                _message = (_message << 4) | _nibble as u16;
+            }
+            let _codeword = ((_message as u32) << 12) | golay_mul_b(_message) as u32;
+            _output[3 * k..3 * k + 3].copy_from_slice(&_codeword.to_be_bytes()[1..]);
+        }
+    }
+
+    fn decode(
+        &self,
+        _received: &[u8],
+        _erasures: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, DecodeError> {
+        check_lengths(self, _received, _output.len())?;
+        let _nibbles = 2 * _output.len();
+        let mut _corrected: usize = 0;
+        for k in 0..Self::codewords(_output.len()) {
+            let _codeword = u32::from_be_bytes([
+                0,
+                _received[3 * k],
+                _received[3 * k + 1],
+                _received[3 * k + 2],
+            ]);
+            let (_message, _count) =
+                golay_decode_word(_codeword).ok_or(DecodeError::Uncorrectable)?;
+            _corrected += _count;
+            for j in 3 * k..(3 * k + 3).min(_nibbles) {
+                set_nibble(_output, j, (_message >> (4 * (3 * k + 2 - j))) as u8 & 0x0F);
+            }
+        }
+        Ok(_corrected)
+    }
+}
+
+/// Hamming(7,4) codeword of a nibble. Bit `i` holds position `i` of the textbook code (parity at
+/// positions 1, 2 and 4, data at 3, 5, 6 and 7), so the syndrome is the position of a single error.
+const fn hamming_encode_nibble(_nibble: u8) -> u8 {
+    let mut output: u8 = ((_nibble >> 3) & 1) << 3
+        | ((_nibble >> 2) & 1) << 5
+        | ((_nibble >> 1) & 1) << 6
+        | (_nibble & 1) << 7;
+    // set the parity bits whose positions add up to the syndrome, cancelling it
+    let _syndrome = hamming_syndrome(output);
+    output |= (_syndrome & 0b001) << 1 | (_syndrome & 0b010) << 1 | (_syndrome & 0b100) << 2;
+    output
+}
+
+/// XOR of the positions of every set bit; zero for a valid codeword.
+const fn hamming_syndrome(_codeword: u8) -> u8 {
+    let mut _syndrome: u8 = 0;
+    let mut i: u8 = 1;
+    while i < 8 {
+        if _codeword & (1 << i) != 0 {
+            _syndrome ^= i;
+        }
+        i += 1;
+    }
+    _syndrome
+}
+
+/// The data nibble of a (corrected) codeword.
+const fn hamming_data_nibble(_codeword: u8) -> u8 {
+    ((_codeword >> 3) & 1) << 3
+        | ((_codeword >> 5) & 1) << 2
+        | ((_codeword >> 6) & 1) << 1
+        | ((_codeword >> 7) & 1)
+}
+
+const fn get_bit(_data: &[u8], _index: usize) -> bool {
+    _data[_index / 8] & (0x80 >> (_index % 8)) != 0
+}
+
+/// Hamming(7,4), one codeword per nibble, with the bits of every codeword in a packet interleaved:
+/// all the first bits, then all the second bits, and so on. A burst shorter than twice the data
+/// length then hits each codeword at most once, and is corrected in full.
+///
+/// The cheapest code here, but two errors in one codeword are miscorrected rather than detected,
+/// so pair it with [crate::telemetry::encode_packet_with_crc] or similar if that matters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InterleavedHamming74;
+
+impl Fec for InterleavedHamming74 {
+    fn overhead(&self, _data_len: usize) -> usize {
+        (7 * _data_len).div_ceil(4) - _data_len
+    }
+
+    fn encode(&self, _data: &[u8], _output: &mut [u8]) {
+        assert_eq!(_output.len(), self.encoded_len(_data.len()));
+        _output.fill(0);
+        let _codewords = 2 * _data.len();
+        for k in 0..7 * _codewords {
+            let _codeword = hamming_encode_nibble(nibble(_data, k % _codewords));
+            if _codeword & (1 << (k / _codewords + 1)) != 0 {
+                _output[k / 8] |= 0x80 >> (k % 8);
+            }
+        }
+    }
+
+    fn decode(
+        &self,
+        _received: &[u8],
+        _erasures: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, DecodeError> {
+        check_lengths(self, _received, _output.len())?;
+        let _codewords = 2 * _output.len();
+        let mut _corrected: usize = 0;
+        for i in 0.._codewords {
+            let mut _codeword: u8 = 0;
+            for j in 0..7 {
+                if get_bit(_received, j * _codewords + i) {
+                    _codeword |= 1 << (j + 1);
+                }
+            }
+            let _syndrome = hamming_syndrome(_codeword);
+            if _syndrome != 0 {
+                _codeword ^= 1 << _syndrome;
+                _corrected += 1;
+            }
+            set_nibble(_output, i, hamming_data_nibble(_codeword));
+        }
+        Ok(_corrected)
+    }
+}
+
+/// No FEC at all: the data is sent as-is, and nothing is corrected. For links that already have
+/// an outer code, or for testing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NoFec;
+
+impl Fec for NoFec {
+    fn overhead(&self, _data_len: usize) -> usize {
+        0
+    }
+
+    fn is_systematic(&self) -> bool {
+        true
+    }
+
+    fn max_erasures(&self, _data_len: usize) -> usize {
+        0
+    }
+
+    fn encode(&self, _data: &[u8], _output: &mut [u8]) {
+        _output.copy_from_slice(_data);
+    }
+
+    fn decode(
+        &self,
+        _received: &[u8],
+        _erasures: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, DecodeError> {
+        check_lengths(self, _received, _output.len())?;
+        if !_erasures.is_empty() {
+            return Err(DecodeError::TooManyErasures);
+        }
+        _output.copy_from_slice(_received);
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::make_packet_skeleton_nofec;
+
+    fn roundtrip<F: Fec>(_fec: &F, _damage: &[(usize, u8)]) -> Result<usize, DecodeError> {
+        let _packet = make_packet_skeleton_nofec(true);
+        let mut _encoded = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+        let _encoded = &mut _encoded[.._fec.encoded_len(_packet.len())];
+        _fec.encode(&_packet, _encoded);
+        for &(_index, _mask) in _damage {
+            _encoded[_index] ^= _mask;
+        }
+
+        let mut _decoded: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+        let _corrected = _fec.decode(_encoded, &[], &mut _decoded)?;
+        assert_eq!(_decoded, _packet);
+        Ok(_corrected)
+    }
+
+    #[test]
+    fn test_fec_overheads() {
+        assert_eq!(
+            DEFAULT_FEC.overhead(BARE_MESSAGE_LENGTH_BYTES),
+            FEC_EXTRA_BYTES
+        );
+        assert_eq!(
+            DEFAULT_FEC.encoded_len(BARE_MESSAGE_LENGTH_BYTES),
+            TOTAL_MESSAGE_LENGTH_BYTES
+        );
+        // 160 nibbles make 54 codewords of 3 bytes
+        assert_eq!(Golay24.encoded_len(BARE_MESSAGE_LENGTH_BYTES), 162);
+        // 160 codewords of 7 bits
+        assert_eq!(
+            InterleavedHamming74.encoded_len(BARE_MESSAGE_LENGTH_BYTES),
+            140
+        );
+        assert_eq!(
+            NoFec.encoded_len(BARE_MESSAGE_LENGTH_BYTES),
+            BARE_MESSAGE_LENGTH_BYTES
+        );
+    }
+
+    #[test]
+    fn test_fec_corrects() {
+        assert_eq!(roundtrip(&DEFAULT_FEC, &[(3, 0xFF), (40, 0x01)]), Ok(2));
+
+        // 3 bits in one codeword, and 1 in another
+        assert_eq!(
+            roundtrip(&Golay24, &[(0, 0x81), (2, 0x10), (5, 0x01)]),
+            Ok(4)
+        );
+        // 4 bits in one codeword are beyond it
+        assert_eq!(
+            roundtrip(&Golay24, &[(0, 0x81), (2, 0x11)]),
+            Err(DecodeError::Uncorrectable)
+        );
+
+        // a 16-byte burst is 128 bits, one per codeword
+        let _burst: [(usize, u8); 16] = core::array::from_fn(|i| (40 + i, 0xFF));
+        assert_eq!(roundtrip(&InterleavedHamming74, &_burst), Ok(128));
+
+        assert_eq!(roundtrip(&NoFec, &[]), Ok(0));
+    }
+
+    #[test]
+    fn test_hamming_single_errors() {
+        for _nibble in 0..16u8 {
+            let _codeword = hamming_encode_nibble(_nibble);
+            assert_eq!(hamming_syndrome(_codeword), 0);
+            assert_eq!(hamming_data_nibble(_codeword), _nibble);
+            for _position in 1..8 {
+                let _damaged = _codeword ^ (1 << _position);
+                assert_eq!(hamming_syndrome(_damaged), _position);
+            }
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use crate::fec::{Fec, DEFAULT_FEC};
+use crate::parameters::*;
+use crate::telemetry::{encode_packet, DecodeError};
+
+/// Bytes of each fragment taken up by [START_HEADER_DATA] and [FRAGMENT_MARKER].
+const FRAGMENT_PREFIX_SIZE: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE;
+/// Bytes of each fragment taken up by its transfer ID, index, count and payload length.
+const FRAGMENT_HEADER_SIZE: usize = 4;
+
+/// Payload bytes carried by each fragment.
+pub const FRAGMENT_PAYLOAD_SIZE: usize =
+    BARE_MESSAGE_LENGTH_BYTES - FRAGMENT_PREFIX_SIZE - FRAGMENT_HEADER_SIZE;
+
+/// Most fragments a single transfer can be split into; this is what fits in the reassembly bitmap.
+pub const MAX_FRAGMENTS: usize = u32::BITS as usize;
+
+/// Largest payload that can be sent as fragments.
+pub const MAX_FRAGMENTED_PAYLOAD: usize = FIGURES_FRAME_SIZE;
+
+const _: () = assert!(
+    MAX_FRAGMENTED_PAYLOAD <= MAX_FRAGMENTS * FRAGMENT_PAYLOAD_SIZE,
+    "MAX_FRAGMENTED_PAYLOAD_too_large"
+);
+
+/// Returns `true` if a received packet is a fragment rather than a telemetry packet, going by
+/// whichever of [FRAGMENT_MARKER] and [BLOCK_DELIMITER] is closer to what follows the header.
+pub const fn is_fragment(_packet: &TotalMessage) -> bool {
+    has_fragment_marker(_packet)
+}
+
+/// Like [is_fragment], for a packet of which only the start has been received so far. `false` if
+/// the marker has not arrived yet.
+pub const fn has_fragment_marker(_packet: &[u8]) -> bool {
+    if _packet.len() < START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE {
+        return false;
+    }
+    let _marker = u16::from_le_bytes([
+        _packet[START_HEADER_DATA.len()],
+        _packet[START_HEADER_DATA.len() + 1],
+    ]);
+    (_marker ^ FRAGMENT_MARKER).count_ones() < (_marker ^ BLOCK_DELIMITER).count_ones()
+}
+
+/// One numbered piece of a larger payload.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    /// Identifies which transfer this fragment belongs to, so that fragments of back-to-back
+    /// transfers are not mixed up.
+    pub transfer_id: u8,
+    pub index: u8,
+    pub count: u8,
+    payload: [u8; FRAGMENT_PAYLOAD_SIZE],
+    payload_len: u8,
+}
+
+impl Fragment {
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_len as usize]
+    }
+
+    /// Builds this fragment into a packet, without FEC.
+    pub fn to_bare_message(&self) -> BareMessage {
+        let mut output: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+        let mut i: usize = 0;
+        output[i..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
+        i += START_HEADER_DATA.len();
+        output[i..i + BLOCK_DELIMITER_SIZE].copy_from_slice(&FRAGMENT_MARKER.to_le_bytes());
+        i += BLOCK_DELIMITER_SIZE;
+        output[i] = self.transfer_id;
+        output[i + 1] = self.index;
+        output[i + 2] = self.count;
+        output[i + 3] = self.payload_len;
+        i += FRAGMENT_HEADER_SIZE;
+        output[i..].copy_from_slice(&self.payload);
+        output
+    }
+
+    fn from_bare_message(_packet: &BareMessage) -> Result<Self, DecodeError> {
+        let i = FRAGMENT_PREFIX_SIZE;
+        let output = Self {
+            transfer_id: _packet[i],
+            index: _packet[i + 1],
+            count: _packet[i + 2],
+            payload: _packet[i + FRAGMENT_HEADER_SIZE..].try_into().unwrap(),
+            payload_len: _packet[i + 3],
+        };
+
+        if output.count == 0
+            || output.count as usize > MAX_FRAGMENTS
+            || output.index >= output.count
+            || output.payload_len as usize > FRAGMENT_PAYLOAD_SIZE
+        {
+            return Err(DecodeError::BadLength);
+        }
+        Ok(output)
+    }
+}
+
+/// Corrects a received fragment packet and parses it.
+///
+/// Only [START_HEADER_DATA] and [FRAGMENT_MARKER] are restored before correction; the rest of a
+/// fragment has no fixed skeleton.
+pub fn decode_fragment(_packet: &[u8]) -> Result<Fragment, DecodeError> {
+    if _packet.len() != TOTAL_MESSAGE_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut _packet_data_full: TotalMessage = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+    _packet_data_full.copy_from_slice(_packet);
+    _packet_data_full[..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
+    _packet_data_full[START_HEADER_DATA.len()..FRAGMENT_PREFIX_SIZE]
+        .copy_from_slice(&FRAGMENT_MARKER.to_le_bytes());
+
+    let mut recovered_packet: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+    DEFAULT_FEC
+        .decode(&_packet_data_full, &[], &mut recovered_packet)
+        .map_err(|_| DecodeError::Uncorrectable)?;
+    Fragment::from_bare_message(&recovered_packet)
+}
+
+/// Splits a payload of up to [MAX_FRAGMENTED_PAYLOAD] bytes into fragments, each encoded with its
+/// own FEC into a [TotalMessage].
+pub struct Fragmenter<'a> {
+    data: &'a [u8],
+    transfer_id: u8,
+    next: u8,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// Returns `None` if `_data` is longer than [MAX_FRAGMENTED_PAYLOAD], rather than sending only
+    /// part of it.
+    pub fn new(_data: &'a [u8], _transfer_id: u8) -> Option<Self> {
+        if _data.len() > MAX_FRAGMENTED_PAYLOAD {
+            return None;
+        }
+        Some(Self {
+            data: _data,
+            transfer_id: _transfer_id,
+            next: 0,
+        })
+    }
+
+    /// Number of fragments the payload is split into. An empty payload is still sent as one fragment.
+    pub fn fragment_count(&self) -> u8 {
+        self.data.len().div_ceil(FRAGMENT_PAYLOAD_SIZE).max(1) as u8
+    }
+
+    /// Returns fragment `_index`, without FEC.
+    pub fn fragment(&self, _index: u8) -> Fragment {
+        let _start = (_index as usize * FRAGMENT_PAYLOAD_SIZE).min(self.data.len());
+        let _end = (_start + FRAGMENT_PAYLOAD_SIZE).min(self.data.len());
+        let mut _payload = [0u8; FRAGMENT_PAYLOAD_SIZE];
+        _payload[.._end - _start].copy_from_slice(&self.data[_start.._end]);
+        Fragment {
+            transfer_id: self.transfer_id,
+            index: _index,
+            count: self.fragment_count(),
+            payload: _payload,
+            payload_len: (_end - _start) as u8,
+        }
+    }
+}
+
+impl<'a> Iterator for Fragmenter<'a> {
+    type Item = TotalMessage;
+
+    fn next(&mut self) -> Option<TotalMessage> {
+        if self.next >= self.fragment_count() {
+            return None;
+        }
+        let _fragment = self.fragment(self.next);
+        self.next += 1;
+        Some(encode_packet(&_fragment.to_bare_message()))
+    }
+}
+
+/// What happened to the transfer a fragment was given to [Reassembler::push].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReassemblyStatus {
+    /// Still waiting for the fragments in [Reassembler::missing].
+    Incomplete,
+    /// Every fragment has arrived; the payload is available from [Reassembler::payload].
+    Complete,
+    /// This fragment was already received.
+    Duplicate,
+    /// The fragment does not agree with the rest of its transfer (e.g. a different count).
+    Inconsistent,
+}
+
+/// Ground-side reassembly of fragments into a payload of up to [MAX_FRAGMENTED_PAYLOAD] bytes.
+///
+/// Time is whatever monotonic tick the caller counts in (seconds, packets received, ...). A
+/// transfer not completed within `timeout` ticks of its first fragment is abandoned.
+#[derive(Debug, Copy, Clone)]
+pub struct Reassembler {
+    buffer: FiguresFrameArray,
+    transfer_id: Option<u8>,
+    count: u8,
+    received: u32,
+    payload_len: usize,
+    started_at: u32,
+    timeout: u32,
+}
+
+impl Reassembler {
+    pub const fn new(_timeout: u32) -> Self {
+        Self {
+            buffer: [0u8; FIGURES_FRAME_SIZE],
+            transfer_id: None,
+            count: 0,
+            received: 0,
+            payload_len: 0,
+            started_at: 0,
+            timeout: _timeout,
+        }
+    }
+
+    /// The transfer currently being reassembled, if any.
+    pub const fn transfer_id(&self) -> Option<u8> {
+        self.transfer_id
+    }
+
+    /// Bitmap of received fragments; bit `n` is set once fragment `n` has arrived.
+    pub const fn received(&self) -> u32 {
+        self.received
+    }
+
+    /// Bitmap of fragments still missing from the current transfer.
+    pub const fn missing(&self) -> u32 {
+        let _all: u32 = if self.count as usize >= MAX_FRAGMENTS {
+            u32::MAX
+        } else {
+            (1u32 << self.count) - 1
+        };
+        _all & !self.received
+    }
+
+    pub const fn is_complete(&self) -> bool {
+        self.transfer_id.is_some() && self.missing() == 0
+    }
+
+    /// Returns `true` if the current transfer has gone `timeout` ticks without completing.
+    pub const fn is_timed_out(&self, _now: u32) -> bool {
+        self.transfer_id.is_some()
+            && !self.is_complete()
+            && _now.wrapping_sub(self.started_at) > self.timeout
+    }
+
+    /// The reassembled payload, once every fragment has arrived.
+    pub fn payload(&self) -> Option<&[u8]> {
+        match self.is_complete() {
+            true => Some(&self.buffer[..self.payload_len]),
+            false => None,
+        }
+    }
+
+    /// Abandons the current transfer.
+    pub const fn reset(&mut self) {
+        self.transfer_id = None;
+        self.count = 0;
+        self.received = 0;
+        self.payload_len = 0;
+    }
+
+    /// Adds a fragment at time `_now`. A fragment from a different transfer, or arriving `timeout`
+    /// ticks after the current transfer started, starts a new transfer. Until then a resent
+    /// fragment of a completed transfer is a [ReassemblyStatus::Duplicate]; a transfer ID reused
+    /// sooner, e.g. after a reboot, needs a [Reassembler::reset] first.
+    pub fn push(&mut self, _fragment: &Fragment, _now: u32) -> ReassemblyStatus {
+        if self.transfer_id != Some(_fragment.transfer_id)
+            || _now.wrapping_sub(self.started_at) > self.timeout
+        {
+            self.reset();
+            self.transfer_id = Some(_fragment.transfer_id);
+            self.count = _fragment.count;
+            self.started_at = _now;
+        }
+
+        if _fragment.count != self.count || _fragment.index >= self.count {
+            return ReassemblyStatus::Inconsistent;
+        }
+
+        let _bit = 1u32 << _fragment.index;
+        if self.received & _bit != 0 {
+            return ReassemblyStatus::Duplicate;
+        }
+
+        let _start = _fragment.index as usize * FRAGMENT_PAYLOAD_SIZE;
+        let _end = _start + _fragment.payload().len();
+        let _is_last = _fragment.index + 1 == self.count;
+        if _end > FIGURES_FRAME_SIZE
+            || (!_is_last && _fragment.payload().len() != FRAGMENT_PAYLOAD_SIZE)
+        {
+            return ReassemblyStatus::Inconsistent;
+        }
+
+        self.buffer[_start.._end].copy_from_slice(_fragment.payload());
+        self.received |= _bit;
+        if _is_last {
+            self.payload_len = _end;
+        }
+
+        match self.is_complete() {
+            true => ReassemblyStatus::Complete,
+            false => ReassemblyStatus::Incomplete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::make_packet_skeleton;
+
+    const PAYLOAD_LEN: usize = 300;
+
+    fn make_payload() -> [u8; PAYLOAD_LEN] {
+        let mut _payload = [0u8; PAYLOAD_LEN];
+        for i in 0..PAYLOAD_LEN {
+            _payload[i] = (i * 7) as u8;
+        }
+        _payload
+    }
+
+    #[test]
+    fn test_fragment_roundtrip() {
+        let _payload = make_payload();
+        let _fragmenter = Fragmenter::new(&_payload, 42).unwrap();
+        let _count = _fragmenter.fragment_count();
+        assert_eq!(_count as usize, PAYLOAD_LEN.div_ceil(FRAGMENT_PAYLOAD_SIZE));
+
+        let mut _packets = [[0u8; TOTAL_MESSAGE_LENGTH_BYTES]; 8];
+        for (i, _packet) in _fragmenter.enumerate() {
+            _packets[i] = _packet;
+        }
+
+        let mut _reassembler = Reassembler::new(100);
+        // deliver out of order, with damage, and leave one out
+        for i in (1.._count as usize).rev() {
+            let mut _packet = _packets[i];
+            assert!(is_fragment(&_packet));
+            _packet[20] ^= 0xFF;
+            _packet[START_HEADER_DATA.len()] ^= 0x01;
+            let _fragment = decode_fragment(&_packet).unwrap();
+            assert_eq!(
+                _reassembler.push(&_fragment, 10),
+                ReassemblyStatus::Incomplete
+            );
+        }
+        assert_eq!(_reassembler.missing(), 0b1);
+        assert!(_reassembler.payload().is_none());
+
+        let _fragment = decode_fragment(&_packets[2]).unwrap();
+        assert_eq!(
+            _reassembler.push(&_fragment, 11),
+            ReassemblyStatus::Duplicate
+        );
+
+        let _fragment = decode_fragment(&_packets[0]).unwrap();
+        assert_eq!(
+            _reassembler.push(&_fragment, 12),
+            ReassemblyStatus::Complete
+        );
+        assert_eq!(_reassembler.payload(), Some(&_payload[..]));
+    }
+
+    #[test]
+    fn test_reassembly_timeout() {
+        let _payload = make_payload();
+        let _fragmenter = Fragmenter::new(&_payload, 7).unwrap();
+        let mut _reassembler = Reassembler::new(100);
+
+        _reassembler.push(&_fragmenter.fragment(0), 1000);
+        _reassembler.push(&_fragmenter.fragment(1), 1050);
+        assert!(!_reassembler.is_timed_out(1100));
+        assert!(_reassembler.is_timed_out(1101));
+
+        // a late fragment starts over rather than completing a stale transfer
+        assert_eq!(
+            _reassembler.push(&_fragmenter.fragment(2), 1200),
+            ReassemblyStatus::Incomplete
+        );
+        assert_eq!(_reassembler.received(), 0b100);
+
+        // as does a fragment from another transfer
+        let _other = Fragmenter::new(&_payload[..10], 8).unwrap();
+        assert_eq!(
+            _reassembler.push(&_other.fragment(0), 1201),
+            ReassemblyStatus::Complete
+        );
+        assert_eq!(_reassembler.payload(), Some(&_payload[..10]));
+    }
+
+    #[test]
+    fn test_reassembly_reused_id() {
+        let _payload = make_payload();
+        let _first = Fragmenter::new(&_payload[..10], 5).unwrap();
+        let mut _reassembler = Reassembler::new(100);
+        assert_eq!(
+            _reassembler.push(&_first.fragment(0), 1),
+            ReassemblyStatus::Complete
+        );
+
+        // the flight sends the same fragment again; the payload is kept
+        assert_eq!(
+            _reassembler.push(&_first.fragment(0), 2),
+            ReassemblyStatus::Duplicate
+        );
+        assert_eq!(_reassembler.payload(), Some(&_payload[..10]));
+
+        // a new transfer under the same ID is taken once the old one has aged out
+        let _second = Fragmenter::new(&_payload[20..], 5).unwrap();
+        assert_eq!(
+            _reassembler.push(&_second.fragment(0), 50),
+            ReassemblyStatus::Inconsistent
+        );
+        for i in 0.._second.fragment_count() {
+            let _status = _reassembler.push(&_second.fragment(i), 102);
+            assert_ne!(_status, ReassemblyStatus::Duplicate);
+            assert_ne!(_status, ReassemblyStatus::Inconsistent);
+        }
+        assert_eq!(_reassembler.payload(), Some(&_payload[20..]));
+
+        // or straight away after a reset, e.g. when the flight is known to have rebooted
+        _reassembler.reset();
+        assert_eq!(
+            _reassembler.push(&_first.fragment(0), 103),
+            ReassemblyStatus::Complete
+        );
+        assert_eq!(_reassembler.payload(), Some(&_payload[..10]));
+    }
+
+    #[test]
+    fn test_fragmenter_too_long() {
+        let _payload = [0u8; MAX_FRAGMENTED_PAYLOAD + 1];
+        assert!(Fragmenter::new(&_payload, 1).is_none());
+        assert!(Fragmenter::new(&_payload[..MAX_FRAGMENTED_PAYLOAD], 1).is_some());
+    }
+
+    #[test]
+    fn test_is_fragment() {
+        assert!(!is_fragment(&make_packet_skeleton(true)));
+        assert!(!is_fragment(&make_packet_skeleton(false)));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::linecode::{EncodedBits, LineCode, Symbol, Symbols};
+use crate::parameters::*;
+use crate::whitening::{WhitenedBits, Whitening};
+
+/// Entries in [SINE_TABLE]: one full cycle, indexed by the top 8 bits of a 32-bit phase.
+pub const SINE_TABLE_SIZE: usize = 256;
+const SINE_TABLE_BITS: u32 = SINE_TABLE_SIZE.trailing_zeros();
+
+/// pi in Q30 fixed point.
+const PI_Q30: i64 = 3_373_259_426;
+
+/// sin(_quarter * pi / 128) for `_quarter` in `0..=64`, in Q30, from its Taylor series. Integer
+/// only, so the table is built at compile time without floating point.
+const fn sin_q30(_quarter: usize) -> i64 {
+    let x = _quarter as i64 * PI_Q30 / (SINE_TABLE_SIZE as i64 / 2);
+    let x2 = (x * x) >> 30;
+    let mut _term = x;
+    let mut _sum = x;
+    let mut k: i64 = 1;
+    while k <= 6 {
+        _term = -(_term * x2 >> 30) / ((2 * k) * (2 * k + 1));
+        _sum += _term;
+        k += 1;
+    }
+    _sum
+}
+
+const fn make_sine_table() -> [i16; SINE_TABLE_SIZE] {
+    let _quarter = SINE_TABLE_SIZE / 4;
+    let mut output = [0i16; SINE_TABLE_SIZE];
+    let mut i: usize = 0;
+    while i < SINE_TABLE_SIZE {
+        let _index = i % _quarter;
+        let _magnitude = match (i / _quarter) % 2 {
+            0 => sin_q30(_index),
+            _ => sin_q30(_quarter - _index),
+        };
+        let _value = ((_magnitude * i16::MAX as i64 + (1 << 29)) >> 30) as i16;
+        output[i] = if i / (2 * _quarter) == 0 {
+            _value
+        } else {
+            -_value
+        };
+        i += 1;
+    }
+    output
+}
+
+/// One cycle of a full-scale sine wave.
+pub const SINE_TABLE: [i16; SINE_TABLE_SIZE] = make_sine_table();
+
+/// Tones, timing and whitening of an FSK link. All integers, so that nothing needs floating point at
+/// run time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FskConfig {
+    pub mark_hz: u32,
+    pub space_hz: u32,
+    /// Symbols per second.
+    pub baud: u32,
+    pub sample_rate: u32,
+    /// Applied to each packet by [modulate_packet], and undone by a
+    /// [FrameSynchronizer](crate::sync::FrameSynchronizer) made with the same [Whitening].
+    pub whitening: Whitening,
+}
+
+impl FskConfig {
+    /// The flight link: [FSK_MARK_FREQUENCY] and [FSK_SPACE_FREQUENCY] at [BAUDRATE], sampled at
+    /// [FSK_SAMPLE_RATE].
+    pub const DEFAULT: Self = Self {
+        mark_hz: FSK_MARK_FREQUENCY,
+        space_hz: FSK_SPACE_FREQUENCY,
+        baud: BAUDRATE as u32,
+        sample_rate: FSK_SAMPLE_RATE,
+        whitening: Whitening::None,
+    };
+
+    pub const fn tone_hz(&self, _symbol: Symbol) -> u32 {
+        match _symbol {
+            true => self.mark_hz,
+            false => self.space_hz,
+        }
+    }
+
+    /// Phase step per sample for `_frequency`, with a full cycle being 2^32.
+    pub const fn phase_increment(&self, _frequency: u32) -> u32 {
+        (((_frequency as u64) << 32) / self.sample_rate as u64) as u32
+    }
+
+    /// Length of one symbol, in microseconds.
+    pub const fn symbol_period_us(&self) -> u32 {
+        1_000_000 / self.baud
+    }
+}
+
+/// Phase-continuous 2-FSK modulator. Yields one signed sample per call, for a DAC or the host;
+/// [FskModulator::next_pwm] gives the same sample as an 8-bit PWM duty cycle.
+///
+/// The phase is never reset when the tone changes, so there are no clicks at symbol boundaries.
+/// Symbol timing is kept with an integer accumulator, so that sample rates that are not a multiple
+/// of the baud rate do not drift.
+#[derive(Debug, Clone)]
+pub struct FskModulator<I: Iterator<Item = Symbol>> {
+    config: FskConfig,
+    symbols: I,
+    mark_increment: u32,
+    space_increment: u32,
+    increment: u32,
+    phase: u32,
+    symbol_clock: u32,
+    done: bool,
+}
+
+impl<I: Iterator<Item = Symbol>> FskModulator<I> {
+    pub fn new(_config: FskConfig, mut _symbols: I) -> Self {
+        let mark_increment = _config.phase_increment(_config.mark_hz);
+        let space_increment = _config.phase_increment(_config.space_hz);
+        let (increment, done) = match _symbols.next() {
+            Some(true) => (mark_increment, false),
+            Some(false) => (space_increment, false),
+            None => (0, true),
+        };
+        Self {
+            config: _config,
+            symbols: _symbols,
+            mark_increment,
+            space_increment,
+            increment,
+            phase: 0,
+            symbol_clock: 0,
+            done,
+        }
+    }
+
+    pub const fn config(&self) -> &FskConfig {
+        &self.config
+    }
+
+    /// The next sample as an unsigned 8-bit PWM value, centred on 128.
+    pub fn next_pwm(&mut self) -> Option<u8> {
+        self.next()
+            .map(|_sample| ((_sample >> 8) as i8 as u8) ^ 0x80)
+    }
+
+    /// Fills `_output` with samples, returning how many were written. Fewer than `_output.len()`
+    /// means the message has ended.
+    pub fn modulate_into(&mut self, _output: &mut [i16]) -> usize {
+        let mut _written: usize = 0;
+        for _slot in _output.iter_mut() {
+            match self.next() {
+                Some(_sample) => *_slot = _sample,
+                None => break,
+            }
+            _written += 1;
+        }
+        _written
+    }
+}
+
+impl<I: Iterator<Item = Symbol>> Iterator for FskModulator<I> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.done {
+            return None;
+        }
+
+        let _sample = SINE_TABLE[(self.phase >> (32 - SINE_TABLE_BITS)) as usize];
+        self.phase = self.phase.wrapping_add(self.increment);
+
+        self.symbol_clock += self.config.baud;
+        if self.symbol_clock >= self.config.sample_rate {
+            self.symbol_clock -= self.config.sample_rate;
+            match self.symbols.next() {
+                Some(true) => self.increment = self.mark_increment,
+                Some(false) => self.increment = self.space_increment,
+                None => self.done = true,
+            }
+        }
+        Some(_sample)
+    }
+}
+
+/// Bits of alternating padding sent around a packet by [modulate_padded]: `0x55` bytes.
+const PADDING_BYTE_BITS: usize = 8;
+
+/// Bits of a packet as the 2-FSK link sends it, made by [modulate_padded].
+#[derive(Debug, Clone)]
+pub struct PacketBits<'a> {
+    /// Padding bits still to send before the packet.
+    preamble: usize,
+    /// [LineCode::Nrz] symbols are the bits themselves.
+    packet: WhitenedBits<Symbols<'a>>,
+    /// Padding bits still to send after the packet.
+    tail: usize,
+}
+
+impl<'a> Iterator for PacketBits<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.preamble > 0 {
+            self.preamble -= 1;
+            return Some(self.preamble & 1 == 0);
+        }
+        if let Some(_bit) = self.packet.next() {
+            return Some(_bit);
+        }
+        if self.tail > 0 {
+            self.tail -= 1;
+            return Some(self.tail & 1 == 0);
+        }
+        None
+    }
+}
+
+/// Samples of one packet, made by [modulate_packet] or [modulate_padded].
+pub type PacketModulator<'a> = FskModulator<EncodedBits<PacketBits<'a>>>;
+
+/// Modulates `_packet` with [LineCode::Nrz], as the 2-FSK link sends it, whitened with
+/// [FskConfig::whitening].
+pub fn modulate_packet(_config: FskConfig, _packet: &[u8]) -> PacketModulator<'_> {
+    modulate_padded(_config, 0, _packet, 0)
+}
+
+/// Like [modulate_packet], with `_preamble` bytes of alternating bits before the packet, for the
+/// receiver's clock to settle, and `_tail` bytes after it, so its last bit is not cut short. The
+/// padding is not whitened, so the whitening starts on the packet's first bit, as the receiver
+/// expects.
+pub fn modulate_padded(
+    _config: FskConfig,
+    _preamble: usize,
+    _packet: &[u8],
+    _tail: usize,
+) -> PacketModulator<'_> {
+    let _bits = PacketBits {
+        preamble: _preamble * PADDING_BYTE_BITS,
+        packet: _config
+            .whitening
+            .whiten_bits(LineCode::Nrz.symbols(_packet)),
+        tail: _tail * PADDING_BYTE_BITS,
+    };
+    FskModulator::new(_config, LineCode::Nrz.encode_bits(_bits))
+}
+
+/// A tone to key up, for radios that switch between two programmed tones (or a timer that toggles
+/// a pin) instead of playing samples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ToneSwitch {
+    pub frequency_hz: u32,
+    /// How long to hold the tone. Runs of equal symbols are merged into one switch.
+    pub duration_us: u32,
+}
+
+/// Iterator over the [ToneSwitch]es of a symbol stream.
+#[derive(Debug, Clone)]
+pub struct ToneSwitches<I: Iterator<Item = Symbol>> {
+    config: FskConfig,
+    symbols: core::iter::Peekable<I>,
+}
+
+impl<I: Iterator<Item = Symbol>> ToneSwitches<I> {
+    pub fn new(_config: FskConfig, _symbols: I) -> Self {
+        Self {
+            config: _config,
+            symbols: _symbols.peekable(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Symbol>> Iterator for ToneSwitches<I> {
+    type Item = ToneSwitch;
+
+    fn next(&mut self) -> Option<ToneSwitch> {
+        let _symbol = self.symbols.next()?;
+        let mut _run: u32 = 1;
+        while self.symbols.next_if_eq(&_symbol).is_some() {
+            _run += 1;
+        }
+        Some(ToneSwitch {
+            frequency_hz: self.config.tone_hz(_symbol),
+            duration_us: _run * self.config.symbol_period_us(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::make_packet_skeleton;
+
+    #[test]
+    fn test_sine_table() {
+        assert_eq!(SINE_TABLE[0], 0);
+        assert_eq!(SINE_TABLE[64], i16::MAX);
+        assert_eq!(SINE_TABLE[128], 0);
+        assert_eq!(SINE_TABLE[192], -i16::MAX);
+        // sin(pi / 4) * 32767
+        assert!((SINE_TABLE[32] - 23170).abs() <= 1);
+        assert_eq!(SINE_TABLE[10], -SINE_TABLE[138]);
+    }
+
+    #[test]
+    fn test_fsk_modulator() {
+        let _config = FskConfig {
+            mark_hz: 1200,
+            space_hz: 2200,
+            baud: 1200,
+            sample_rate: 9600,
+            whitening: Whitening::None,
+        };
+        let _packet = make_packet_skeleton(true);
+        let mut _samples = [0i16; TOTAL_MESSAGE_LENGTH_BYTES * 8 * 8 + 1];
+        let _len = modulate_packet(_config, &_packet).modulate_into(&mut _samples);
+        assert_eq!(_len, TOTAL_MESSAGE_LENGTH_BYTES * 8 * 8);
+
+        // phase-continuous: no sample jumps further than the faster tone can move in one step,
+        // which is a chord of 2 * sin(step / 2)
+        let _max_step = 2 * SINE_TABLE[(_config.phase_increment(2200) >> 25) as usize + 1] as i32;
+        for _pair in _samples[.._len].windows(2) {
+            assert!((_pair[1] as i32 - _pair[0] as i32).abs() <= _max_step);
+        }
+
+        let mut _pwm = modulate_packet(_config, &_packet);
+        assert_eq!(_pwm.next_pwm(), Some(0x80));
+    }
+
+    #[test]
+    fn test_tone_switches() {
+        let _config = FskConfig::DEFAULT;
+        let _switches: [ToneSwitch; 4] = [
+            ToneSwitch {
+                frequency_hz: _config.mark_hz,
+                duration_us: _config.symbol_period_us(),
+            },
+            ToneSwitch {
+                frequency_hz: _config.space_hz,
+                duration_us: _config.symbol_period_us(),
+            },
+            ToneSwitch {
+                frequency_hz: _config.mark_hz,
+                duration_us: 2 * _config.symbol_period_us(),
+            },
+            ToneSwitch {
+                frequency_hz: _config.space_hz,
+                duration_us: 4 * _config.symbol_period_us(),
+            },
+        ];
+        assert!(ToneSwitches::new(_config, LineCode::Nrz.symbols(&[0xB0])).eq(_switches));
+    }
+}
@@ -0,0 +1,481 @@
+use crate::parameters::APRS_INFO_FIELD_MAX;
+use crate::tnc::KissBytes;
+use crc::{Crc, NoTable, CRC_16_IBM_SDLC};
+
+/// Opens and closes every HDLC frame, and fills the gaps between them.
+pub const HDLC_FLAG: u8 = 0x7E;
+/// A sixth 1 after five in a row can only be part of a flag (or an abort), so the sender stuffs a 0
+/// after every five 1s of frame data.
+const MAX_ONES_RUN: u8 = 5;
+
+/// The AX.25 frame check sequence: CRC-16/X-25, sent low byte first.
+const AX25_FCS: Crc<NoTable<u16>> = Crc::<NoTable<u16>>::new(&CRC_16_IBM_SDLC);
+pub const FCS_SIZE: usize = 2;
+
+pub fn ax25_fcs(_frame: &[u8]) -> u16 {
+    AX25_FCS.checksum(_frame)
+}
+
+/// Iterates over the bits of one HDLC frame on the air, before NRZI: `preamble_flags` flags, the
+/// frame and its FCS (least significant bit first, bit-stuffed), then `tail_flags` flags.
+/// At least one flag is always sent on each side.
+#[derive(Debug, Clone)]
+pub struct HdlcEncoder<'a> {
+    frame: &'a [u8],
+    fcs: [u8; FCS_SIZE],
+    preamble_bits: usize,
+    /// Index into the frame followed by the FCS, in bits.
+    data_bit: usize,
+    tail_bits: usize,
+    ones_run: u8,
+}
+
+impl<'a> HdlcEncoder<'a> {
+    pub fn new(_frame: &'a [u8], _preamble_flags: usize, _tail_flags: usize) -> Self {
+        Self {
+            frame: _frame,
+            fcs: ax25_fcs(_frame).to_le_bytes(),
+            preamble_bits: _preamble_flags.max(1) * 8,
+            data_bit: 0,
+            tail_bits: _tail_flags.max(1) * 8,
+            ones_run: 0,
+        }
+    }
+
+    fn data_byte(&self, _index: usize) -> u8 {
+        match _index.checked_sub(self.frame.len()) {
+            None => self.frame[_index],
+            Some(_fcs_index) => self.fcs[_fcs_index],
+        }
+    }
+}
+
+/// Bit `_index` of a run of flags, least significant first.
+const fn flag_bit(_index: usize) -> bool {
+    (HDLC_FLAG >> (_index % 8)) & 1 != 0
+}
+
+impl<'a> Iterator for HdlcEncoder<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.preamble_bits > 0 {
+            self.preamble_bits -= 1;
+            return Some(flag_bit(7 - self.preamble_bits % 8));
+        }
+
+        if self.data_bit < (self.frame.len() + FCS_SIZE) * 8 {
+            if self.ones_run == MAX_ONES_RUN {
+                self.ones_run = 0;
+                return Some(false);
+            }
+            let _bit = (self.data_byte(self.data_bit / 8) >> (self.data_bit % 8)) & 1 != 0;
+            self.data_bit += 1;
+            self.ones_run = if _bit { self.ones_run + 1 } else { 0 };
+            return Some(_bit);
+        }
+        // a stuffed bit still owed after the last data bit
+        if self.ones_run == MAX_ONES_RUN {
+            self.ones_run = 0;
+            return Some(false);
+        }
+
+        if self.tail_bits > 0 {
+            self.tail_bits -= 1;
+            return Some(flag_bit(7 - self.tail_bits % 8));
+        }
+        None
+    }
+}
+
+/// Bytes in one AX.25 address: six shifted callsign characters and the SSID byte.
+pub const AX25_ADDRESS_LEN: usize = 7;
+/// Destination, source and up to eight digipeaters.
+const AX25_MAX_ADDRESSES: usize = 10;
+/// Longest frame (without FCS) that [HdlcDecoder] keeps: every address, control, PID and a full
+/// information field.
+pub const AX25_MAX_FRAME_LEN: usize =
+    AX25_MAX_ADDRESSES * AX25_ADDRESS_LEN + 2 + APRS_INFO_FIELD_MAX;
+/// Anything shorter than two addresses and a control byte is noise between flags.
+const AX25_MIN_FRAME_LEN: usize = 2 * AX25_ADDRESS_LEN + 1;
+
+/// Marks the last address of a frame, in the lowest bit of its SSID byte.
+const ADDRESS_END_BIT: u8 = 0x01;
+/// The H bit of a digipeater address (the C bit for source and destination).
+const ADDRESS_REPEATED_BIT: u8 = 0x80;
+/// The two reserved bits of the SSID byte, sent as ones.
+const ADDRESS_RESERVED_BITS: u8 = 0x60;
+
+/// One address field of an AX.25 frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ax25Address {
+    /// Upper case and padded with spaces, as on the air (but not shifted).
+    pub callsign: [u8; 6],
+    pub ssid: u8,
+    /// For a digipeater, whether it has already repeated the frame.
+    pub repeated: bool,
+}
+
+impl Ax25Address {
+    pub const fn from_bytes(_bytes: &[u8; AX25_ADDRESS_LEN]) -> Self {
+        let mut _callsign = [0u8; 6];
+        let mut i: usize = 0;
+        while i < _callsign.len() {
+            _callsign[i] = _bytes[i] >> 1;
+            i += 1;
+        }
+        Self {
+            callsign: _callsign,
+            ssid: (_bytes[6] >> 1) & 0x0F,
+            repeated: _bytes[6] & ADDRESS_REPEATED_BIT != 0,
+        }
+    }
+
+    /// `_last` is set on the final address of the frame.
+    pub const fn to_bytes(&self, _last: bool) -> [u8; AX25_ADDRESS_LEN] {
+        let mut _bytes = [0u8; AX25_ADDRESS_LEN];
+        let mut i: usize = 0;
+        while i < self.callsign.len() {
+            _bytes[i] = self.callsign[i] << 1;
+            i += 1;
+        }
+        _bytes[6] = ADDRESS_RESERVED_BITS | (self.ssid & 0x0F) << 1;
+        if self.repeated {
+            _bytes[6] |= ADDRESS_REPEATED_BIT;
+        }
+        if _last {
+            _bytes[6] |= ADDRESS_END_BIT;
+        }
+        _bytes
+    }
+}
+
+/// A received AX.25 frame, from the first address through the information field, with its FCS
+/// already checked and removed.
+#[derive(Clone, Copy)]
+pub struct Ax25Frame {
+    data: [u8; AX25_MAX_FRAME_LEN],
+    len: usize,
+}
+
+impl Ax25Frame {
+    /// Returns `None` if `_bytes` is longer than [AX25_MAX_FRAME_LEN].
+    pub fn from_bytes(_bytes: &[u8]) -> Option<Self> {
+        if _bytes.len() > AX25_MAX_FRAME_LEN {
+            return None;
+        }
+        let mut _frame = Self {
+            data: [0u8; AX25_MAX_FRAME_LEN],
+            len: _bytes.len(),
+        };
+        _frame.data[.._bytes.len()].copy_from_slice(_bytes);
+        Some(_frame)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Number of addresses, up to and including the one with the end bit set. Zero if no
+    /// address ends within the frame.
+    pub fn address_count(&self) -> usize {
+        self.as_bytes()
+            .chunks_exact(AX25_ADDRESS_LEN)
+            .take(AX25_MAX_ADDRESSES)
+            .position(|_address| _address[6] & ADDRESS_END_BIT != 0)
+            .map_or(0, |_index| _index + 1)
+    }
+
+    pub fn address(&self, _index: usize) -> Option<Ax25Address> {
+        if _index >= self.address_count() {
+            return None;
+        }
+        let _start = _index * AX25_ADDRESS_LEN;
+        let _bytes = self.data[_start.._start + AX25_ADDRESS_LEN]
+            .try_into()
+            .unwrap();
+        Some(Ax25Address::from_bytes(_bytes))
+    }
+
+    pub fn destination(&self) -> Option<Ax25Address> {
+        self.address(0)
+    }
+
+    pub fn source(&self) -> Option<Ax25Address> {
+        self.address(1)
+    }
+
+    /// The digipeater path, in order. A digipeated copy has `repeated` set on each hop so far.
+    pub fn digipeaters(&self) -> impl Iterator<Item = Ax25Address> + '_ {
+        (2..self.address_count()).filter_map(|_index| self.address(_index))
+    }
+
+    /// Everything after the control and PID bytes, which for the UI frames APRS uses is the
+    /// information field.
+    pub fn information(&self) -> &[u8] {
+        let _start = self.address_count() * AX25_ADDRESS_LEN + 2;
+        self.as_bytes().get(_start..).unwrap_or(&[])
+    }
+
+    /// The frame as a KISS `SendDataFrame`, ready for a host. Escaped as it goes, so even an
+    /// [AX25_MAX_FRAME_LEN] frame of nothing but FENDs needs no buffer.
+    pub fn kiss_bytes(&self, _port: u8) -> KissBytes<'_> {
+        KissBytes::data_frame(_port, self.as_bytes())
+    }
+}
+
+/// The receiving end of [HdlcEncoder]: takes bits after NRZI decoding, finds the flags, removes
+/// the stuffed zeros and yields every frame whose FCS checks out.
+#[derive(Clone, Copy)]
+pub struct HdlcDecoder {
+    buffer: [u8; AX25_MAX_FRAME_LEN + FCS_SIZE],
+    len: usize,
+    /// Set after a flag, cleared by an abort or a frame too long for the buffer.
+    in_frame: bool,
+    /// The last eight bits received, the newest in the top bit.
+    pattern: u8,
+    ones_run: u8,
+    byte: u8,
+    bit_count: u8,
+    fcs_errors: u32,
+}
+
+impl HdlcDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0u8; AX25_MAX_FRAME_LEN + FCS_SIZE],
+            len: 0,
+            in_frame: false,
+            pattern: 0,
+            ones_run: 0,
+            byte: 0,
+            bit_count: 0,
+            fcs_errors: 0,
+        }
+    }
+
+    /// Frames long enough to be real that were dropped for a bad FCS.
+    pub const fn fcs_errors(&self) -> u32 {
+        self.fcs_errors
+    }
+
+    pub fn push_bit(&mut self, _bit: bool) -> Option<Ax25Frame> {
+        self.pattern = (self.pattern >> 1) | ((_bit as u8) << 7);
+        if self.pattern == HDLC_FLAG {
+            let _frame = self.finish_frame();
+            self.in_frame = true;
+            self.len = 0;
+            self.bit_count = 0;
+            self.ones_run = 0;
+            return _frame;
+        }
+
+        if _bit {
+            self.ones_run = self.ones_run.saturating_add(1);
+            // seven 1s in a row abort the frame
+            if self.ones_run > MAX_ONES_RUN + 1 {
+                self.in_frame = false;
+            }
+        } else {
+            let _stuffed = self.ones_run == MAX_ONES_RUN;
+            self.ones_run = 0;
+            if _stuffed {
+                return None;
+            }
+        }
+
+        if self.in_frame {
+            self.byte = (self.byte >> 1) | ((_bit as u8) << 7);
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                if self.len == self.buffer.len() {
+                    self.in_frame = false;
+                } else {
+                    self.buffer[self.len] = self.byte;
+                    self.len += 1;
+                }
+            }
+        }
+        None
+    }
+
+    fn finish_frame(&mut self) -> Option<Ax25Frame> {
+        // the first seven bits of the closing flag went in as data, so a frame that was a whole
+        // number of bytes is left with seven over
+        if !self.in_frame || self.bit_count != 7 || self.len < AX25_MIN_FRAME_LEN + FCS_SIZE {
+            return None;
+        }
+        let _len = self.len - FCS_SIZE;
+        let _fcs = u16::from_le_bytes([self.buffer[_len], self.buffer[_len + 1]]);
+        if ax25_fcs(&self.buffer[.._len]) != _fcs {
+            self.fcs_errors += 1;
+            return None;
+        }
+        Ax25Frame::from_bytes(&self.buffer[.._len])
+    }
+}
+
+impl Default for HdlcDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::MAX_KISS_FRAME_SIZE;
+
+    #[test]
+    fn test_ax25_fcs() {
+        // the CRC-16/X-25 check value
+        assert_eq!(ax25_fcs(b"123456789"), 0x906E);
+    }
+
+    #[test]
+    fn test_hdlc_stuffing() {
+        let _frame = [0xFFu8, 0x01];
+        let mut _bits = HdlcEncoder::new(&_frame, 1, 1);
+
+        let _flag: [bool; 8] = [false, true, true, true, true, true, true, false];
+        assert!(_bits.by_ref().take(8).eq(_flag));
+        // 0xFF gets a 0 after its fifth bit; 0x01 starts with the 1 that follows the last three
+        let _data: [bool; 17] = [
+            true, true, true, true, true, false, true, true, true, true, false, false, false,
+            false, false, false, false,
+        ];
+        assert!(_bits.by_ref().take(17).eq(_data));
+
+        // no more than five 1s in a row until the closing flag, which is the only six
+        let mut _ones_run = 0;
+        let mut _longest_run = 0;
+        let mut _remaining = 0;
+        for _bit in _bits {
+            _ones_run = if _bit { _ones_run + 1 } else { 0 };
+            _longest_run = _longest_run.max(_ones_run);
+            _remaining += 1;
+        }
+        assert_eq!(_longest_run, 6);
+        assert!(_remaining >= FCS_SIZE * 8 + 8);
+    }
+
+    const UI_FRAME_LEN: usize = 3 * AX25_ADDRESS_LEN + 2 + 5;
+
+    /// A UI frame from `KD9TFA-11` to `APZNEX`, via `WIDE1-1` (already repeated).
+    fn ui_frame() -> ([u8; UI_FRAME_LEN], [Ax25Address; 3]) {
+        let _addresses = [
+            Ax25Address {
+                callsign: *b"APZNEX",
+                ssid: 0,
+                repeated: false,
+            },
+            Ax25Address {
+                callsign: *b"KD9TFA",
+                ssid: 11,
+                repeated: false,
+            },
+            Ax25Address {
+                callsign: *b"WIDE1 ",
+                ssid: 1,
+                repeated: true,
+            },
+        ];
+        let mut _frame = [0u8; UI_FRAME_LEN];
+        for (_index, _address) in _addresses.iter().enumerate() {
+            let _start = _index * AX25_ADDRESS_LEN;
+            _frame[_start.._start + AX25_ADDRESS_LEN]
+                .copy_from_slice(&_address.to_bytes(_index == _addresses.len() - 1));
+        }
+        let _start = _addresses.len() * AX25_ADDRESS_LEN;
+        _frame[_start.._start + 2].copy_from_slice(&[0x03, 0xF0]);
+        _frame[_start + 2.._start + 7].copy_from_slice(b">\x7E~\xFF!");
+        (_frame, _addresses)
+    }
+
+    #[test]
+    fn test_hdlc_roundtrip() {
+        let (_frame, _addresses) = ui_frame();
+        let _frame = &_frame[..];
+        let mut _decoder = HdlcDecoder::new();
+
+        // two frames back to back, sharing nothing but idle flags
+        let mut _received = HdlcEncoder::new(_frame, 3, 1)
+            .chain(HdlcEncoder::new(_frame, 1, 2))
+            .filter_map(|_bit| _decoder.push_bit(_bit));
+        for _ in 0..2 {
+            let _decoded = _received.next().unwrap();
+            assert_eq!(_decoded.as_bytes(), _frame);
+            assert_eq!(_decoded.address_count(), 3);
+            assert_eq!(_decoded.destination(), Some(_addresses[0]));
+            assert_eq!(_decoded.source(), Some(_addresses[1]));
+            assert!(_decoded.digipeaters().eq([_addresses[2]]));
+            assert_eq!(_decoded.information(), b">\x7E~\xFF!");
+        }
+        assert!(_received.next().is_none());
+        assert_eq!(_decoder.fcs_errors(), 0);
+    }
+
+    #[test]
+    fn test_hdlc_errors() {
+        let (_frame, _) = ui_frame();
+        let _frame = &_frame[..];
+        let mut _decoder = HdlcDecoder::new();
+
+        // one flipped bit in the destination address, well clear of any stuffing
+        let _corrupted = HdlcEncoder::new(_frame, 1, 1)
+            .enumerate()
+            .map(|_bit| _bit.1 != (_bit.0 == 8 + 8 * 2 + 1))
+            .filter_map(|_bit| _decoder.push_bit(_bit))
+            .count();
+        assert_eq!(_corrupted, 0);
+        assert_eq!(_decoder.fcs_errors(), 1);
+
+        // an abort part way through, then the frame again
+        let _aborted = HdlcEncoder::new(_frame, 1, 1)
+            .take(100)
+            .chain([true; 7])
+            .chain(HdlcEncoder::new(_frame, 1, 1))
+            .filter_map(|_bit| _decoder.push_bit(_bit))
+            .count();
+        assert_eq!(_aborted, 1);
+        assert_eq!(_decoder.fcs_errors(), 1);
+    }
+
+    #[test]
+    fn test_kiss_max_length_frame() {
+        // a full frame of nothing but bytes KISS has to escape
+        let (_ui_frame, _) = ui_frame();
+        let mut _frame = [0xC0u8; AX25_MAX_FRAME_LEN];
+        _frame[..UI_FRAME_LEN - 5].copy_from_slice(&_ui_frame[..UI_FRAME_LEN - 5]);
+        for i in (UI_FRAME_LEN - 5.._frame.len()).step_by(2) {
+            _frame[i] = 0xDB;
+        }
+        let mut _decoder = HdlcDecoder::new();
+        let _received = HdlcEncoder::new(&_frame, 1, 1)
+            .find_map(|_bit| _decoder.push_bit(_bit))
+            .unwrap();
+        assert_eq!(_received.as_bytes(), _frame);
+
+        let mut _kiss = _received.kiss_bytes(0);
+        assert_eq!(_kiss.next(), Some(0xC0));
+        assert_eq!(_kiss.next(), Some(0x00));
+        let mut _unescaped: usize = 0;
+        while let Some(_byte) = _kiss.next() {
+            let _byte = match _byte {
+                0xC0 => break,
+                0xDB => match _kiss.next() {
+                    Some(0xDC) => 0xC0,
+                    Some(0xDD) => 0xDB,
+                    _ => panic!("bad escape"),
+                },
+                _ => _byte,
+            };
+            assert_eq!(_byte, _frame[_unescaped]);
+            _unescaped += 1;
+        }
+        assert_eq!(_unescaped, AX25_MAX_FRAME_LEN);
+        assert!(_kiss.next().is_none());
+        assert!(_received.kiss_bytes(0).count() > 2 * MAX_KISS_FRAME_SIZE);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::telemetry::ErasureList;
+
+/// Position of byte `_byte` of packet `_packet` within an interleaved window of depth `_depth`.
+///
+/// Windows are sent column by column: byte 0 of every packet, then byte 1 of every packet, and so
+/// on. A burst of `B` consecutive bytes therefore lands as at most `B / _depth` (rounded up) errors
+/// in each packet.
+pub const fn interleaved_index(_packet: usize, _byte: usize, _depth: usize) -> usize {
+    _byte * _depth + _packet
+}
+
+/// Inverse of [interleaved_index]: returns `(packet, byte)` for a position in the window.
+pub const fn deinterleaved_index(_index: usize, _depth: usize) -> (usize, usize) {
+    (_index % _depth, _index / _depth)
+}
+
+/// Flight-side block interleaver. Collects `DEPTH` packets of `LEN` bytes each in a static buffer,
+/// then hands out the interleaved window one byte at a time.
+///
+/// `LEN` will usually be [crate::parameters::TOTAL_MESSAGE_LENGTH_BYTES] or
+/// [crate::qpacket::QPACKET_FULL_LEN].
+#[derive(Debug, Copy, Clone)]
+pub struct Interleaver<const LEN: usize, const DEPTH: usize> {
+    packets: [[u8; LEN]; DEPTH],
+    filled: usize,
+}
+
+impl<const LEN: usize, const DEPTH: usize> Interleaver<LEN, DEPTH> {
+    /// Length of one interleaved window, in bytes.
+    pub const WINDOW_LEN: usize = LEN * DEPTH;
+
+    const _DEPTH_CHECK: () = assert!(DEPTH > 0, "interleaver depth must be nonzero");
+
+    pub const fn new() -> Self {
+        let _ = Self::_DEPTH_CHECK;
+        Self {
+            packets: [[0u8; LEN]; DEPTH],
+            filled: 0,
+        }
+    }
+
+    /// Adds the next packet to the window. Returns `true` once the window holds `DEPTH` packets.
+    ///
+    /// Packets pushed into an already-full window are dropped; call [Interleaver::clear] once the
+    /// window has been sent.
+    pub const fn push_packet(&mut self, _packet: &[u8; LEN]) -> bool {
+        if self.filled < DEPTH {
+            self.packets[self.filled] = *_packet;
+            self.filled += 1;
+        }
+        self.is_full()
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.filled == DEPTH
+    }
+
+    /// Returns byte `_index` of the interleaved window. Cheap enough to call from a transmit interrupt.
+    pub const fn byte_at(&self, _index: usize) -> u8 {
+        let (_packet, _byte) = deinterleaved_index(_index, DEPTH);
+        self.packets[_packet][_byte]
+    }
+
+    /// Writes the whole interleaved window into `_output`, which must be [Self::WINDOW_LEN] long.
+    pub fn write_window(&self, _output: &mut [u8]) {
+        debug_assert_eq!(_output.len(), Self::WINDOW_LEN);
+        for i in 0..Self::WINDOW_LEN {
+            _output[i] = self.byte_at(i);
+        }
+    }
+
+    pub const fn clear(&mut self) {
+        self.filled = 0;
+    }
+}
+
+/// Ground-side counterpart to [Interleaver]. Bytes are fed in window order; bytes that never
+/// arrived (a dropout, a lost packet's airtime, or the end of a truncated window) are tracked and
+/// handed back as erasures for each packet.
+#[derive(Debug, Copy, Clone)]
+pub struct Deinterleaver<const LEN: usize, const DEPTH: usize> {
+    packets: [[u8; LEN]; DEPTH],
+    received: [[bool; LEN]; DEPTH],
+    position: usize,
+}
+
+impl<const LEN: usize, const DEPTH: usize> Deinterleaver<LEN, DEPTH> {
+    pub const WINDOW_LEN: usize = LEN * DEPTH;
+
+    const _DEPTH_CHECK: () = assert!(DEPTH > 0, "interleaver depth must be nonzero");
+    const _LEN_CHECK: () = assert!(LEN <= 256, "erasure positions must fit in a u8");
+
+    pub const fn new() -> Self {
+        let _ = Self::_DEPTH_CHECK;
+        let _ = Self::_LEN_CHECK;
+        Self {
+            packets: [[0u8; LEN]; DEPTH],
+            received: [[false; LEN]; DEPTH],
+            position: 0,
+        }
+    }
+
+    /// Stores `_byte` at window position `_index`. Out-of-window positions are ignored.
+    pub const fn push_at(&mut self, _index: usize, _byte: u8) {
+        if _index < Self::WINDOW_LEN {
+            let (_packet, _offset) = deinterleaved_index(_index, DEPTH);
+            self.packets[_packet][_offset] = _byte;
+            self.received[_packet][_offset] = true;
+        }
+    }
+
+    /// Stores the next byte of the window. Returns `true` once the window is complete.
+    pub const fn push_byte(&mut self, _byte: u8) -> bool {
+        self.push_at(self.position, _byte);
+        self.position += 1;
+        self.is_complete()
+    }
+
+    /// Skips `_count` bytes that were lost. Returns `true` once the window is complete.
+    pub const fn skip(&mut self, _count: usize) -> bool {
+        self.position += _count;
+        self.is_complete()
+    }
+
+    pub const fn is_complete(&self) -> bool {
+        self.position >= Self::WINDOW_LEN
+    }
+
+    /// Returns packet `_packet` of the window. Missing bytes are zero.
+    pub const fn packet(&self, _packet: usize) -> &[u8; LEN] {
+        &self.packets[_packet]
+    }
+
+    /// Number of bytes of packet `_packet` that never arrived.
+    pub const fn missing(&self, _packet: usize) -> usize {
+        let mut _missing: usize = 0;
+        let mut i: usize = 0;
+        while i < LEN {
+            if !self.received[_packet][i] {
+                _missing += 1;
+            }
+            i += 1;
+        }
+        _missing
+    }
+
+    /// Erasure list for packet `_packet`, suitable for [crate::telemetry::decode_packet].
+    /// Returns [None] if more bytes are missing than fit in `N`, as the packet cannot be recovered.
+    pub fn erasures<const N: usize>(&self, _packet: usize) -> Option<ErasureList<N>> {
+        let mut _erasures: ErasureList<N> = ErasureList::new();
+        for i in 0..LEN {
+            if !self.received[_packet][i] && !_erasures.push(i as u8) {
+                return None;
+            }
+        }
+        Some(_erasures)
+    }
+
+    pub const fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::*;
+    use crate::telemetry::{construct_blocks, construct_packet, decode_packet, encode_packet};
+
+    const DEPTH: usize = 4;
+
+    fn make_window() -> [TotalMessage; DEPTH] {
+        let mut _packets = [[0u8; TOTAL_MESSAGE_LENGTH_BYTES]; DEPTH];
+        for i in 0..DEPTH {
+            let _value = (i as f32 + 1.0f32) * 1000.5f32;
+            _packets[i] = encode_packet(&construct_packet(construct_blocks(
+                &[_value.to_be_bytes(); BLOCK_STACK_DATA_COUNT],
+            )));
+        }
+        _packets
+    }
+
+    #[test]
+    fn test_interleave_roundtrip() {
+        let _packets = make_window();
+        let mut _interleaver: Interleaver<TOTAL_MESSAGE_LENGTH_BYTES, DEPTH> = Interleaver::new();
+        for _packet in &_packets {
+            _interleaver.push_packet(_packet);
+        }
+        assert!(_interleaver.is_full());
+
+        let mut _window = [0u8; TOTAL_MESSAGE_LENGTH_BYTES * DEPTH];
+        _interleaver.write_window(&mut _window);
+        assert_eq!(_window[1], _packets[1][0]);
+        assert_eq!(_window[DEPTH], _packets[0][1]);
+
+        // a burst far longer than one packet's FEC could handle
+        for i in 40..60 {
+            _window[i] ^= 0xA5;
+        }
+
+        let mut _deinterleaver: Deinterleaver<TOTAL_MESSAGE_LENGTH_BYTES, DEPTH> =
+            Deinterleaver::new();
+        for _byte in _window {
+            _deinterleaver.push_byte(_byte);
+        }
+        assert!(_deinterleaver.is_complete());
+
+        for i in 0..DEPTH {
+            let (_decoded, _report) = decode_packet(_deinterleaver.packet(i), &[]).unwrap();
+            assert_eq!(_decoded, _packets[i][..BARE_MESSAGE_LENGTH_BYTES]);
+            assert_eq!(_report.corrected, 5);
+        }
+    }
+
+    #[test]
+    fn test_deinterleave_missing() {
+        let _packets = make_window();
+        let mut _interleaver: Interleaver<TOTAL_MESSAGE_LENGTH_BYTES, DEPTH> = Interleaver::new();
+        for _packet in &_packets {
+            _interleaver.push_packet(_packet);
+        }
+
+        let mut _deinterleaver: Deinterleaver<TOTAL_MESSAGE_LENGTH_BYTES, DEPTH> =
+            Deinterleaver::new();
+        let mut i: usize = 0;
+        while i < Deinterleaver::<TOTAL_MESSAGE_LENGTH_BYTES, DEPTH>::WINDOW_LEN {
+            // lose a packet's worth of airtime in the middle of the window
+            if i == 100 {
+                _deinterleaver.skip(36);
+                i += 36;
+                continue;
+            }
+            _deinterleaver.push_byte(_interleaver.byte_at(i));
+            i += 1;
+        }
+
+        for i in 0..DEPTH {
+            assert_eq!(_deinterleaver.missing(i), 9);
+            let _erasures: ErasureList = _deinterleaver.erasures(i).unwrap();
+            let (_decoded, _) =
+                decode_packet(_deinterleaver.packet(i), _erasures.as_slice()).unwrap();
+            assert_eq!(_decoded, _packets[i][..BARE_MESSAGE_LENGTH_BYTES]);
+        }
+
+        assert!(_deinterleaver.erasures::<8>(0).is_none());
+    }
+}
@@ -0,0 +1,382 @@
+use crate::auth::{
+    decode_signed_packet, AuthTrailer, AUTHENTICATED_MESSAGE_LENGTH_BYTES, AUTH_TRAILER_SIZE,
+};
+use crate::delta::{decode_delta_packet, is_delta, DeltaPacket};
+use crate::fragment::{decode_fragment, is_fragment, Fragment};
+use crate::parameters::*;
+use crate::qpacket::{decode_qpacket, BareQPacket, QPACKET_BARE_LEN, QPACKET_FULL_LEN};
+use crate::telemetry::{
+    decode_checked_packet, decode_packet, decode_profiled_packet, payload_crc, CorrectionReport,
+    DecodeError, PayloadIntegrity, CHECKED_MESSAGE_LENGTH_BYTES, PAYLOAD_CRC_SIZE,
+};
+
+/// What a receiver needs to know about a [PacketLayout] before decoding it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LayoutInfo {
+    pub layout: PacketLayout,
+    /// Length on air, including FEC.
+    pub total_len: usize,
+    /// Length once FEC is removed.
+    pub bare_len: usize,
+}
+
+/// Every layout this receiver can decode, indexed in [PACKET_LAYOUTS] order.
+pub const LAYOUT_REGISTRY: [LayoutInfo; PACKET_LAYOUTS.len()] = [
+    LayoutInfo {
+        layout: PacketLayout::Telemetry,
+        total_len: TOTAL_MESSAGE_LENGTH_BYTES,
+        bare_len: BARE_MESSAGE_LENGTH_BYTES,
+    },
+    LayoutInfo {
+        layout: PacketLayout::QPacket,
+        total_len: QPACKET_FULL_LEN,
+        bare_len: QPACKET_BARE_LEN,
+    },
+    profiled_layout_info(FecProfile::Light),
+    profiled_layout_info(FecProfile::Standard),
+    profiled_layout_info(FecProfile::Heavy),
+    LayoutInfo {
+        layout: PacketLayout::AuthenticatedTelemetry,
+        total_len: AUTHENTICATED_MESSAGE_LENGTH_BYTES,
+        bare_len: BARE_MESSAGE_LENGTH_BYTES + AUTH_TRAILER_SIZE,
+    },
+    LayoutInfo {
+        layout: PacketLayout::CheckedTelemetry,
+        total_len: CHECKED_MESSAGE_LENGTH_BYTES,
+        bare_len: BARE_MESSAGE_LENGTH_BYTES + PAYLOAD_CRC_SIZE,
+    },
+];
+
+const fn profiled_layout_info(_profile: FecProfile) -> LayoutInfo {
+    LayoutInfo {
+        layout: PacketLayout::ProfiledTelemetry(_profile),
+        total_len: _profile.total_len(),
+        bare_len: BARE_MESSAGE_LENGTH_BYTES + FEC_PROFILE_TAG_SIZE,
+    }
+}
+
+/// Length on air of the longest layout in [LAYOUT_REGISTRY].
+pub const MAX_LAYOUT_TOTAL_LEN: usize = {
+    let mut _max: usize = 0;
+    let mut i: usize = 0;
+    while i < LAYOUT_REGISTRY.len() {
+        if LAYOUT_REGISTRY[i].total_len > _max {
+            _max = LAYOUT_REGISTRY[i].total_len;
+        }
+        i += 1;
+    }
+    _max
+};
+
+const _: () = {
+    let mut i: usize = 0;
+    while i < PACKET_LAYOUTS.len() {
+        assert!(
+            LAYOUT_REGISTRY[i].layout.id() == PACKET_LAYOUTS[i].id(),
+            "LAYOUT_REGISTRY_out_of_order"
+        );
+        i += 1;
+    }
+};
+
+pub const fn layout_info(_layout: PacketLayout) -> &'static LayoutInfo {
+    let mut i: usize = 0;
+    while i < LAYOUT_REGISTRY.len() {
+        if LAYOUT_REGISTRY[i].layout.id() == _layout.id() {
+            return &LAYOUT_REGISTRY[i];
+        }
+        i += 1;
+    }
+    unreachable!()
+}
+
+/// Reads the layout ID of a received (still encoded) packet, and checks that the packet is as
+/// long as that layout should be.
+pub fn identify_layout(_packet: &[u8]) -> Result<PacketLayout, DecodeError> {
+    if _packet.len() < LAYOUT_HEADER_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+    let _layout = PacketLayout::from_id(_packet[START_HEADER_DATA.len()])
+        .ok_or(DecodeError::UnknownLayout)?;
+    if _packet.len() != layout_info(_layout).total_len {
+        return Err(DecodeError::BadLength);
+    }
+    Ok(_layout)
+}
+
+/// A packet decoded by [decode_any_packet].
+///
+/// The telemetry variants all carry a [BareMessage] with the [PacketLayout::Telemetry] ID, whichever
+/// layout it was sent as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodedPacket {
+    Telemetry(BareMessage, CorrectionReport),
+    QPacket(BareQPacket),
+    Fragment(Fragment),
+    Delta(DeltaPacket),
+    ProfiledTelemetry(BareMessage, FecProfile, CorrectionReport),
+    /// The trailer is not checked here, as that needs the key; see
+    /// [AuthVerifier::check](crate::auth::AuthVerifier::check).
+    AuthenticatedTelemetry(BareMessage, AuthTrailer, CorrectionReport),
+    CheckedTelemetry(BareMessage, PayloadIntegrity, CorrectionReport),
+}
+
+/// Decodes a packet of any known layout, as well as fragments and delta packets, for receivers
+/// that hear payloads running different firmware.
+///
+/// Packets whose layout ID is not in [LAYOUT_REGISTRY] fail with [DecodeError::UnknownLayout]
+/// rather than being parsed as the wrong layout.
+pub fn decode_any_packet(_packet: &[u8]) -> Result<DecodedPacket, DecodeError> {
+    if is_delta(_packet) {
+        return decode_delta_packet(_packet).map(DecodedPacket::Delta);
+    }
+    if let Ok(_total_message) = <&TotalMessage>::try_from(_packet) {
+        if is_fragment(_total_message) {
+            return decode_fragment(_packet).map(DecodedPacket::Fragment);
+        }
+    }
+
+    match identify_layout(_packet)? {
+        PacketLayout::Telemetry => decode_packet(_packet, &[])
+            .map(|(_bare_packet, _report)| DecodedPacket::Telemetry(_bare_packet, _report)),
+        PacketLayout::QPacket => decode_qpacket(_packet).map(DecodedPacket::QPacket),
+        PacketLayout::ProfiledTelemetry(_) => {
+            decode_profiled_packet(_packet, &[]).map(|(_bare_packet, _profile, _report)| {
+                DecodedPacket::ProfiledTelemetry(_bare_packet, _profile, _report)
+            })
+        }
+        PacketLayout::AuthenticatedTelemetry => {
+            decode_signed_packet(_packet).map(|(_bare_packet, _trailer, _report)| {
+                DecodedPacket::AuthenticatedTelemetry(_bare_packet, _trailer, _report)
+            })
+        }
+        PacketLayout::CheckedTelemetry => {
+            decode_checked_packet(_packet, &[]).map(|(_bare_packet, _integrity, _report)| {
+                DecodedPacket::CheckedTelemetry(_bare_packet, _integrity, _report)
+            })
+        }
+    }
+}
+
+/// Reads a packet sent without FEC, such as one from
+/// [crate::telemetry::TelemetryEncoder::generate_packet_no_fec], which is exactly as long as its
+/// layout's [LayoutInfo::bare_len]. Nothing can be corrected, so a telemetry packet always comes
+/// with an empty [CorrectionReport].
+pub fn decode_bare_packet(_packet: &[u8]) -> Result<DecodedPacket, DecodeError> {
+    if _packet.len() < LAYOUT_HEADER_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+    if _packet[..START_HEADER_DATA.len()] != START_HEADER_DATA {
+        return Err(DecodeError::BadHeader);
+    }
+    let _layout = PacketLayout::from_id(_packet[START_HEADER_DATA.len()])
+        .ok_or(DecodeError::UnknownLayout)?;
+    if _packet.len() != layout_info(_layout).bare_len {
+        return Err(DecodeError::BadLength);
+    }
+    Ok(match _layout {
+        PacketLayout::Telemetry => {
+            DecodedPacket::Telemetry(_packet.try_into().unwrap(), CorrectionReport::new())
+        }
+        PacketLayout::QPacket => DecodedPacket::QPacket(_packet.try_into().unwrap()),
+        PacketLayout::ProfiledTelemetry(_profile) => DecodedPacket::ProfiledTelemetry(
+            bare_telemetry(_packet),
+            _profile,
+            CorrectionReport::new(),
+        ),
+        PacketLayout::AuthenticatedTelemetry => DecodedPacket::AuthenticatedTelemetry(
+            bare_telemetry(_packet),
+            _packet[BARE_MESSAGE_LENGTH_BYTES..].try_into().unwrap(),
+            CorrectionReport::new(),
+        ),
+        PacketLayout::CheckedTelemetry => {
+            let _bare_packet = bare_telemetry(_packet);
+            let _integrity = if _packet[BARE_MESSAGE_LENGTH_BYTES..]
+                == payload_crc(&_bare_packet).to_be_bytes()
+            {
+                PayloadIntegrity::Verified
+            } else {
+                PayloadIntegrity::CrcMismatch
+            };
+            DecodedPacket::CheckedTelemetry(_bare_packet, _integrity, CorrectionReport::new())
+        }
+    })
+}
+
+/// The [BareMessage] at the start of a bare telemetry packet of another layout, with its ID put back
+/// to [PacketLayout::Telemetry].
+fn bare_telemetry(_packet: &[u8]) -> BareMessage {
+    let mut output: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+    output.copy_from_slice(&_packet[..BARE_MESSAGE_LENGTH_BYTES]);
+    output[START_HEADER_DATA.len()] = PacketLayout::Telemetry.id();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthVerifier, Authenticator};
+    use crate::delta::encode_delta_packet;
+    use crate::fragment::Fragmenter;
+    use crate::qpacket::{QPacketEncoder, USER_DATA_BLOCKS};
+    use crate::telemetry::{
+        encode_packet, encode_packet_with_crc, make_packet_skeleton, make_packet_skeleton_nofec,
+        make_packet_skeleton_with_profile,
+    };
+
+    #[test]
+    fn test_decode_mixed_traffic() {
+        let _telemetry = make_packet_skeleton(true);
+        let _qpacket = QPacketEncoder::new().encode_values(1_400_000_000, &[0.0; USER_DATA_BLOCKS]);
+        let _fragment = Fragmenter::new(b"hello", 3).unwrap().next().unwrap();
+        let _keyframe = make_packet_skeleton_nofec(false);
+        let _delta = encode_delta_packet(&DeltaPacket::new(
+            &_keyframe,
+            &make_packet_skeleton_nofec(true),
+        ));
+
+        assert_eq!(identify_layout(&_telemetry), Ok(PacketLayout::Telemetry));
+        assert_eq!(identify_layout(&_qpacket), Ok(PacketLayout::QPacket));
+
+        assert!(matches!(
+            decode_any_packet(&_telemetry),
+            Ok(DecodedPacket::Telemetry(_, _))
+        ));
+        assert_eq!(
+            decode_any_packet(&_qpacket),
+            Ok(DecodedPacket::QPacket(decode_qpacket(&_qpacket).unwrap()))
+        );
+        assert!(matches!(
+            decode_any_packet(&_fragment),
+            Ok(DecodedPacket::Fragment(_))
+        ));
+        assert!(matches!(
+            decode_any_packet(_delta.as_bytes()),
+            Ok(DecodedPacket::Delta(_))
+        ));
+
+        // telemetry sent with a profile, a signature or a CRC comes back as plain telemetry
+        let _heavy = make_packet_skeleton_with_profile(true, FecProfile::Heavy);
+        assert_eq!(
+            identify_layout(_heavy.as_bytes()),
+            Ok(PacketLayout::ProfiledTelemetry(FecProfile::Heavy))
+        );
+        assert!(matches!(
+            decode_any_packet(_heavy.as_bytes()),
+            Ok(DecodedPacket::ProfiledTelemetry(_packet, FecProfile::Heavy, _))
+                if _packet == make_packet_skeleton_nofec(true)
+        ));
+        let _key = [0x42; 16];
+        let _signed = Authenticator::new(_key, 7).sign_packet(&_keyframe);
+        match decode_any_packet(&_signed) {
+            Ok(DecodedPacket::AuthenticatedTelemetry(_packet, _trailer, _)) => {
+                assert_eq!(_packet, _keyframe);
+                assert!(AuthVerifier::new(_key)
+                    .check(&_packet, &_trailer)
+                    .is_authentic());
+            }
+            _other => panic!("{:?}", _other),
+        }
+        assert!(matches!(
+            decode_any_packet(&encode_packet_with_crc(&_keyframe)),
+            Ok(DecodedPacket::CheckedTelemetry(_packet, PayloadIntegrity::Verified, _))
+                if _packet == _keyframe
+        ));
+
+        // a layout ID three bits away from anything known
+        let mut _unknown = _telemetry;
+        _unknown[START_HEADER_DATA.len()] ^= 0b0000_0111;
+        assert_eq!(
+            decode_any_packet(&_unknown),
+            Err(DecodeError::UnknownLayout)
+        );
+
+        // a qpacket's header on a packet of telemetry length is not parsed as telemetry
+        let mut _mislabelled = encode_packet(&make_packet_skeleton_nofec(true));
+        _mislabelled[START_HEADER_DATA.len()] = PacketLayout::QPacket.id();
+        assert_eq!(identify_layout(&_mislabelled), Err(DecodeError::BadLength));
+        assert_eq!(
+            decode_packet(&_mislabelled, &[]).map(|_| ()),
+            Err(DecodeError::UnknownLayout)
+        );
+    }
+
+    #[test]
+    fn test_layout_id_bit_errors() {
+        let _telemetry = make_packet_skeleton(false);
+        let _qpacket = QPacketEncoder::new().encode_values(1_400_000_000, &[0.0; USER_DATA_BLOCKS]);
+        let _id = START_HEADER_DATA.len();
+
+        let mut _damaged_telemetry = _telemetry;
+        _damaged_telemetry[_id] ^= 0b0001_0000;
+        assert_eq!(
+            identify_layout(&_damaged_telemetry),
+            Ok(PacketLayout::Telemetry)
+        );
+        match decode_any_packet(&_damaged_telemetry) {
+            Ok(DecodedPacket::Telemetry(_bare_packet, _report)) => {
+                assert_eq!(_bare_packet, _telemetry[..BARE_MESSAGE_LENGTH_BYTES]);
+                assert_eq!(_report.positions(), [_id as u8]);
+            }
+            _other => panic!("{:?}", _other),
+        }
+
+        let mut _damaged_qpacket = _qpacket;
+        _damaged_qpacket[_id] ^= 0b1000_0000;
+        assert_eq!(
+            decode_any_packet(&_damaged_qpacket),
+            Ok(DecodedPacket::QPacket(decode_qpacket(&_qpacket).unwrap()))
+        );
+
+        for _layout in PACKET_LAYOUTS {
+            for _other in PACKET_LAYOUTS {
+                if _other != _layout {
+                    assert!((_layout.id() ^ _other.id()).count_ones() >= 4);
+                }
+            }
+            for _marker in [BLOCK_DELIMITER, FRAGMENT_MARKER, DELTA_MARKER] {
+                assert!((_layout.id() ^ _marker.to_le_bytes()[0]).count_ones() >= 4);
+            }
+        }
+
+        // the nearest layout wins, and an ID halfway between two layouts is no layout
+        assert_eq!(
+            PacketLayout::from_id(PacketLayout::QPacket.id() ^ 0b0000_0101),
+            Some(PacketLayout::QPacket)
+        );
+        assert_eq!(PacketLayout::from_id(0xAA), None);
+        assert_eq!(
+            PacketLayout::from_id(PacketLayout::QPacket.id() ^ 0b0000_0011),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_bare_packets() {
+        let _telemetry = make_packet_skeleton_nofec(true);
+        let _qpacket = QPacketEncoder::new()
+            .construct_packet_from_values(1_400_000_000, &[0.0; USER_DATA_BLOCKS]);
+
+        assert_eq!(decode_any_packet(&_telemetry), Err(DecodeError::BadLength));
+        assert_eq!(
+            decode_bare_packet(&_telemetry),
+            Ok(DecodedPacket::Telemetry(
+                _telemetry,
+                CorrectionReport::new()
+            ))
+        );
+        assert_eq!(
+            decode_bare_packet(&_qpacket),
+            Ok(DecodedPacket::QPacket(_qpacket))
+        );
+
+        // a packet with its FEC is not bare
+        assert_eq!(
+            decode_bare_packet(&make_packet_skeleton(true)),
+            Err(DecodeError::BadLength)
+        );
+        let mut _noise = _telemetry;
+        _noise[0] ^= 0x01;
+        assert_eq!(decode_bare_packet(&_noise), Err(DecodeError::BadHeader));
+    }
+}
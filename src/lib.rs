@@ -25,6 +25,9 @@
 #![feature(const_float_bits_conv)]
 #![feature(generic_arg_infer)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 /// Functions relating to APRS and TNC function.
 pub mod figures;
 
@@ -40,6 +43,8 @@ use parameters::*;
 /// Unifying parameters and telemetry. Cleaning up a *lot* of messiness in parameters.
 pub mod qpacket;
 
+/// Packs `_blockstackdata` as given. [TelemetryEncoder](telemetry::TelemetryEncoder) fills in the
+/// sequence number and GPS time first.
 pub fn generate_packet(_blockstackdata: BlockStackData) -> TotalMessage {
     let _blocks = construct_blocks(&_blockstackdata);
     let _packet: BareMessage = construct_packet(_blocks);
@@ -54,5 +59,58 @@ pub const fn generate_packet_no_fec(_blockstackdata: BlockStackData) -> BareMess
 pub mod aprs;
 pub mod tnc;
 
+/// Bit-level frame synchronization for demodulated bitstreams.
+pub mod sync;
+
+/// Interleaving of several packets' bytes, so bursts spread out across packets.
+pub mod interleave;
+
+/// Tracking of packet sequence numbers, per callsign.
+pub mod sequence;
+
+/// Splitting payloads larger than one packet into fragments, and reassembling them.
+pub mod fragment;
+
+/// Delta-encoded telemetry: periodic full keyframes, with small per-block deltas in between.
+pub mod delta;
+
+/// Authentication trailers (truncated SipHash and a replay counter) for packets and commands.
+pub mod auth;
+
+/// Registry of packet layouts, so one receiver can decode traffic from different firmware.
+pub mod layout;
+
+/// Inner convolutional code (K=7, r=1/2) and its Viterbi decoder, to sit between RS and the modem.
+pub mod convolutional;
+
+/// Pluggable FEC codes (Reed-Solomon, Golay, interleaved Hamming, or none) for the packet builders.
+pub mod fec;
+
+/// Whitening applied after FEC (G3RUH scrambling or PN9), to break up long runs of equal bits.
+pub mod whitening;
+
+/// Line codes (NRZ, NRZI, Manchester) turning bits into symbols for the radio, and back.
+pub mod linecode;
+
+/// Table-driven, phase-continuous 2-FSK modulator, with no floating point.
+pub mod fsk;
+
+/// 2-FSK demodulator (tone filters, clock recovery, frequency tracking) producing soft bits.
+pub mod demod;
+
+/// HDLC framing for AX.25 (flags, bit stuffing, the frame check sequence) and received frames.
+pub mod hdlc;
+
+/// Bell 202 AFSK1200 modem for APRS: modulator with KISS TXDELAY and TXTAIL, and demodulator.
+pub mod afsk;
+
+/// WAV file reading and writing, and the modems run over whole recordings. Needs `std`.
+#[cfg(feature = "std")]
+pub mod wav;
+
+/// Flight track export from decoded packets to CSV, GeoJSON, KML and GPX. Needs `std`.
+#[cfg(feature = "std")]
+pub mod track;
+
 #[cfg(test)]
 mod tests;
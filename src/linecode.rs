@@ -0,0 +1,285 @@
+use crate::parameters::*;
+
+/// A line level to hold for one symbol period: `true` for mark (high), `false` for space (low).
+pub type Symbol = bool;
+
+/// How often a timer interrupt must pop a symbol from [Symbols], in microseconds.
+pub const SYMBOL_PERIOD_US: u32 = 1_000_000 / BAUDRATE as u32;
+
+/// How bits are turned into line levels for the radio.
+///
+/// Symbols are clocked out at [BAUDRATE] symbols per second, so [LineCode::Manchester] carries
+/// half as many bits per second as the others.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineCode {
+    /// A 1 is sent as mark, a 0 as space.
+    Nrz,
+    /// A 0 is sent as a change of level, a 1 as no change, as AX.25 does. Immune to inverted
+    /// receivers, and with bit stuffing, never goes long without a transition.
+    Nrzi,
+    /// IEEE 802.3 convention: a 0 is mark then space, a 1 is space then mark. There is a transition
+    /// in every bit, and the line is DC balanced whatever the data.
+    Manchester,
+}
+
+impl LineCode {
+    pub const fn symbols_per_bit(&self) -> usize {
+        match self {
+            Self::Nrz | Self::Nrzi => 1,
+            Self::Manchester => 2,
+        }
+    }
+
+    /// Iterates over the symbols for `_data`, most significant bit first, starting from a space
+    /// level.
+    pub const fn symbols<'a>(&self, _data: &'a [u8]) -> Symbols<'a> {
+        Symbols {
+            encoder: LineEncoder::new(*self),
+            data: _data,
+            bit_index: 0,
+            pending: None,
+        }
+    }
+
+    /// Like [LineCode::symbols], but for any stream of bits, such as one that has been bit-stuffed
+    /// and so no longer comes in whole bytes.
+    pub const fn encode_bits<I: Iterator<Item = bool>>(&self, _bits: I) -> EncodedBits<I> {
+        EncodedBits {
+            encoder: LineEncoder::new(*self),
+            bits: _bits,
+            pending: None,
+        }
+    }
+}
+
+/// Bit-at-a-time line encoder. Remembers the line level, which [LineCode::Nrzi] depends on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineEncoder {
+    code: LineCode,
+    level: Symbol,
+}
+
+impl LineEncoder {
+    pub const fn new(_code: LineCode) -> Self {
+        Self {
+            code: _code,
+            level: false,
+        }
+    }
+
+    pub const fn code(&self) -> LineCode {
+        self.code
+    }
+
+    /// Symbols for one bit. Only the first [LineCode::symbols_per_bit] are meaningful.
+    pub const fn encode_bit(&mut self, _bit: bool) -> [Symbol; 2] {
+        match self.code {
+            LineCode::Nrz => [_bit, _bit],
+            LineCode::Nrzi => {
+                if !_bit {
+                    self.level = !self.level;
+                }
+                [self.level, self.level]
+            }
+            LineCode::Manchester => [!_bit, _bit],
+        }
+    }
+}
+
+/// Iterator over the symbols of a byte slice, made by [LineCode::symbols]. Cheap enough to call
+/// `next()` from a timer interrupt.
+#[derive(Debug, Clone)]
+pub struct Symbols<'a> {
+    encoder: LineEncoder,
+    data: &'a [u8],
+    bit_index: usize,
+    /// Second half of a [LineCode::Manchester] bit, still to be sent.
+    pending: Option<Symbol>,
+}
+
+impl<'a> Iterator for Symbols<'a> {
+    type Item = Symbol;
+
+    fn next(&mut self) -> Option<Symbol> {
+        if let Some(_symbol) = self.pending.take() {
+            return Some(_symbol);
+        }
+        if self.bit_index >= self.data.len() * 8 {
+            return None;
+        }
+
+        let _bit = self.data[self.bit_index / 8] & (0x80 >> (self.bit_index % 8)) != 0;
+        self.bit_index += 1;
+        let _symbols = self.encoder.encode_bit(_bit);
+        if self.encoder.code.symbols_per_bit() == 2 {
+            self.pending = Some(_symbols[1]);
+        }
+        Some(_symbols[0])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let _remaining = (self.data.len() * 8 - self.bit_index)
+            * self.encoder.code.symbols_per_bit()
+            + self.pending.is_some() as usize;
+        (_remaining, Some(_remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Symbols<'a> {}
+
+/// Iterator over the symbols of a stream of bits, made by [LineCode::encode_bits].
+#[derive(Debug, Clone)]
+pub struct EncodedBits<I: Iterator<Item = bool>> {
+    encoder: LineEncoder,
+    bits: I,
+    pending: Option<Symbol>,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for EncodedBits<I> {
+    type Item = Symbol;
+
+    fn next(&mut self) -> Option<Symbol> {
+        if let Some(_symbol) = self.pending.take() {
+            return Some(_symbol);
+        }
+        let _symbols = self.encoder.encode_bit(self.bits.next()?);
+        if self.encoder.code.symbols_per_bit() == 2 {
+            self.pending = Some(_symbols[1]);
+        }
+        Some(_symbols[0])
+    }
+}
+
+/// Symbol-at-a-time line decoder, the inverse of [LineEncoder].
+///
+/// For [LineCode::Manchester], a pair of equal symbols cannot be a bit, so the decoder takes it as
+/// a sign it is paired up the wrong way round: it drops the first symbol, counts a violation, and
+/// re-pairs from the second.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineDecoder {
+    code: LineCode,
+    level: Symbol,
+    half: Option<Symbol>,
+    violations: usize,
+}
+
+impl LineDecoder {
+    pub const fn new(_code: LineCode) -> Self {
+        Self {
+            code: _code,
+            level: false,
+            half: None,
+            violations: 0,
+        }
+    }
+
+    /// Number of [LineCode::Manchester] pairs that had no transition.
+    pub const fn violations(&self) -> usize {
+        self.violations
+    }
+
+    /// Takes one received symbol, returning a bit once one is complete.
+    pub const fn push_symbol(&mut self, _symbol: Symbol) -> Option<bool> {
+        match self.code {
+            LineCode::Nrz => Some(_symbol),
+            LineCode::Nrzi => {
+                let _bit = _symbol == self.level;
+                self.level = _symbol;
+                Some(_bit)
+            }
+            LineCode::Manchester => match self.half.take() {
+                None => {
+                    self.half = Some(_symbol);
+                    None
+                }
+                Some(_first) if _first != _symbol => Some(_symbol),
+                Some(_) => {
+                    self.violations += 1;
+                    self.half = Some(_symbol);
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Decodes a run of symbols into `_output`, most significant bit first. Returns the number of whole
+/// bytes written; trailing bits that do not make a byte are dropped.
+pub fn decode_symbols<I: IntoIterator<Item = Symbol>>(
+    _code: LineCode,
+    _symbols: I,
+    _output: &mut [u8],
+) -> usize {
+    let mut _decoder = LineDecoder::new(_code);
+    let mut _bits: usize = 0;
+    for _symbol in _symbols {
+        if _bits >= _output.len() * 8 {
+            break;
+        }
+        if let Some(_bit) = _decoder.push_symbol(_symbol) {
+            let _mask = 0x80 >> (_bits % 8);
+            if _bit {
+                _output[_bits / 8] |= _mask;
+            } else {
+                _output[_bits / 8] &= !_mask;
+            }
+            _bits += 1;
+        }
+    }
+    _bits / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::make_packet_skeleton;
+
+    #[test]
+    fn test_line_code_symbols() {
+        let _nrz: [Symbol; 8] = [true, false, true, true, false, false, false, false];
+        assert!(LineCode::Nrz.symbols(&[0xB0]).eq(_nrz));
+
+        // every 0 flips the level, every 1 holds it
+        let _nrzi: [Symbol; 8] = [false, true, true, true, false, true, false, true];
+        assert!(LineCode::Nrzi.symbols(&[0xB0]).eq(_nrzi));
+
+        let _manchester: [Symbol; 4] = [false, true, true, false];
+        assert!(LineCode::Manchester
+            .symbols(&[0x80])
+            .take(4)
+            .eq(_manchester));
+        assert_eq!(LineCode::Manchester.symbols(&[0x80, 0x00]).len(), 32);
+    }
+
+    #[test]
+    fn test_line_code_roundtrip() {
+        let _packet = make_packet_skeleton(true);
+        for _code in [LineCode::Nrz, LineCode::Nrzi, LineCode::Manchester] {
+            let mut _decoded: TotalMessage = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+            let _len = decode_symbols(_code, _code.symbols(&_packet), &mut _decoded);
+            assert_eq!(_len, TOTAL_MESSAGE_LENGTH_BYTES);
+            assert_eq!(_decoded, _packet);
+        }
+
+        // NRZI does not care which way up the receiver is, after the first bit
+        let mut _decoded: TotalMessage = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+        decode_symbols(
+            LineCode::Nrzi,
+            LineCode::Nrzi.symbols(&_packet).map(|_symbol| !_symbol),
+            &mut _decoded,
+        );
+        assert_eq!(_decoded[1..], _packet[1..]);
+    }
+
+    #[test]
+    fn test_manchester_realigns() {
+        let mut _decoder = LineDecoder::new(LineCode::Manchester);
+        // a stray mark, then a 0 (mark, space) and a 1 (space, mark)
+        let mut _bits: [Option<bool>; 5] = [None; 5];
+        for (i, _symbol) in [true, true, false, false, true].into_iter().enumerate() {
+            _bits[i] = _decoder.push_symbol(_symbol);
+        }
+        assert_eq!(_bits, [None, None, Some(false), None, Some(true)]);
+        assert_eq!(_decoder.violations(), 1);
+    }
+}
@@ -2,12 +2,17 @@ pub const NOMINAL_VOLTAGE: f32 = 3.6; // nominal battery voltage, in volts
 pub const CALLSIGN: &[u8] = b"KD9TFA"; // callsign of the balloon. MUST be an even number of characters, Space padding at the end is OK.
 pub const FLOAT_PRECISION: usize = 8; // number of significant digits in the floating point data
 pub const BAUDRATE: u16 = 1;
+pub const FSK_MARK_FREQUENCY: u32 = 1200; // Hz, sent for a 1. Our choice: rf_decoder has no FSK demodulator yet
+pub const FSK_SPACE_FREQUENCY: u32 = 1000; // Hz, sent for a 0
+pub const FSK_SAMPLE_RATE: u32 = 32000; // Hz, the samp_rate of rf_decoder/balloon_1.py
 
 pub type BareMessage = [u8; BARE_MESSAGE_LENGTH_BYTES];
 pub type TotalMessage = [u8; TOTAL_MESSAGE_LENGTH_BYTES];
 
 const BLOCK_LENGTH: usize = 1; // Packet length = 2^BLOCK_LENGTH bytes
 pub const BLOCK_DELIMITER: u16 = 0xF0F0; // Delimiter between blocks
+pub const FRAGMENT_MARKER: u16 = 0x0F0F; // Sent in place of the first delimiter, to mark a fragment
+pub const DELTA_MARKER: u16 = 0xCCCC; // Sent in place of the first delimiter, to mark a delta packet
 
 pub const START_END_HEADER: u16 = 0x1BE4;
 pub const MAX_KISS_FRAME_SIZE: usize = 128; // bytes
@@ -16,7 +21,7 @@ pub const FIGURES_FRAME_SIZE: usize = 1024; // bytes
 
 // packet related constants
 
-const BLOCK_COUNT: usize = 6;
+const BLOCK_COUNT: usize = 8;
 
 pub const BLOCK_TYPE_STACK: [BlockType; BLOCK_COUNT] = [
     BlockType::I32,
@@ -24,11 +29,37 @@ pub const BLOCK_TYPE_STACK: [BlockType; BLOCK_COUNT] = [
     BlockType::F32,
     BlockType::I32,
     BlockType::I32,
-    BlockType::BYTES,
+    BlockType::I32,
+    BlockType::SEQUENCE,
+    BlockType::GPSTIME,
+];
+
+/// Human-readable names for each data block, in the same order as [BLOCK_TYPE_STACK].
+pub const BLOCK_NAME_STACK: [&str; BLOCK_COUNT] = [
+    "status",
+    "altitude",
+    "voltage",
+    "temperature",
+    "latitude",
+    "longitude",
+    "sequence",
+    "gps_time",
+];
+
+/// How each data block's integer maps to a physical value, in the same order as [BLOCK_TYPE_STACK].
+pub const BLOCK_SCALING_STACK: [BlockScaling; BLOCK_COUNT] = [
+    BlockScaling::IDENTITY,
+    BlockScaling::new(1.0, 0.0, "m"),
+    BlockScaling::new(1.0, 0.0, "V"),
+    BlockScaling::new(0.01, 0.0, "degC"),
+    BlockScaling::new(1e-7, 0.0, "deg"),
+    BlockScaling::new(1e-7, 0.0, "deg"),
+    BlockScaling::IDENTITY,
+    BlockScaling::new(1.0, 0.0, "s"),
 ];
 
 pub const BLOCK_DELIMITER_SIZE: usize = core::mem::size_of_val(&BLOCK_DELIMITER);
-pub const BARE_MESSAGE_LENGTH_BYTES: usize = 64; // Bare message length, in bytes.
+pub const BARE_MESSAGE_LENGTH_BYTES: usize = 80; // Bare message length, in bytes.
 pub const BARE_MESSAGE_LENGTH_BLOCKS: usize = (BARE_MESSAGE_LENGTH_BYTES) >> (2 ^ BLOCK_LENGTH); // Message length, in blocks, omitting the FEC
 pub const PACKET_LENGTH_BYTES: usize = usize::pow(2, BLOCK_LENGTH as u32); // Packet length, in bytes
 
@@ -65,6 +96,73 @@ pub const BLOCK_LABEL_SIZE: usize = 1;
 pub const FEC_EXTRA_BYTES: usize = FEC_EXTRA_PACKETS * PACKET_LENGTH_BYTES; // Number of extra bytes to send for FEC
 pub const TOTAL_MESSAGE_LENGTH_BYTES: usize = BARE_MESSAGE_LENGTH_BYTES + FEC_EXTRA_BYTES; // Total message length, in bytes
 
+/// Selectable Reed-Solomon parity lengths, so FEC strength can be traded against airtime per packet.
+///
+/// A profiled packet is its [BareMessage], then a one-byte profile tag, then the profile's parity.
+/// Tags are at least 4 bits apart from each other, so a tag with a single bit error is still read correctly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FecProfile {
+    /// For ascent, close to the ground station.
+    Light,
+    /// The same parity length as [TotalMessage].
+    Standard,
+    /// For float altitude, hundreds of km away.
+    Heavy,
+}
+
+pub const FEC_PROFILES: [FecProfile; 3] =
+    [FecProfile::Light, FecProfile::Standard, FecProfile::Heavy];
+
+pub const FEC_PROFILE_TAG_SIZE: usize = 1;
+
+impl FecProfile {
+    /// Number of extra packets to send for FEC.
+    pub const fn extra_packets(&self) -> usize {
+        match self {
+            Self::Light => 2,
+            Self::Standard => FEC_EXTRA_PACKETS,
+            Self::Heavy => 16,
+        }
+    }
+
+    /// Number of extra bytes to send for FEC.
+    pub const fn extra_bytes(&self) -> usize {
+        self.extra_packets() * PACKET_LENGTH_BYTES
+    }
+
+    /// Total message length, in bytes, including the profile tag.
+    pub const fn total_len(&self) -> usize {
+        BARE_MESSAGE_LENGTH_BYTES + FEC_PROFILE_TAG_SIZE + self.extra_bytes()
+    }
+
+    /// The tag byte sent on air to identify this profile.
+    pub const fn tag(&self) -> u8 {
+        match self {
+            Self::Light => 0x0F,
+            Self::Standard => 0x3C,
+            Self::Heavy => 0xF0,
+        }
+    }
+
+    /// Returns the profile whose tag is within one bit of `_tag`, if any.
+    pub const fn from_tag(_tag: u8) -> Option<Self> {
+        let mut i: usize = 0;
+        while i < FEC_PROFILES.len() {
+            if (FEC_PROFILES[i].tag() ^ _tag).count_ones() <= 1 {
+                return Some(FEC_PROFILES[i]);
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+pub const MAX_FEC_PROFILE_BYTES: usize = FecProfile::Heavy.extra_bytes();
+pub const PROFILED_MESSAGE_MAX_LENGTH_BYTES: usize =
+    BARE_MESSAGE_LENGTH_BYTES + FEC_PROFILE_TAG_SIZE + MAX_FEC_PROFILE_BYTES;
+
+pub type ProfiledMessageArray = [u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES];
+
 // Start of message header, in binary it is 00 01 10 11 11 10 01 00
 
 pub const START_HEADER_DATA: [u8; CALLSIGN.len() + 2] = [
@@ -88,7 +186,106 @@ pub const END_HEADER_DATA: [u8; CALLSIGN.len() + 2] = [
     START_END_HEADER.to_le_bytes()[1],
 ]; // End of message header data
 
-const PACKET_BEGINNING_OFFSET: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE;
+/// Packet layouts a receiver can tell apart. Each layout's ID is sent right after
+/// [START_HEADER_DATA], so that one receiver can decode traffic from payloads running different
+/// firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketLayout {
+    /// The [BLOCK_TYPE_STACK] layout, built by [crate::telemetry].
+    Telemetry,
+    /// The layout built by [crate::qpacket].
+    QPacket,
+    /// Telemetry sent by [crate::telemetry::encode_packet_with_profile]. Each profile has its own
+    /// ID, so that a receiver knows the packet's length before its profile tag arrives.
+    ProfiledTelemetry(FecProfile),
+    /// Telemetry signed by [crate::auth::Authenticator::sign_packet].
+    AuthenticatedTelemetry,
+    /// Telemetry sent by [crate::telemetry::encode_packet_with_crc].
+    CheckedTelemetry,
+}
+
+pub const PACKET_LAYOUTS: [PacketLayout; 7] = [
+    PacketLayout::Telemetry,
+    PacketLayout::QPacket,
+    PacketLayout::ProfiledTelemetry(FecProfile::Light),
+    PacketLayout::ProfiledTelemetry(FecProfile::Standard),
+    PacketLayout::ProfiledTelemetry(FecProfile::Heavy),
+    PacketLayout::AuthenticatedTelemetry,
+    PacketLayout::CheckedTelemetry,
+];
+
+pub const LAYOUT_ID_SIZE: usize = 1;
+
+/// Bit errors a received layout ID may have and still be read as the closest layout. The IDs are
+/// all at least 4 bits apart, and 4 from every marker, so 2 errors can at worst leave a tie, which
+/// [PacketLayout::from_id] rejects.
+pub const LAYOUT_ID_MAX_BIT_ERRORS: u32 = 2;
+
+impl PacketLayout {
+    /// The ID byte sent on air. IDs are at least 4 bits apart from each other, and from the first
+    /// byte of [BLOCK_DELIMITER], [FRAGMENT_MARKER] and [DELTA_MARKER].
+    pub const fn id(&self) -> u8 {
+        match self {
+            Self::Telemetry => 0xA5,
+            Self::QPacket => 0x5A,
+            Self::ProfiledTelemetry(FecProfile::Light) => 0x33,
+            Self::ProfiledTelemetry(FecProfile::Standard) => 0x66,
+            Self::ProfiledTelemetry(FecProfile::Heavy) => 0x99,
+            Self::AuthenticatedTelemetry => 0x69,
+            Self::CheckedTelemetry => 0x96,
+        }
+    }
+
+    /// Returns the layout whose ID is closest to `_id`, if it is within
+    /// [LAYOUT_ID_MAX_BIT_ERRORS] bits and no other layout's ID is as close.
+    pub const fn from_id(_id: u8) -> Option<Self> {
+        let mut _closest: Option<Self> = None;
+        let mut _closest_distance = LAYOUT_ID_MAX_BIT_ERRORS + 1;
+        let mut i: usize = 0;
+        while i < PACKET_LAYOUTS.len() {
+            let _distance = (PACKET_LAYOUTS[i].id() ^ _id).count_ones();
+            if _distance < _closest_distance {
+                _closest = Some(PACKET_LAYOUTS[i]);
+                _closest_distance = _distance;
+            } else if _distance == _closest_distance {
+                _closest = None;
+            }
+            i += 1;
+        }
+        _closest
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Telemetry => "telemetry",
+            Self::QPacket => "qpacket",
+            Self::ProfiledTelemetry(FecProfile::Light) => "telemetry-light",
+            Self::ProfiledTelemetry(FecProfile::Standard) => "telemetry-standard",
+            Self::ProfiledTelemetry(FecProfile::Heavy) => "telemetry-heavy",
+            Self::AuthenticatedTelemetry => "telemetry-signed",
+            Self::CheckedTelemetry => "telemetry-checked",
+        }
+    }
+}
+
+pub const LAYOUT_HEADER_LENGTH_BYTES: usize = START_HEADER_DATA.len() + LAYOUT_ID_SIZE;
+
+/// [START_HEADER_DATA] followed by `_layout`'s ID.
+pub const fn layout_header_data(_layout: PacketLayout) -> [u8; LAYOUT_HEADER_LENGTH_BYTES] {
+    let mut output = [0u8; LAYOUT_HEADER_LENGTH_BYTES];
+    let mut i: usize = 0;
+    while i < START_HEADER_DATA.len() {
+        output[i] = START_HEADER_DATA[i];
+        i += 1;
+    }
+    output[i] = _layout.id();
+    output
+}
+
+pub const TELEMETRY_HEADER_DATA: [u8; LAYOUT_HEADER_LENGTH_BYTES] =
+    layout_header_data(PacketLayout::Telemetry);
+
+const PACKET_BEGINNING_OFFSET: usize = LAYOUT_HEADER_LENGTH_BYTES + BLOCK_DELIMITER_SIZE;
 
 // pub const ALTITUDE_LOCATION_START: usize = PACKET_BEGINNING_OFFSET + BLOCK_LABEL_SIZE; // for the sake of consistency
 // pub const ALTITUDE_LOCATION_END: usize = ALTITUDE_LOCATION_START + ALTITUDE_SIZE;
@@ -108,7 +305,7 @@ const PACKET_BEGINNING_OFFSET: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER
 type BlockConfig = usize;
 type BlockConfigStack = [BlockConfig; BLOCK_STACK_DATA_COUNT];
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockType {
     CALLSIGN,
     F32,
@@ -117,6 +314,10 @@ pub enum BlockType {
     I16,
     U16,
     BYTES,
+    /// Packet sequence number, a [u16] filled in by the encoder and incremented per packet.
+    SEQUENCE,
+    /// Seconds since the GPS epoch (1980-01-06), as a [u32], filled in by the encoder.
+    GPSTIME,
     NONE,
 }
 
@@ -130,6 +331,8 @@ impl BlockType {
             Self::I16 => I16_DATA_SIZE,
             Self::U16 => U16_DATA_SIZE,
             Self::BYTES => BYTES_DATA_SIZE,
+            Self::SEQUENCE => U16_DATA_SIZE,
+            Self::GPSTIME => U32_DATA_SIZE,
             Self::NONE => 0usize,
         }
     }
@@ -137,7 +340,47 @@ impl BlockType {
 
 type BlockTypeStack = [BlockType; BLOCK_TYPE_STACK.len()];
 
-#[derive(Clone, Copy)]
+/// Fixed-point scaling of a block: `physical = raw * scale + offset`, in `unit`.
+///
+/// e.g. latitude as an [BlockType::I32] in 1e-7 degrees is `BlockScaling::new(1e-7, 0.0, "deg")`.
+/// Float blocks normally use a scale of 1, so that only their unit is declared.
+///
+/// The arithmetic is done in `f64`, which holds every [BlockType::I32] raw value exactly, so a
+/// declared step such as 1e-7 degrees is kept rather than lost to an `f32`'s 24-bit mantissa.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockScaling {
+    pub scale: f64,
+    pub offset: f64,
+    pub unit: &'static str,
+}
+
+impl BlockScaling {
+    /// The raw value is the physical value, with no unit.
+    pub const IDENTITY: Self = Self::new(1.0, 0.0, "");
+
+    pub const fn new(scale: f64, offset: f64, unit: &'static str) -> Self {
+        Self {
+            scale,
+            offset,
+            unit,
+        }
+    }
+
+    /// Converts a raw (on-air) value to its physical value.
+    ///
+    /// Divides by the inverse of the scale, which is exact for decimal steps such as 1e-7, so the
+    /// result is the nearest `f64` to the decimal value rather than one rounding step off it.
+    pub const fn to_physical(&self, raw: f64) -> f64 {
+        raw / (1.0 / self.scale) + self.offset
+    }
+
+    /// Converts a physical value to its raw (on-air) value, before any rounding.
+    pub const fn to_raw(&self, physical: f64) -> f64 {
+        (physical - self.offset) * (1.0 / self.scale)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct BlockIdent {
     pub size: BlockConfig,
     pub block_type: BlockType,
@@ -235,6 +478,16 @@ pub const BLOCK_IDENT_STACK: BlockIdentStack = {
     _blockidentstack
 };
 
+const _: () = assert!(
+    BLOCK_IDENT_STACK[BLOCK_STACK_DATA_COUNT - 1].end_location
+        + BLOCK_DELIMITER_SIZE
+        + BLOCK_LABEL_SIZE
+        + END_HEADER_DATA.len()
+        + BLOCK_DELIMITER_SIZE
+        <= BARE_MESSAGE_LENGTH_BYTES,
+    "blocks_do_not_fit_in_bare_message"
+);
+
 // const _: () = assert!(BLOCK_IDENT_STACK[0].beginning_location == ALTITUDE_LOCATION_START);
 // const _: () = assert!(BLOCK_IDENT_STACK[0].end_location == ALTITUDE_LOCATION_END);
 
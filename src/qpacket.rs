@@ -2,35 +2,53 @@
 
 use core::mem;
 
+use crate::fec::{Fec, ReedSolomon, MAX_FEC_CODEWORD_LENGTH_BYTES};
 use crate::parameters::*;
 
-use reed_solomon::{Decoder, Encoder};
+use crate::telemetry::{BlockValue, DecodeError};
 const FEC_BYTES: usize = 19;
+/// The code [encode_qpacket] uses.
+pub const QPACKET_FEC: ReedSolomon = ReedSolomon::new(FEC_BYTES);
 
 type BlockLabelType = u8;
 const FIRST_BLOCK_LABEL: BlockLabelType = 128;
 
-pub const TOTAL_DATA_BLOCKS: usize = 5;
+pub const TOTAL_DATA_BLOCKS: usize = 7;
 const BLOCK_CFG_STACK: BlockCfgStack = [
     BlockCfg {
-        block_type: BlockType::I32, // latitude
+        block_type: BlockType::SEQUENCE,
         do_transmit_label: true,
+        scaling: BlockScaling::IDENTITY,
     },
     BlockCfg {
-        block_type: BlockType::I32, // longitude
+        block_type: BlockType::GPSTIME,
         do_transmit_label: true,
+        scaling: BlockScaling::new(1.0, 0.0, "s"),
     },
     BlockCfg {
-        block_type: BlockType::I32, // altitude
+        block_type: BlockType::I32, // latitude, 1e-7 degrees
         do_transmit_label: true,
+        scaling: BlockScaling::new(1e-7, 0.0, "deg"),
     },
     BlockCfg {
-        block_type: BlockType::I16, // voltage
+        block_type: BlockType::I32, // longitude, 1e-7 degrees
         do_transmit_label: true,
+        scaling: BlockScaling::new(1e-7, 0.0, "deg"),
     },
     BlockCfg {
-        block_type: BlockType::I16, // temperature
+        block_type: BlockType::I32, // altitude, centimetres
         do_transmit_label: true,
+        scaling: BlockScaling::new(0.01, 0.0, "m"),
+    },
+    BlockCfg {
+        block_type: BlockType::I16, // voltage, millivolts
+        do_transmit_label: true,
+        scaling: BlockScaling::new(0.001, 0.0, "V"),
+    },
+    BlockCfg {
+        block_type: BlockType::I16, // temperature, centi-degrees
+        do_transmit_label: true,
+        scaling: BlockScaling::new(0.01, 0.0, "degC"),
     },
 ];
 
@@ -49,6 +67,7 @@ const BLOCK_TYPE_STACK: [BlockType; BLOCK_CFG_STACK.len()] = {
 struct BlockCfg {
     block_type: BlockType,
     do_transmit_label: bool,
+    scaling: BlockScaling,
 }
 
 type BlockCfgStack = [BlockCfg; TOTAL_DATA_BLOCKS];
@@ -59,6 +78,7 @@ pub struct BlockIdent {
     label: BlockLabelType,
     do_transmit_label: bool,
     position: (usize, usize),
+    scaling: BlockScaling,
 }
 
 impl BlockIdent {
@@ -70,6 +90,7 @@ impl BlockIdent {
             label: 0 as BlockLabelType,
             do_transmit_label: false,
             position: (0usize, 0usize + BlockType::NONE.len()),
+            scaling: BlockScaling::IDENTITY,
         }
     }
 
@@ -89,7 +110,7 @@ const fn cfg_stack_to_ident_stack(cfg_stack: BlockCfgStack) -> BlockIdentStack {
     // a block is its label, its data, and the following delimiter
     let mut output: BlockIdentStack = [BlockIdent::BLANK; TOTAL_DATA_BLOCKS];
     let mut i: usize = 0;
-    let mut current_packet_position: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE; // bytes, accounting for START_HEADER_DATA and its delimiter
+    let mut current_packet_position: usize = QPACKET_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE; // bytes, accounting for QPACKET_HEADER_DATA and its delimiter
 
     while i < output.len() {
         if i > BlockLabelType::MAX as usize || i >= cfg_stack.len() {
@@ -114,6 +135,7 @@ const fn cfg_stack_to_ident_stack(cfg_stack: BlockCfgStack) -> BlockIdentStack {
             label: _block_label,
             do_transmit_label: _do_transmit_label,
             position: (current_packet_position, end_position),
+            scaling: cfg_stack[i].scaling,
         };
         current_packet_position = end_position + 1;
         i += 1;
@@ -122,6 +144,9 @@ const fn cfg_stack_to_ident_stack(cfg_stack: BlockCfgStack) -> BlockIdentStack {
 }
 
 const BLOCK_IDENT_STACK: BlockIdentStack = cfg_stack_to_ident_stack(BLOCK_CFG_STACK);
+/// [START_HEADER_DATA], followed by the [PacketLayout::QPacket] ID.
+const QPACKET_HEADER_DATA: [u8; LAYOUT_HEADER_LENGTH_BYTES] =
+    layout_header_data(PacketLayout::QPacket);
 pub const QPACKET_DATA_LEN: usize = BLOCK_IDENT_STACK[BLOCK_IDENT_STACK.len() - 1].position.1 - 1;
 const START_END_HEADER_SIZE: usize = mem::size_of_val::<_>(&START_END_HEADER);
 pub const QPACKET_BARE_LEN: usize =
@@ -138,8 +163,58 @@ const BLOCK_SIZE_STACK: [usize; TOTAL_DATA_BLOCKS] = {
 
     output
 };
-type BareQPacket = [u8; QPACKET_BARE_LEN];
-type FullQPacket = [u8; QPACKET_FULL_LEN];
+pub type BareQPacket = [u8; QPACKET_BARE_LEN];
+pub type FullQPacket = [u8; QPACKET_FULL_LEN];
+
+/// Number of blocks whose data is supplied by the caller, rather than filled in by [QPacketEncoder].
+pub const USER_DATA_BLOCKS: usize = {
+    let mut output: usize = 0;
+    let mut i: usize = 0;
+
+    while i < BLOCK_TYPE_STACK.len() {
+        match BLOCK_TYPE_STACK[i] {
+            BlockType::SEQUENCE | BlockType::GPSTIME => (),
+            _ => output += 1,
+        }
+        i += 1;
+    }
+
+    output
+};
+
+/// The blocks whose data is supplied by the caller, in [BLOCK_CFG_STACK] order.
+const USER_BLOCK_IDENT_STACK: [BlockIdent; USER_DATA_BLOCKS] = {
+    let mut output: [BlockIdent; USER_DATA_BLOCKS] = [BlockIdent::BLANK; USER_DATA_BLOCKS];
+    let mut i: usize = 0;
+    let mut x: usize = 0;
+
+    while i < BLOCK_IDENT_STACK.len() {
+        match BLOCK_IDENT_STACK[i].block_type {
+            BlockType::SEQUENCE | BlockType::GPSTIME => (),
+            _ => {
+                output[x] = BLOCK_IDENT_STACK[i];
+                x += 1;
+            }
+        }
+        i += 1;
+    }
+
+    output
+};
+
+/// Scaling of each caller-supplied block, as taken by [QPacketEncoder::encode_values] and
+/// returned by [qpacket_values].
+pub const USER_BLOCK_SCALING: [BlockScaling; USER_DATA_BLOCKS] = {
+    let mut output: [BlockScaling; USER_DATA_BLOCKS] = [BlockScaling::IDENTITY; USER_DATA_BLOCKS];
+    let mut i: usize = 0;
+
+    while i < USER_DATA_BLOCKS {
+        output[i] = USER_BLOCK_IDENT_STACK[i].scaling;
+        i += 1;
+    }
+
+    output
+};
 
 pub struct QPacketBlock<'a> {
     identity: BlockIdent,
@@ -200,8 +275,8 @@ const fn construct_blank_packet<const DATA: u8>(_blockstack: BlockIdentStack) ->
     let mut i: usize = 0;
     let mut x: usize = 0;
 
-    while x < START_HEADER_DATA.len() {
-        output[x] = START_HEADER_DATA[x];
+    while x < QPACKET_HEADER_DATA.len() {
+        output[x] = QPACKET_HEADER_DATA[x];
         x += 1;
     }
 
@@ -209,7 +284,7 @@ const fn construct_blank_packet<const DATA: u8>(_blockstack: BlockIdentStack) ->
 
     // manually insert first delimiter
     while x < BLOCK_DELIMITER_SIZE {
-        output[x + START_HEADER_DATA.len()] = BLOCK_DELIMITER.to_be_bytes()[x];
+        output[x + QPACKET_HEADER_DATA.len()] = BLOCK_DELIMITER.to_be_bytes()[x];
         x += 1;
     }
 
@@ -250,8 +325,8 @@ const fn construct_blank_packet<const DATA: u8>(_blockstack: BlockIdentStack) ->
 pub fn construct_packet_nofec(_block_stack: QPacketBlockStack) -> BareQPacket {
     let mut unencoded_output: BareQPacket = [0u8; QPACKET_BARE_LEN];
     let mut packet_position: usize = 0;
-    unencoded_output[0..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
-    packet_position += START_HEADER_DATA.len();
+    unencoded_output[0..QPACKET_HEADER_DATA.len()].copy_from_slice(&QPACKET_HEADER_DATA);
+    packet_position += QPACKET_HEADER_DATA.len();
     unencoded_output[packet_position..packet_position + BLOCK_DELIMITER_SIZE]
         .copy_from_slice(&BLOCK_DELIMITER.to_be_bytes());
 
@@ -290,9 +365,199 @@ pub const MIN_QPACKET: BareQPacket = construct_blank_packet::<0x00u8>(BLOCK_IDEN
 pub const MAX_QPACKET: BareQPacket = construct_blank_packet::<0xFFu8>(BLOCK_IDENT_STACK);
 
 pub fn encode_qpacket(_bare_packet: BareQPacket) -> FullQPacket {
-    let enc = Encoder::new(FEC_BYTES);
-    let _encoded_packet = enc.encode(&_bare_packet[..]);
-    _encoded_packet[..].try_into().unwrap()
+    let mut output: FullQPacket = [0u8; QPACKET_FULL_LEN];
+    encode_qpacket_with(_bare_packet, &QPACKET_FEC, &mut output);
+    output
+}
+
+/// Encodes a QPacket with any [Fec] into `_output`, which must be
+/// `_fec.encoded_len(QPACKET_BARE_LEN)` long. See [decode_qpacket_with].
+///
+/// Unless `_fec` is [QPACKET_FEC], receivers cannot frame the result from the sync word alone;
+/// send it inside an outer sync or length layer (see [Fec]).
+pub fn encode_qpacket_with<F: Fec>(_bare_packet: BareQPacket, _fec: &F, _output: &mut [u8]) {
+    _fec.encode(&_bare_packet[..], _output);
+}
+
+/// Builds and encodes QPackets, filling in the [BlockType::SEQUENCE] and [BlockType::GPSTIME]
+/// blocks itself. The sequence number starts at 0 (or wherever [QPacketEncoder::with_sequence] says)
+/// and wraps around after [u16::MAX].
+#[derive(Debug, Copy, Clone)]
+pub struct QPacketEncoder {
+    sequence: u16,
+}
+
+impl QPacketEncoder {
+    pub const fn new() -> Self {
+        Self::with_sequence(0)
+    }
+
+    /// Starts counting from `_sequence`, e.g. to carry on from a value saved before a reset.
+    pub const fn with_sequence(_sequence: u16) -> Self {
+        Self {
+            sequence: _sequence,
+        }
+    }
+
+    /// The sequence number the next packet will carry.
+    pub const fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Builds the next packet, without FEC. `_data` holds one slice per caller-supplied block, in
+    /// [BLOCK_CFG_STACK] order, skipping the blocks this encoder fills in.
+    pub fn construct_packet_nofec(
+        &mut self,
+        _gps_time: u32,
+        _data: &[&[u8]; USER_DATA_BLOCKS],
+    ) -> BareQPacket {
+        let _sequence_bytes = self.sequence.to_be_bytes();
+        let _gps_time_bytes = _gps_time.to_be_bytes();
+        let mut _block_stack: QPacketBlockStack = [QPacketBlock::BLANK; TOTAL_DATA_BLOCKS];
+        let mut _user_block: usize = 0;
+
+        for i in 0..TOTAL_DATA_BLOCKS {
+            let _block_data: &[u8] = match BLOCK_IDENT_STACK[i].block_type {
+                BlockType::SEQUENCE => &_sequence_bytes,
+                BlockType::GPSTIME => &_gps_time_bytes,
+                _ => {
+                    _user_block += 1;
+                    _data[_user_block - 1]
+                }
+            };
+            debug_assert_eq!(_block_data.len(), BLOCK_IDENT_STACK[i].data_len());
+            _block_stack[i] = QPacketBlock {
+                identity: BLOCK_IDENT_STACK[i],
+                data: _block_data,
+            };
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        construct_packet_nofec(_block_stack)
+    }
+
+    /// Like [QPacketEncoder::construct_packet_nofec], followed by [encode_qpacket].
+    pub fn encode(&mut self, _gps_time: u32, _data: &[&[u8]; USER_DATA_BLOCKS]) -> FullQPacket {
+        encode_qpacket(self.construct_packet_nofec(_gps_time, _data))
+    }
+
+    /// Like [QPacketEncoder::construct_packet_nofec], but takes physical values in the units of
+    /// [USER_BLOCK_SCALING] and converts them to their on-air integers.
+    pub fn construct_packet_from_values(
+        &mut self,
+        _gps_time: u32,
+        _values: &[f64; USER_DATA_BLOCKS],
+    ) -> BareQPacket {
+        let mut _raw: [[u8; 4]; USER_DATA_BLOCKS] = [[0u8; 4]; USER_DATA_BLOCKS];
+        for i in 0..USER_DATA_BLOCKS {
+            _raw[i] = BlockValue::from_physical(
+                USER_BLOCK_IDENT_STACK[i].block_type,
+                &USER_BLOCK_SCALING[i],
+                _values[i],
+            )
+            .to_be_bytes();
+        }
+
+        let _data: [&[u8]; USER_DATA_BLOCKS] =
+            core::array::from_fn(|i| &_raw[i][..USER_BLOCK_IDENT_STACK[i].data_len()]);
+        self.construct_packet_nofec(_gps_time, &_data)
+    }
+
+    /// Like [QPacketEncoder::construct_packet_nofec], followed by [encode_qpacket_with].
+    pub fn encode_with<F: Fec>(
+        &mut self,
+        _gps_time: u32,
+        _data: &[&[u8]; USER_DATA_BLOCKS],
+        _fec: &F,
+        _output: &mut [u8],
+    ) {
+        encode_qpacket_with(self.construct_packet_nofec(_gps_time, _data), _fec, _output)
+    }
+
+    /// Like [QPacketEncoder::construct_packet_from_values], followed by [encode_qpacket].
+    pub fn encode_values(
+        &mut self,
+        _gps_time: u32,
+        _values: &[f64; USER_DATA_BLOCKS],
+    ) -> FullQPacket {
+        encode_qpacket(self.construct_packet_from_values(_gps_time, _values))
+    }
+}
+
+/// Corrects a received QPacket and returns its bare form.
+///
+/// Only the [START_END_HEADER] bytes are checked, not the callsign, so that packets from other
+/// payloads can be decoded too; see [qpacket_callsign].
+pub fn decode_qpacket(_packet: &[u8]) -> Result<BareQPacket, DecodeError> {
+    decode_qpacket_with(_packet, &QPACKET_FEC)
+}
+
+/// Like [decode_qpacket], but for packets made by [encode_qpacket_with] with the same `_fec`.
+pub fn decode_qpacket_with<F: Fec>(_packet: &[u8], _fec: &F) -> Result<BareQPacket, DecodeError> {
+    if _packet.len() != _fec.encoded_len(QPACKET_BARE_LEN)
+        || _packet.len() > MAX_FEC_CODEWORD_LENGTH_BYTES
+    {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut recovered_packet: BareQPacket = [0u8; QPACKET_BARE_LEN];
+    _fec.decode(_packet, &[], &mut recovered_packet)
+        .map_err(|_| DecodeError::Uncorrectable)?;
+
+    if recovered_packet[..START_END_HEADER_SIZE] != START_HEADER_DATA[..START_END_HEADER_SIZE] {
+        return Err(DecodeError::BadHeader);
+    }
+    if PacketLayout::from_id(recovered_packet[START_HEADER_DATA.len()])
+        != Some(PacketLayout::QPacket)
+    {
+        return Err(DecodeError::UnknownLayout);
+    }
+
+    Ok(recovered_packet)
+}
+
+/// Returns the callsign a bare QPacket was sent with.
+pub fn qpacket_callsign(_packet: &BareQPacket) -> [u8; CALLSIGN.len()] {
+    let mut output: [u8; CALLSIGN.len()] = [0u8; CALLSIGN.len()];
+    output.copy_from_slice(&_packet[START_END_HEADER_SIZE..START_HEADER_DATA.len()]);
+    output
+}
+
+/// Returns the data of the first block of type `_block_type`, if the layout has one.
+pub fn qpacket_block_data(_packet: &BareQPacket, _block_type: BlockType) -> Option<&[u8]> {
+    for _block in BLOCK_IDENT_STACK {
+        if _block.block_type == _block_type {
+            let _data_start = _block.position.0 + if _block.do_transmit_label { 1 } else { 0 };
+            return Some(&_packet[_data_start.._data_start + _block.data_len()]);
+        }
+    }
+    None
+}
+
+/// Returns the sequence number of a bare QPacket.
+pub fn qpacket_sequence(_packet: &BareQPacket) -> Option<u16> {
+    qpacket_block_data(_packet, BlockType::SEQUENCE)
+        .map(|_data| u16::from_be_bytes(_data.try_into().unwrap()))
+}
+
+/// Returns the GPS time (seconds since the GPS epoch) of a bare QPacket.
+pub fn qpacket_gps_time(_packet: &BareQPacket) -> Option<u32> {
+    qpacket_block_data(_packet, BlockType::GPSTIME)
+        .map(|_data| u32::from_be_bytes(_data.try_into().unwrap()))
+}
+
+/// Returns the physical value of each caller-supplied block, in the units of [USER_BLOCK_SCALING].
+pub fn qpacket_values(_packet: &BareQPacket) -> [f64; USER_DATA_BLOCKS] {
+    let mut output: [f64; USER_DATA_BLOCKS] = [0.0f64; USER_DATA_BLOCKS];
+
+    for i in 0..USER_DATA_BLOCKS {
+        let _block = &USER_BLOCK_IDENT_STACK[i];
+        let _data_start = _block.position.0 + if _block.do_transmit_label { 1 } else { 0 };
+        output[i] = BlockValue::from_bytes(_block.block_type, &_packet[_data_start..])
+            .to_physical(&_block.scaling);
+    }
+
+    output
 }
 
 #[cfg(test)]
@@ -300,10 +565,10 @@ mod tests {
     use super::*;
 
     fn check_packet_formation<const T: usize>(packet: [u8; T]) {
-        let mut _packet_head = [0u8; (START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE)];
+        let mut _packet_head = [0u8; (QPACKET_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE)];
         let mut i: usize = 0;
-        _packet_head[0..START_HEADER_DATA.len()].copy_from_slice(&START_HEADER_DATA);
-        i += START_HEADER_DATA.len();
+        _packet_head[0..QPACKET_HEADER_DATA.len()].copy_from_slice(&QPACKET_HEADER_DATA);
+        i += QPACKET_HEADER_DATA.len();
         _packet_head[i..i + BLOCK_DELIMITER_SIZE].copy_from_slice(&BLOCK_DELIMITER.to_be_bytes());
         assert_eq!(packet[0..i + BLOCK_DELIMITER_SIZE], _packet_head);
 
@@ -348,6 +613,68 @@ mod tests {
         assert_eq!(MIN_QPACKET, manual_min_qpacket);
     }
 
+    #[test]
+    pub fn test_qpacket_encoder() {
+        let mut _encoder = QPacketEncoder::with_sequence(u16::MAX);
+        let _data: [&[u8]; USER_DATA_BLOCKS] = [
+            &419_876_543i32.to_be_bytes(),
+            &(-872_345_678i32).to_be_bytes(),
+            &31_337i32.to_be_bytes(),
+            &3_600i16.to_be_bytes(),
+            &(-4_050i16).to_be_bytes(),
+        ];
+
+        let _first = _encoder.encode(1_400_000_000, &_data);
+        let _second = _encoder.encode(1_400_000_010, &_data);
+        assert_eq!(_encoder.sequence(), 1);
+
+        let mut _damaged = _first;
+        _damaged[20] ^= 0xFF;
+        let _first = decode_qpacket(&_damaged).unwrap();
+        let _second = decode_qpacket(&_second).unwrap();
+        check_packet_formation(_first);
+
+        let _golay = crate::fec::Golay24;
+        let mut _third = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+        let _third = &mut _third[.._golay.encoded_len(QPACKET_BARE_LEN)];
+        _encoder.encode_with(1_400_000_020, &_data, &_golay, _third);
+        _third[4] ^= 0x01;
+        let _third = decode_qpacket_with(_third, &_golay).unwrap();
+        assert_eq!(qpacket_sequence(&_third), Some(1));
+
+        assert_eq!(qpacket_callsign(&_first), CALLSIGN);
+        assert_eq!(qpacket_sequence(&_first), Some(u16::MAX));
+        assert_eq!(qpacket_sequence(&_second), Some(0));
+        assert_eq!(qpacket_gps_time(&_second), Some(1_400_000_010));
+        assert_eq!(
+            qpacket_block_data(&_first, BlockType::I16),
+            Some(&3_600i16.to_be_bytes()[..])
+        );
+    }
+
+    #[test]
+    pub fn test_qpacket_values() {
+        let mut _encoder = QPacketEncoder::new();
+        let _values: [f64; USER_DATA_BLOCKS] = [41.9876543, -87.2345678, 313.37, 3.6, -40.5];
+        let _packet = _encoder.construct_packet_from_values(1_400_000_000, &_values);
+
+        // the same on-air integers as written out by hand in test_qpacket_encoder
+        assert_eq!(
+            qpacket_block_data(&_packet, BlockType::I16),
+            Some(&3_600i16.to_be_bytes()[..])
+        );
+        let _decoded = qpacket_values(&decode_qpacket(&encode_qpacket(_packet)).unwrap());
+        for i in 0..USER_DATA_BLOCKS {
+            assert!(
+                (_decoded[i] - _values[i]).abs() <= USER_BLOCK_SCALING[i].scale,
+                "{} != {}",
+                _decoded[i],
+                _values[i]
+            );
+        }
+        assert_eq!(USER_BLOCK_SCALING[4].unit, "degC");
+    }
+
     #[test]
     pub fn a() {
         panic!("{:02X?}", encode_qpacket(MIN_QPACKET));
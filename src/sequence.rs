@@ -0,0 +1,150 @@
+use crate::parameters::CALLSIGN;
+
+pub type Callsign = [u8; CALLSIGN.len()];
+
+/// What a newly received sequence number means, relative to what was already seen from that callsign.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// First packet seen from this callsign.
+    First,
+    /// The packet directly after the previous one.
+    InOrder,
+    /// Newer than expected; this many packets in between were missed.
+    Gap(u16),
+    /// The counter wrapped past [u16::MAX]; this many packets in between were missed.
+    Wrapped(u16),
+    /// A packet that was already received.
+    Duplicate,
+    /// An older packet that had not been received yet, arriving late.
+    Reordered,
+    /// The tracker is already following as many callsigns as it can hold.
+    Untracked,
+}
+
+/// How far back [SequenceTracker] remembers which packets it has seen, to tell a late packet from
+/// a duplicate.
+pub const SEQUENCE_HISTORY: u16 = u32::BITS as u16;
+
+#[derive(Debug, Copy, Clone)]
+struct TrackedCallsign {
+    callsign: Callsign,
+    latest: u16,
+    /// Bit `n` is set if packet `latest - n` has been received.
+    history: u32,
+}
+
+/// Follows the sequence numbers of up to `N` callsigns, and classifies each new one as in-order,
+/// a gap, a duplicate, a reorder or a wraparound.
+///
+/// Sequence numbers are compared modulo 2^16: one less than 2^15 ahead of the latest is treated as
+/// newer, anything else as older.
+#[derive(Debug, Copy, Clone)]
+pub struct SequenceTracker<const N: usize> {
+    tracked: [Option<TrackedCallsign>; N],
+}
+
+impl<const N: usize> SequenceTracker<N> {
+    pub const fn new() -> Self {
+        Self { tracked: [None; N] }
+    }
+
+    /// Records that `_sequence` was received from `_callsign`.
+    pub fn track(&mut self, _callsign: &Callsign, _sequence: u16) -> SequenceEvent {
+        let mut _free_slot: Option<usize> = None;
+
+        for i in 0..N {
+            match &mut self.tracked[i] {
+                Some(_tracked) if _tracked.callsign == *_callsign => {
+                    return Self::update(_tracked, _sequence)
+                }
+                None if _free_slot.is_none() => _free_slot = Some(i),
+                _ => (),
+            }
+        }
+
+        match _free_slot {
+            Some(i) => {
+                self.tracked[i] = Some(TrackedCallsign {
+                    callsign: *_callsign,
+                    latest: _sequence,
+                    history: 1,
+                });
+                SequenceEvent::First
+            }
+            None => SequenceEvent::Untracked,
+        }
+    }
+
+    /// The latest sequence number received from `_callsign`, if it is being tracked.
+    pub fn latest(&self, _callsign: &Callsign) -> Option<u16> {
+        self.tracked
+            .iter()
+            .flatten()
+            .find(|_tracked| _tracked.callsign == *_callsign)
+            .map(|_tracked| _tracked.latest)
+    }
+
+    fn update(_tracked: &mut TrackedCallsign, _sequence: u16) -> SequenceEvent {
+        let _ahead = _sequence.wrapping_sub(_tracked.latest);
+
+        if _ahead == 0 {
+            return SequenceEvent::Duplicate;
+        }
+
+        if _ahead < 0x8000 {
+            let _missed = _ahead - 1;
+            _tracked.history = if _ahead < SEQUENCE_HISTORY {
+                (_tracked.history << _ahead) | 1
+            } else {
+                1
+            };
+            let _wrapped = _sequence < _tracked.latest;
+            _tracked.latest = _sequence;
+
+            return if _wrapped {
+                SequenceEvent::Wrapped(_missed)
+            } else if _missed == 0 {
+                SequenceEvent::InOrder
+            } else {
+                SequenceEvent::Gap(_missed)
+            };
+        }
+
+        let _behind = _tracked.latest.wrapping_sub(_sequence);
+        if _behind < SEQUENCE_HISTORY {
+            let _bit = 1u32 << _behind;
+            if _tracked.history & _bit != 0 {
+                return SequenceEvent::Duplicate;
+            }
+            _tracked.history |= _bit;
+        }
+        SequenceEvent::Reordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_tracker() {
+        const OTHER_CALLSIGN: Callsign = *b"N0CALL";
+        let _callsign: Callsign = CALLSIGN.try_into().unwrap();
+        let mut _tracker: SequenceTracker<2> = SequenceTracker::new();
+
+        assert_eq!(_tracker.track(&_callsign, 65530), SequenceEvent::First);
+        assert_eq!(_tracker.track(&_callsign, 65531), SequenceEvent::InOrder);
+        assert_eq!(_tracker.track(&_callsign, 65534), SequenceEvent::Gap(2));
+        assert_eq!(_tracker.track(&_callsign, 65533), SequenceEvent::Reordered);
+        assert_eq!(_tracker.track(&_callsign, 65533), SequenceEvent::Duplicate);
+        assert_eq!(_tracker.track(&_callsign, 65534), SequenceEvent::Duplicate);
+        assert_eq!(_tracker.track(&_callsign, 1), SequenceEvent::Wrapped(2));
+        assert_eq!(_tracker.track(&_callsign, 65531), SequenceEvent::Duplicate);
+        assert_eq!(_tracker.track(&_callsign, 65532), SequenceEvent::Reordered);
+        assert_eq!(_tracker.latest(&_callsign), Some(1));
+
+        assert_eq!(_tracker.track(&OTHER_CALLSIGN, 1), SequenceEvent::First);
+        assert_eq!(_tracker.track(b"W1AW  ", 1), SequenceEvent::Untracked);
+        assert_eq!(_tracker.track(&OTHER_CALLSIGN, 2), SequenceEvent::InOrder);
+    }
+}
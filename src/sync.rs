@@ -0,0 +1,481 @@
+use crate::delta::{
+    delta_packet_len, is_delta, DELTA_MAX_TOTAL_LEN, DELTA_MIN_BARE_LEN, DELTA_WIDTH_FIELD_END,
+};
+use crate::fragment::has_fragment_marker;
+use crate::layout::{decode_any_packet, layout_info, DecodedPacket, MAX_LAYOUT_TOTAL_LEN};
+use crate::parameters::*;
+use crate::telemetry::DecodeError;
+use crate::whitening::{BitWhitener, Whitening};
+
+/// Number of bits in the sync word, which is the entirety of [START_HEADER_DATA].
+pub const SYNC_WORD_BITS: usize = START_HEADER_DATA.len() * 8;
+
+const _: () = assert!(
+    START_HEADER_DATA.len() == core::mem::size_of::<u64>(),
+    "sync word must fit in the shift register"
+);
+
+/// [START_HEADER_DATA] as it appears in the shift register, first transmitted bit in the MSB.
+pub const SYNC_WORD: u64 = u64::from_be_bytes(START_HEADER_DATA);
+
+/// [SYNC_WORD] as it is sent with `_whitening`, which starts afresh on every frame.
+pub const fn whitened_sync_word(_whitening: Whitening) -> u64 {
+    let mut _whitener = BitWhitener::new(_whitening);
+    let mut output: u64 = 0;
+    let mut i: usize = 0;
+    while i < SYNC_WORD_BITS {
+        let _bit = (SYNC_WORD >> (SYNC_WORD_BITS - 1 - i)) & 1 != 0;
+        output = (output << 1) | _whitener.whiten_bit(_bit) as u64;
+        i += 1;
+    }
+    output
+}
+
+/// Bytes the synchronizer reads after the sync word before telling delta packets and fragments
+/// from layouts.
+const MARKER_END: usize = START_HEADER_DATA.len() + BLOCK_DELIMITER_SIZE;
+
+/// Length of the longest packet the synchronizer can collect.
+pub const MAX_FRAME_LEN: usize = MAX_LAYOUT_TOTAL_LEN;
+
+const _: () = assert!(
+    DELTA_MAX_TOTAL_LEN <= MAX_FRAME_LEN
+        && TOTAL_MESSAGE_LENGTH_BYTES <= MAX_FRAME_LEN
+        && MARKER_END < DELTA_WIDTH_FIELD_END
+        && DELTA_WIDTH_FIELD_END <= DELTA_MIN_BARE_LEN
+        && LAYOUT_HEADER_LENGTH_BYTES <= MARKER_END,
+    "packet_does_not_fit_the_sync_buffer"
+);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncState {
+    /// Shifting bits in, looking for [SYNC_WORD].
+    Hunting,
+    /// Sync word found, collecting the rest of the packet.
+    Collecting,
+}
+
+/// A packet collected by [FrameSynchronizer], as long as its layout, or a delta packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SyncedFrame {
+    data: [u8; MAX_FRAME_LEN],
+    len: usize,
+}
+
+impl SyncedFrame {
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Finds [START_HEADER_DATA] at any bit offset in a demodulated bitstream, then collects the
+/// packet it begins: as long as its width field says if the marker after the sync word is closest
+/// to [DELTA_MARKER] (see [crate::delta::delta_packet_len]), [TOTAL_MESSAGE_LENGTH_BYTES] for a
+/// fragment, otherwise the
+/// [LayoutInfo::total_len](crate::layout::LayoutInfo::total_len) of the layout whose ID follows
+/// the sync word. Packets with an unknown ID are collected at [TOTAL_MESSAGE_LENGTH_BYTES], for the
+/// decoder to reject.
+///
+/// Bits are expected most-significant-bit first within each byte. Up to `max_bit_errors` bits of
+/// the sync word may be wrong; the received (not the expected) sync bits are kept in the output,
+/// so that the decoder sees and reports them.
+///
+/// On a whitened link (see [FrameSynchronizer::with_whitening]) the synchronizer looks for the
+/// whitened sync word, and de-whitens the packet as it collects it, so packets come out as they
+/// were before whitening.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameSynchronizer {
+    max_bit_errors: u32,
+    whitening: Whitening,
+    sync_word: u64,
+    whitener: BitWhitener,
+    state: SyncState,
+    shift_register: u64,
+    bits_in_register: usize,
+    buffer: [u8; MAX_FRAME_LEN],
+    byte_index: usize,
+    bit_index: u8,
+    /// Length of the packet being collected, known once the layout ID and marker have been read.
+    frame_len: usize,
+    last_sync_errors: u32,
+}
+
+impl FrameSynchronizer {
+    pub const fn new(max_bit_errors: u8) -> Self {
+        Self::with_whitening(max_bit_errors, Whitening::None)
+    }
+
+    /// For a link whose packets are whitened with `whitening`.
+    pub const fn with_whitening(max_bit_errors: u8, whitening: Whitening) -> Self {
+        Self {
+            max_bit_errors: max_bit_errors as u32,
+            whitening,
+            sync_word: whitened_sync_word(whitening),
+            whitener: BitWhitener::new(whitening),
+            state: SyncState::Hunting,
+            shift_register: 0,
+            bits_in_register: 0,
+            buffer: [0u8; MAX_FRAME_LEN],
+            byte_index: 0,
+            bit_index: 0,
+            frame_len: TOTAL_MESSAGE_LENGTH_BYTES,
+            last_sync_errors: 0,
+        }
+    }
+
+    pub const fn state(&self) -> SyncState {
+        self.state
+    }
+
+    /// Number of bit errors in the sync word of the most recently found frame.
+    pub const fn last_sync_errors(&self) -> u32 {
+        self.last_sync_errors
+    }
+
+    /// Drops any partially collected frame and goes back to hunting.
+    pub const fn reset(&mut self) {
+        self.state = SyncState::Hunting;
+        self.shift_register = 0;
+        self.bits_in_register = 0;
+        self.byte_index = 0;
+        self.bit_index = 0;
+        self.frame_len = TOTAL_MESSAGE_LENGTH_BYTES;
+    }
+
+    /// Feeds one bit. Returns the full packet once its final bit has been received.
+    pub const fn push_bit(&mut self, bit: bool) -> Option<SyncedFrame> {
+        match self.state {
+            SyncState::Hunting => {
+                self.shift_register = (self.shift_register << 1) | bit as u64;
+                if self.bits_in_register < SYNC_WORD_BITS {
+                    self.bits_in_register += 1;
+                }
+
+                let _errors = (self.shift_register ^ self.sync_word).count_ones();
+                if self.bits_in_register == SYNC_WORD_BITS && _errors <= self.max_bit_errors {
+                    self.last_sync_errors = _errors;
+                    self.whitener = BitWhitener::new(self.whitening);
+                    let mut _sync: u64 = 0;
+                    let mut i: usize = 0;
+                    while i < SYNC_WORD_BITS {
+                        let _bit = (self.shift_register >> (SYNC_WORD_BITS - 1 - i)) & 1 != 0;
+                        _sync = (_sync << 1) | self.whitener.dewhiten_bit(_bit) as u64;
+                        i += 1;
+                    }
+                    let _sync_bytes = _sync.to_be_bytes();
+                    let mut i: usize = 0;
+                    while i < _sync_bytes.len() {
+                        self.buffer[i] = _sync_bytes[i];
+                        i += 1;
+                    }
+                    self.byte_index = START_HEADER_DATA.len();
+                    self.bit_index = 0;
+                    self.state = SyncState::Collecting;
+                }
+                None
+            }
+            SyncState::Collecting => {
+                let bit = self.whitener.dewhiten_bit(bit);
+                if self.bit_index == 0 {
+                    self.buffer[self.byte_index] = 0;
+                }
+                self.buffer[self.byte_index] |= (bit as u8) << (7 - self.bit_index);
+                self.bit_index += 1;
+
+                if self.bit_index == 8 {
+                    self.bit_index = 0;
+                    self.byte_index += 1;
+                    if self.byte_index == LAYOUT_HEADER_LENGTH_BYTES {
+                        if let Some(_layout) =
+                            PacketLayout::from_id(self.buffer[START_HEADER_DATA.len()])
+                        {
+                            self.frame_len = layout_info(_layout).total_len;
+                        }
+                    }
+                    if self.byte_index == MARKER_END {
+                        let _marker = self.buffer.split_at(MARKER_END).0;
+                        if is_delta(_marker) {
+                            // refined once the width field is in
+                            self.frame_len = DELTA_MAX_TOTAL_LEN;
+                        } else if has_fragment_marker(_marker) {
+                            self.frame_len = TOTAL_MESSAGE_LENGTH_BYTES;
+                        }
+                    }
+                    if self.byte_index == DELTA_WIDTH_FIELD_END
+                        && is_delta(self.buffer.split_at(MARKER_END).0)
+                    {
+                        self.frame_len = delta_packet_len(self.buffer.split_at(self.byte_index).0);
+                    }
+                    if self.byte_index == self.frame_len {
+                        let _frame = SyncedFrame {
+                            data: self.buffer,
+                            len: self.frame_len,
+                        };
+                        self.reset();
+                        return Some(_frame);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Feeds eight bits, most-significant bit first. At most one packet can complete per byte.
+    pub const fn push_byte(&mut self, byte: u8) -> Option<SyncedFrame> {
+        let mut _frame: Option<SyncedFrame> = None;
+        let mut i: u8 = 0;
+        while i < 8 {
+            if let Some(_completed) = self.push_bit((byte >> (7 - i)) & 1 != 0) {
+                _frame = Some(_completed);
+            }
+            i += 1;
+        }
+        _frame
+    }
+
+    /// Like [FrameSynchronizer::push_bit], but hands a completed packet straight to
+    /// [decode_any_packet].
+    pub fn push_bit_and_decode(&mut self, bit: bool) -> Option<Result<DecodedPacket, DecodeError>> {
+        self.push_bit(bit)
+            .map(|_frame| decode_any_packet(_frame.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Authenticator, AUTHENTICATED_MESSAGE_LENGTH_BYTES};
+    use crate::delta::{encode_delta_packet, DeltaPacket};
+    use crate::qpacket::{decode_qpacket, QPacketEncoder, QPACKET_FULL_LEN, USER_DATA_BLOCKS};
+    use crate::telemetry::{
+        decode_packet, make_packet_skeleton, make_packet_skeleton_nofec,
+        make_packet_skeleton_with_profile,
+    };
+
+    fn feed_bytes(
+        _sync: &mut FrameSynchronizer,
+        _bytes: &[u8],
+        _offset: usize,
+    ) -> Option<SyncedFrame> {
+        let mut _frame: Option<SyncedFrame> = None;
+        // some leading noise, so that the packet starts at a non-byte-aligned offset
+        for i in 0.._offset {
+            assert!(_sync.push_bit(i % 3 == 0).is_none());
+        }
+        for _byte in _bytes {
+            if let Some(_completed) = _sync.push_byte(*_byte) {
+                _frame = Some(_completed);
+            }
+        }
+        _frame
+    }
+
+    #[test]
+    fn test_sync_unaligned() {
+        let _packet = make_packet_skeleton(true);
+        let mut _sync = FrameSynchronizer::new(0);
+        let _frame = feed_bytes(&mut _sync, &_packet, 13).unwrap();
+        assert_eq!(_frame.as_bytes(), _packet);
+        assert_eq!(_sync.state(), SyncState::Hunting);
+    }
+
+    #[test]
+    fn test_sync_bit_errors() {
+        let _packet = make_packet_skeleton(false);
+        let mut _damaged_packet = _packet;
+        _damaged_packet[0] ^= 0b0001_0000;
+        _damaged_packet[5] ^= 0b1000_0000;
+
+        let mut _strict_sync = FrameSynchronizer::new(1);
+        assert!(feed_bytes(&mut _strict_sync, &_damaged_packet, 5).is_none());
+
+        let mut _sync = FrameSynchronizer::new(2);
+        let _frame = feed_bytes(&mut _sync, &_damaged_packet, 5).unwrap();
+        assert_eq!(_frame.as_bytes(), _damaged_packet);
+        assert_eq!(_sync.last_sync_errors(), 2);
+
+        let (_decoded, _report) = decode_packet(_frame.as_bytes(), &[]).unwrap();
+        assert_eq!(_decoded, _packet[..BARE_MESSAGE_LENGTH_BYTES]);
+        assert_eq!(_report.positions(), [0, 5]);
+    }
+
+    #[test]
+    fn test_sync_qpacket_then_telemetry() {
+        let _qpacket = QPacketEncoder::new().encode_values(1_400_000_000, &[0.0; USER_DATA_BLOCKS]);
+        let _telemetry = make_packet_skeleton(true);
+        let mut _stream = [0u8; QPACKET_FULL_LEN + TOTAL_MESSAGE_LENGTH_BYTES];
+        _stream[..QPACKET_FULL_LEN].copy_from_slice(&_qpacket);
+        _stream[QPACKET_FULL_LEN..].copy_from_slice(&_telemetry);
+        // a bit error in the qpacket's layout ID still gets the qpacket's length
+        _stream[START_HEADER_DATA.len()] ^= 0b0000_1000;
+
+        let mut _sync = FrameSynchronizer::new(0);
+        for i in 0..11 {
+            assert!(_sync.push_bit(i % 3 == 0).is_none());
+        }
+        let mut _decoded = [None, None];
+        let mut _count: usize = 0;
+        for _byte in _stream {
+            for i in 0..8 {
+                if let Some(_result) = _sync.push_bit_and_decode((_byte >> (7 - i)) & 1 != 0) {
+                    _decoded[_count] = Some(_result);
+                    _count += 1;
+                }
+            }
+        }
+        assert_eq!(_count, 2);
+        assert_eq!(
+            _decoded[0],
+            Some(Ok(DecodedPacket::QPacket(
+                decode_qpacket(&_qpacket).unwrap()
+            )))
+        );
+        assert!(matches!(
+            _decoded[1],
+            Some(Ok(DecodedPacket::Telemetry(_packet, _)))
+                if _packet == _telemetry[..BARE_MESSAGE_LENGTH_BYTES]
+        ));
+    }
+
+    #[test]
+    fn test_sync_whitened() {
+        let _telemetry = make_packet_skeleton(false);
+        let _qpacket = QPacketEncoder::new().encode_values(1_400_000_000, &[0.0; USER_DATA_BLOCKS]);
+        for _whitening in [Whitening::G3ruh, Whitening::Pn9] {
+            let mut _first = _telemetry;
+            _whitening.whiten(&mut _first);
+            let mut _second = _qpacket;
+            _whitening.whiten(&mut _second);
+            let mut _stream = [0u8; TOTAL_MESSAGE_LENGTH_BYTES + QPACKET_FULL_LEN];
+            _stream[..TOTAL_MESSAGE_LENGTH_BYTES].copy_from_slice(&_first);
+            _stream[TOTAL_MESSAGE_LENGTH_BYTES..].copy_from_slice(&_second);
+            // a bit error in the sync word, and one in the body
+            _stream[2] ^= 0b0010_0000;
+            _stream[30] ^= 0b0000_0001;
+
+            let mut _plain = FrameSynchronizer::new(2);
+            assert!(feed_bytes(&mut _plain, &_stream, 3).is_none());
+
+            let mut _sync = FrameSynchronizer::with_whitening(2, _whitening);
+            for i in 0..5 {
+                assert!(_sync.push_bit(i % 2 == 0).is_none());
+            }
+            let mut _decoded = [None, None];
+            let mut _count: usize = 0;
+            for _byte in _stream {
+                for i in 0..8 {
+                    if let Some(_result) = _sync.push_bit_and_decode((_byte >> (7 - i)) & 1 != 0) {
+                        _decoded[_count] = Some(_result);
+                        _count += 1;
+                    }
+                }
+            }
+            assert_eq!(_count, 2, "{:?}", _whitening);
+            assert!(matches!(
+                _decoded[0],
+                Some(Ok(DecodedPacket::Telemetry(_packet, _)))
+                    if _packet == make_packet_skeleton_nofec(false)
+            ));
+            assert_eq!(
+                _decoded[1],
+                Some(Ok(DecodedPacket::QPacket(
+                    decode_qpacket(&_qpacket).unwrap()
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn test_sync_delta_then_keyframe() {
+        let _keyframe = make_packet_skeleton_nofec(false);
+        let _delta = DeltaPacket::new(&_keyframe, &make_packet_skeleton_nofec(true));
+        let _encoded = encode_delta_packet(&_delta);
+        let _delta_len = _encoded.as_bytes().len();
+        assert!(_delta_len < DELTA_MAX_TOTAL_LEN);
+        let mut _stream = [0u8; DELTA_MAX_TOTAL_LEN + TOTAL_MESSAGE_LENGTH_BYTES];
+        _stream[.._delta_len].copy_from_slice(_encoded.as_bytes());
+        _stream[_delta_len.._delta_len + TOTAL_MESSAGE_LENGTH_BYTES]
+            .copy_from_slice(&make_packet_skeleton(false));
+        // a bit error in the delta marker still gets the delta packet's length
+        _stream[START_HEADER_DATA.len()] ^= 0b0100_0000;
+
+        let mut _sync = FrameSynchronizer::new(0);
+        for i in 0..7 {
+            assert!(_sync.push_bit(i % 2 == 0).is_none());
+        }
+        let mut _decoded = [None, None];
+        let mut _count: usize = 0;
+        for _byte in _stream {
+            if let Some(_frame) = _sync.push_byte(_byte) {
+                _decoded[_count] = Some(decode_any_packet(_frame.as_bytes()));
+                _count += 1;
+            }
+        }
+        assert_eq!(_count, 2);
+        assert_eq!(_decoded[0], Some(Ok(DecodedPacket::Delta(_delta))));
+        assert!(matches!(
+            _decoded[1],
+            Some(Ok(DecodedPacket::Telemetry(_packet, _))) if _packet == _keyframe
+        ));
+    }
+
+    #[test]
+    fn test_sync_profiled_and_signed() {
+        let _heavy = make_packet_skeleton_with_profile(true, FecProfile::Heavy);
+        let _heavy = _heavy.as_bytes();
+        let _signed =
+            Authenticator::new([0x42; 16], 1).sign_packet(&make_packet_skeleton_nofec(false));
+        let _telemetry = make_packet_skeleton(true);
+        let mut _stream = [0u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES
+            + AUTHENTICATED_MESSAGE_LENGTH_BYTES
+            + TOTAL_MESSAGE_LENGTH_BYTES];
+        let mut i: usize = 0;
+        for _packet in [_heavy, &_signed[..], &_telemetry[..]] {
+            _stream[i..i + _packet.len()].copy_from_slice(_packet);
+            i += _packet.len();
+        }
+        assert!(_heavy.len() > TOTAL_MESSAGE_LENGTH_BYTES);
+        // a bit error in each layout ID
+        _stream[START_HEADER_DATA.len()] ^= 0b0000_0100;
+        _stream[_heavy.len() + START_HEADER_DATA.len()] ^= 0b0010_0000;
+
+        let mut _sync = FrameSynchronizer::new(0);
+        let mut _lengths = [0usize; 3];
+        let mut _decoded = [None, None, None];
+        let mut _count: usize = 0;
+        for _byte in _stream {
+            if let Some(_frame) = _sync.push_byte(_byte) {
+                _lengths[_count] = _frame.len();
+                _decoded[_count] = Some(decode_any_packet(_frame.as_bytes()));
+                _count += 1;
+            }
+        }
+        assert_eq!(_count, 3);
+        assert_eq!(
+            _lengths,
+            [
+                _heavy.len(),
+                AUTHENTICATED_MESSAGE_LENGTH_BYTES,
+                TOTAL_MESSAGE_LENGTH_BYTES
+            ]
+        );
+        assert!(matches!(
+            _decoded[0],
+            Some(Ok(DecodedPacket::ProfiledTelemetry(_packet, FecProfile::Heavy, _)))
+                if _packet == make_packet_skeleton_nofec(true)
+        ));
+        assert!(matches!(
+            _decoded[1],
+            Some(Ok(DecodedPacket::AuthenticatedTelemetry(_packet, _, _)))
+                if _packet == make_packet_skeleton_nofec(false)
+        ));
+        assert!(matches!(
+            _decoded[2],
+            Some(Ok(DecodedPacket::Telemetry(_, _)))
+        ));
+    }
+}
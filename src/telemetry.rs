@@ -1,10 +1,13 @@
+use core::fmt;
 use core::intrinsics::*;
 use core::option::Option::Some;
 
 use crate::parameters::*;
 use crate::{generate_packet, generate_packet_no_fec};
 
-use reed_solomon::{Decoder, Encoder};
+use crate::fec::{Fec, ReedSolomon, DEFAULT_FEC, MAX_FEC_CODEWORD_LENGTH_BYTES};
+
+use crc::{Crc, NoTable, CRC_16_IBM_3740};
 
 /// Makes a blank packet (with valid FEC) of size [TotalMessage] that is all-zeroes if `false` and all-ones if `true`.
 /// If a packet without FEC is desired or can be used, [make_packet_skeleton_nofec] will generate such a packet (sans
@@ -17,6 +20,15 @@ pub fn make_packet_skeleton(_type: bool) -> TotalMessage {
     generate_packet(_blockstackdata)
 }
 
+/// Like [make_packet_skeleton], but encoded with the given [FecProfile].
+pub fn make_packet_skeleton_with_profile(_type: bool, _profile: FecProfile) -> ProfiledMessage {
+    let _blockstackdata = match _type {
+        true => MAX_BLOCKSTACKDATA,
+        false => MIN_BLOCKSTACKDATA,
+    };
+    encode_packet_with_profile(&generate_packet_no_fec(_blockstackdata), _profile)
+}
+
 /// Like [make_packet_skeleton], generates an all-zeroes if `false` or all-ones if `true` [BareMessage] (without FEC).
 /// Because it does not have FEC, this packet can be generated at compile-time.
 pub const fn make_packet_skeleton_nofec(_type: bool) -> BareMessage {
@@ -86,7 +98,7 @@ impl Block {
 
 pub type BlockStackData = [[u8; 4]; BLOCK_STACK_DATA_COUNT];
 
-pub type PacketDecodedData = [f32; BLOCK_STACK_DATA_COUNT];
+pub type PacketDecodedData = [f64; BLOCK_STACK_DATA_COUNT];
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -119,7 +131,7 @@ pub const fn construct_blocks(_data: &BlockStackData) -> BlockStack {
 
     const _START_HEADER_BLOCK: Block = Block {
         label: FIRST_BLOCK_LABEL,
-        data: BlockData::StaticData(Some(&START_HEADER_DATA)),
+        data: BlockData::StaticData(Some(&TELEMETRY_HEADER_DATA)),
         do_transmit_label: false,
     };
     data_location += 1;
@@ -158,6 +170,18 @@ pub const fn construct_blocks(_data: &BlockStackData) -> BlockStack {
         data: BlockData::DynData(Some(_data[data_location - 1])),
         do_transmit_label: true,
     };
+    data_location += 1;
+    let _sequence_block = Block {
+        label: FIRST_BLOCK_LABEL + data_location as u8,
+        data: BlockData::DynData(Some(_data[data_location - 1])),
+        do_transmit_label: true,
+    };
+    data_location += 1;
+    let _gps_time_block = Block {
+        label: FIRST_BLOCK_LABEL + data_location as u8,
+        data: BlockData::DynData(Some(_data[data_location - 1])),
+        do_transmit_label: true,
+    };
     const _END_HEADER_BLOCK: Block = Block {
         label: FIRST_BLOCK_LABEL + BLOCK_STACK_DATA_COUNT as u8,
         data: BlockData::StaticData(Some(&END_HEADER_DATA)),
@@ -173,6 +197,8 @@ pub const fn construct_blocks(_data: &BlockStackData) -> BlockStack {
             _temperature_block,
             _latitude_block,
             _longitude_block,
+            _sequence_block,
+            _gps_time_block,
             _END_HEADER_BLOCK,
         ],
     }
@@ -198,11 +224,20 @@ pub const fn construct_packet(_blockstack: BlockStack) -> BareMessage {
             packet_index += 1;
         }
 
-        let _blockdata = block.data.get_data();
-
-        packet[packet_index..(packet_index + block.data.len() as usize)]
-            .copy_from_slice(_blockdata);
-        packet_index += block.data.len() as usize;
+        // dynamic data is padded to 4 bytes, of which the block's type may use fewer
+        let _blockdata = match block.data.which_type() {
+            true => {
+                block
+                    .data
+                    .get_data()
+                    .split_at(BLOCK_TYPE_STACK[i - 1].len())
+                    .0
+            }
+            false => block.data.get_data(),
+        };
+
+        packet[packet_index..(packet_index + _blockdata.len())].copy_from_slice(_blockdata);
+        packet_index += _blockdata.len();
         //packet_index += block.length as usize;
 
         packet[packet_index] = BLOCK_DELIMITER.to_le_bytes()[0];
@@ -214,11 +249,98 @@ pub const fn construct_packet(_blockstack: BlockStack) -> BareMessage {
     packet
 }
 
-/// Encodes the given packet using the reed_solomon crate. Returns the encoded packet.
+/// Encodes the given packet with [DEFAULT_FEC]. Returns the encoded packet.
 pub fn encode_packet(_bare_packet: &BareMessage) -> TotalMessage {
-    let enc = Encoder::new(FEC_EXTRA_BYTES);
-    let _encoded_packet = enc.encode(&_bare_packet[..]);
-    _encoded_packet[..].try_into().unwrap()
+    let mut output: TotalMessage = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
+    encode_packet_with(_bare_packet, &DEFAULT_FEC, &mut output);
+    output
+}
+
+/// Encodes the given packet with any [Fec] into `_output`, which must be
+/// `_fec.encoded_len(BARE_MESSAGE_LENGTH_BYTES)` long. See [decode_packet_with].
+///
+/// Unless `_fec` is [DEFAULT_FEC], receivers cannot frame the result from the sync word alone;
+/// send it inside an outer sync or length layer (see [Fec]).
+pub fn encode_packet_with<F: Fec>(_bare_packet: &BareMessage, _fec: &F, _output: &mut [u8]) {
+    _fec.encode(&_bare_packet[..], _output);
+}
+
+/// A packet encoded with a [FecProfile]. Its length depends on the profile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProfiledMessage {
+    data: ProfiledMessageArray,
+    profile: FecProfile,
+}
+
+impl ProfiledMessage {
+    pub const fn profile(&self) -> FecProfile {
+        self.profile
+    }
+
+    pub const fn len(&self) -> usize {
+        self.profile.total_len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len()]
+    }
+}
+
+/// Encodes the given packet with the parity length of `_profile`, tagging it so the receiver
+/// knows which profile was used. The packet is sent with the [PacketLayout::ProfiledTelemetry] ID
+/// of its profile in place of the telemetry one. See [decode_profiled_packet].
+pub fn encode_packet_with_profile(
+    _bare_packet: &BareMessage,
+    _profile: FecProfile,
+) -> ProfiledMessage {
+    let mut _tagged_packet: [u8; BARE_MESSAGE_LENGTH_BYTES + FEC_PROFILE_TAG_SIZE] =
+        [0u8; BARE_MESSAGE_LENGTH_BYTES + FEC_PROFILE_TAG_SIZE];
+    _tagged_packet[..BARE_MESSAGE_LENGTH_BYTES].copy_from_slice(_bare_packet);
+    _tagged_packet[START_HEADER_DATA.len()] = PacketLayout::ProfiledTelemetry(_profile).id();
+    _tagged_packet[BARE_MESSAGE_LENGTH_BYTES] = _profile.tag();
+
+    let mut output = ProfiledMessage {
+        data: [0u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES],
+        profile: _profile,
+    };
+    ReedSolomon::new(_profile.extra_bytes()).encode(
+        &_tagged_packet[..],
+        &mut output.data[.._profile.total_len()],
+    );
+    output
+}
+
+/// CRC-16/CCITT (the `0x1021` polynomial, initial value `0xFFFF`), as used by [payload_crc].
+const PAYLOAD_CRC: Crc<NoTable<u16>> = Crc::<NoTable<u16>>::new(&CRC_16_IBM_3740);
+
+pub const PAYLOAD_CRC_SIZE: usize = core::mem::size_of::<u16>();
+pub const CHECKED_MESSAGE_LENGTH_BYTES: usize =
+    BARE_MESSAGE_LENGTH_BYTES + PAYLOAD_CRC_SIZE + FEC_EXTRA_BYTES;
+pub type CheckedMessage = [u8; CHECKED_MESSAGE_LENGTH_BYTES];
+
+/// CRC over the data of each block in [BLOCK_IDENT_STACK]. Headers, labels and delimiters are left
+/// out, as [decode_packet] restores those from the skeleton regardless of what was received.
+pub fn payload_crc(_bare_packet: &BareMessage) -> u16 {
+    let mut _digest = PAYLOAD_CRC.digest();
+    for _ident in BLOCK_IDENT_STACK {
+        _digest.update(&_bare_packet[_ident.beginning_location.._ident.end_location]);
+    }
+    _digest.finalize()
+}
+
+/// Encodes the given packet followed by its [payload_crc], with [FEC_EXTRA_BYTES] of parity over
+/// both, and the [PacketLayout::CheckedTelemetry] ID in place of the telemetry one. See
+/// [decode_checked_packet].
+pub fn encode_packet_with_crc(_bare_packet: &BareMessage) -> CheckedMessage {
+    let mut _checked_packet = [0u8; BARE_MESSAGE_LENGTH_BYTES + PAYLOAD_CRC_SIZE];
+    _checked_packet[..BARE_MESSAGE_LENGTH_BYTES].copy_from_slice(_bare_packet);
+    _checked_packet[START_HEADER_DATA.len()] = PacketLayout::CheckedTelemetry.id();
+    _checked_packet[BARE_MESSAGE_LENGTH_BYTES..]
+        .copy_from_slice(&payload_crc(_bare_packet).to_be_bytes());
+
+    let mut output: CheckedMessage = [0u8; CHECKED_MESSAGE_LENGTH_BYTES];
+    DEFAULT_FEC.encode(&_checked_packet[..], &mut output);
+    output
 }
 
 // pub fn decimal_to_dms(decimal_degrees: f32) -> (i16, u8, f32) {
@@ -294,83 +416,669 @@ pub fn find_packet_similarities() -> (BareMessage, BareMessage) {
     (packet_bitmask, bare_packet)
 }
 
-pub fn decode_packet(_packet: TotalMessage, _known_erasures: &[u8]) -> BareMessage {
-    // TODO: this entire function needs to be completely refactored
+/// Reasons [decode_packet] can fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The packet had more errors than [FEC_EXTRA_BYTES] of parity can correct.
+    Uncorrectable,
+    /// The packet corrected cleanly, but does not begin with [START_HEADER_DATA].
+    BadHeader,
+    /// The packet was not [TOTAL_MESSAGE_LENGTH_BYTES] long, or an erasure pointed outside of it.
+    BadLength,
+    /// More erasures were given than [FEC_EXTRA_BYTES] of parity can fill in.
+    TooManyErasures,
+    /// The [FecProfile] tag was not within one bit of any known profile.
+    UnknownProfile,
+    /// A delta packet arrived, but the keyframe it was taken against was never received.
+    MissingKeyframe,
+    /// The layout ID was not within one bit of any known [PacketLayout], or named a different
+    /// layout than the one being decoded.
+    UnknownLayout,
+}
 
-    let mut _packet_fec: [u8; FEC_EXTRA_BYTES] = [0u8; FEC_EXTRA_BYTES];
-    let mut _packet_data: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
-    let mut _reconstructed_array: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
-    let mut _mask: u8;
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Uncorrectable => write!(f, "Packet has too many errors to correct"),
+            Self::BadHeader => write!(f, "Packet header does not match"),
+            Self::BadLength => write!(f, "Packet length does not match"),
+            Self::TooManyErasures => write!(f, "Too many erasures for available FEC"),
+            Self::UnknownProfile => write!(f, "Unrecognized FEC profile"),
+            Self::MissingKeyframe => write!(f, "Keyframe for delta packet was not received"),
+            Self::UnknownLayout => write!(f, "Unrecognized packet layout"),
+        }
+    }
+}
 
-    _packet_data.clone_from_slice(&_packet[..BARE_MESSAGE_LENGTH_BYTES]);
-    _packet_fec.clone_from_slice(&_packet[BARE_MESSAGE_LENGTH_BYTES..]); // there is probably a better way of doing this
+/// Describes what [decode_packet] had to change to recover a packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CorrectionReport {
+    /// Number of symbols (bytes) that differ between the received and recovered packet.
+    pub corrected: usize,
+    /// Number of static bytes (header, callsign, labels, delimiters) that arrived damaged.
+    /// This is a cheap channel-quality estimate that does not depend on FEC succeeding.
+    pub damaged_static: usize,
+    /// Number of erasures [infer_erasures] contributed to the successful correction.
+    pub inferred_erasures: usize,
+    positions: [u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES],
+}
 
-    debug_assert_eq!(_packet_data.len() + _packet_fec.len(), _packet.len());
-    if (_packet_data.len() + _packet_fec.len()) != _packet.len() {
-        unreachable!()
+impl CorrectionReport {
+    pub const fn new() -> Self {
+        Self {
+            corrected: 0,
+            damaged_static: 0,
+            inferred_erasures: 0,
+            positions: [0u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES],
+        }
+    }
+
+    /// Returns the indices (into the received packet) of every corrected symbol. Only the first
+    /// [PROFILED_MESSAGE_MAX_LENGTH_BYTES] are kept, which only matters for long codes such as
+    /// [crate::fec::Golay24].
+    pub fn positions(&self) -> &[u8] {
+        &self.positions[..self.corrected.min(self.positions.len())]
+    }
+}
+
+/// A list of up to `N` erasure positions. By default, sized for the most erasures
+/// [FEC_EXTRA_BYTES] of parity can fill in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ErasureList<const N: usize = FEC_EXTRA_BYTES> {
+    /// Number of static bytes that did not match the packet skeleton.
+    pub damaged_static: usize,
+    positions: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ErasureList<N> {
+    pub const fn new() -> Self {
+        Self {
+            damaged_static: 0,
+            positions: [0u8; N],
+            len: 0,
+        }
+    }
+
+    /// Adds `_position` if it is not already present. Returns `false` if the list is full.
+    pub fn push(&mut self, _position: u8) -> bool {
+        if self.as_slice().contains(&_position) {
+            return true;
+        }
+        if self.len >= N {
+            return false;
+        }
+        self.positions[self.len] = _position;
+        self.len += 1;
+        true
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.positions[..self.len]
     }
+}
 
+/// Compares the static bytes of a received packet (see [find_packet_similarities]) against the
+/// packet skeleton, and infers erasures from the damage.
+///
+/// Static bytes themselves never need erasing, since [decode_packet] restores them outright. Instead,
+/// a run of data bytes is marked as erased when the static bytes on *both* sides of it arrived
+/// damaged, as the run was then almost certainly inside the same burst. Erasures cost half as much
+/// parity as unknown errors, so this stretches how long a burst RS can fix.
+///
+/// If more erasures are inferred than fit in `N`, the list is truncated.
+pub fn infer_erasures<const N: usize>(_packet: &[u8]) -> ErasureList<N> {
     let (_packet_bitmask, _bare_packet): (BareMessage, BareMessage) = find_packet_similarities();
+    let mut _erasures: ErasureList<N> = ErasureList::new();
+    let mut _damaged: [bool; BARE_MESSAGE_LENGTH_BYTES] = [false; BARE_MESSAGE_LENGTH_BYTES];
 
-    for i in 0..BARE_MESSAGE_LENGTH_BYTES {
-        _mask = _packet_bitmask[i];
+    for i in 0..BARE_MESSAGE_LENGTH_BYTES.min(_packet.len()) {
+        if _packet_bitmask[i] == 0 && _packet[i] != _bare_packet[i] {
+            _damaged[i] = true;
+            _erasures.damaged_static += 1;
+        }
+    }
+
+    // static byte most recently seen, and whether it was damaged
+    let mut _previous_static_damaged: Option<bool> = None;
+    let mut _run_start: usize = 0;
 
-        match _mask {
-            0 => _reconstructed_array[i] = _bare_packet[i],
-            _ => _reconstructed_array[i] = _packet[i],
+    for i in 0..BARE_MESSAGE_LENGTH_BYTES.min(_packet.len()) {
+        if _packet_bitmask[i] != 0 {
+            continue;
         }
+        if _previous_static_damaged == Some(true) && _damaged[i] {
+            for _position in _run_start..i {
+                _erasures.push(_position as u8);
+            }
+        }
+        _previous_static_damaged = Some(_damaged[i]);
+        _run_start = i + 1;
+    }
+
+    _erasures
+}
+
+/// Recovers a [BareMessage] from a received packet, first restoring the bytes known to be
+/// static (see [find_packet_similarities]) and then running Reed-Solomon correction.
+///
+/// Erasures inferred by [infer_erasures] are merged with `_known_erasures`. Should correction fail
+/// with them, it is retried with only `_known_erasures`, in case the inference was wrong.
+///
+/// On success, also returns a [CorrectionReport] describing which symbols were changed.
+pub fn decode_packet(
+    _packet: &[u8],
+    _known_erasures: &[u8],
+) -> Result<(BareMessage, CorrectionReport), DecodeError> {
+    if _packet.len() != TOTAL_MESSAGE_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+    recover_bare_message(
+        _packet,
+        &DEFAULT_FEC,
+        PacketLayout::Telemetry,
+        None,
+        _known_erasures,
+        &mut [],
+    )
+}
+
+/// Like [decode_packet], but for packets made by [encode_packet_with] with the same `_fec`.
+///
+/// Static bytes can only be restored, and erasures inferred, when `_fec` is systematic; otherwise
+/// this is plain decoding followed by the header check.
+pub fn decode_packet_with<F: Fec>(
+    _packet: &[u8],
+    _fec: &F,
+    _known_erasures: &[u8],
+) -> Result<(BareMessage, CorrectionReport), DecodeError> {
+    if _packet.len() != _fec.encoded_len(BARE_MESSAGE_LENGTH_BYTES) {
+        return Err(DecodeError::BadLength);
+    }
+    recover_bare_message(
+        _packet,
+        _fec,
+        PacketLayout::Telemetry,
+        None,
+        _known_erasures,
+        &mut [],
+    )
+}
+
+/// Like [decode_packet], but for packets made by [encode_packet_with_profile]. The parity length
+/// is taken from the profile tag, and anything after the end of the packet is ignored.
+pub fn decode_profiled_packet(
+    _packet: &[u8],
+    _known_erasures: &[u8],
+) -> Result<(BareMessage, FecProfile, CorrectionReport), DecodeError> {
+    if _packet.len() <= BARE_MESSAGE_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
     }
+    let _profile = FecProfile::from_tag(_packet[BARE_MESSAGE_LENGTH_BYTES])
+        .ok_or(DecodeError::UnknownProfile)?;
+    if _packet.len() < _profile.total_len() {
+        return Err(DecodeError::BadLength);
+    }
+
+    let (_recovered_packet, _report) = recover_bare_message(
+        &_packet[.._profile.total_len()],
+        &ReedSolomon::new(_profile.extra_bytes()),
+        PacketLayout::ProfiledTelemetry(_profile),
+        Some(_profile.tag()),
+        _known_erasures,
+        &mut [],
+    )?;
+    Ok((_recovered_packet, _profile, _report))
+}
+
+/// Whether a packet's data still matches its [payload_crc] after correction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PayloadIntegrity {
+    /// Corrected (if needed) and verified.
+    Verified,
+    /// Reed-Solomon produced a valid codeword, but not the one that was sent: the packet had more
+    /// errors than the parity could fix, and was miscorrected. Its values should not be trusted.
+    CrcMismatch,
+}
 
-    let mut _packet_data_full: TotalMessage = [0u8; TOTAL_MESSAGE_LENGTH_BYTES];
-    _packet_data_full[0..BARE_MESSAGE_LENGTH_BYTES].clone_from_slice(&_reconstructed_array);
-    _packet_data_full[BARE_MESSAGE_LENGTH_BYTES..TOTAL_MESSAGE_LENGTH_BYTES]
-        .clone_from_slice(&_packet[BARE_MESSAGE_LENGTH_BYTES..TOTAL_MESSAGE_LENGTH_BYTES]);
+impl PayloadIntegrity {
+    pub const fn is_verified(&self) -> bool {
+        matches!(self, Self::Verified)
+    }
+}
+
+/// Like [decode_packet], but for packets made by [encode_packet_with_crc]. The CRC is checked
+/// after correction, so that miscorrections are reported as [PayloadIntegrity::CrcMismatch]
+/// instead of being handed back as good data.
+pub fn decode_checked_packet(
+    _packet: &[u8],
+    _known_erasures: &[u8],
+) -> Result<(BareMessage, PayloadIntegrity, CorrectionReport), DecodeError> {
+    if _packet.len() != CHECKED_MESSAGE_LENGTH_BYTES {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut _crc = [0u8; PAYLOAD_CRC_SIZE];
+    let (_recovered_packet, _report) = recover_bare_message(
+        _packet,
+        &DEFAULT_FEC,
+        PacketLayout::CheckedTelemetry,
+        None,
+        _known_erasures,
+        &mut _crc,
+    )?;
+    let _integrity = if u16::from_be_bytes(_crc) == payload_crc(&_recovered_packet) {
+        PayloadIntegrity::Verified
+    } else {
+        PayloadIntegrity::CrcMismatch
+    };
+    Ok((_recovered_packet, _integrity, _report))
+}
+
+/// Shared body of [decode_packet], [decode_packet_with] and [decode_profiled_packet]. `_packet`
+/// must be exactly one codeword of `_fec` long; if `_tag` is given, it is restored right after the
+/// [BareMessage] before correction.
+///
+/// The last `_trailer.len()` bytes of corrected data (any bytes between the [BareMessage] and the
+/// parity, such as an authentication trailer) are copied into `_trailer`.
+///
+/// `_layout` is the ID the packet was sent with. It is put back to [PacketLayout::Telemetry] in
+/// the returned [BareMessage], which is then the same whichever way the packet was sent.
+pub(crate) fn recover_bare_message<F: Fec>(
+    _packet: &[u8],
+    _fec: &F,
+    _layout: PacketLayout,
+    _tag: Option<u8>,
+    _known_erasures: &[u8],
+    _trailer: &mut [u8],
+) -> Result<(BareMessage, CorrectionReport), DecodeError> {
+    let _data_len = BARE_MESSAGE_LENGTH_BYTES + _tag.is_some() as usize + _trailer.len();
+    if _packet.len() != _fec.encoded_len(_data_len) || _packet.len() > MAX_FEC_CODEWORD_LENGTH_BYTES
+    {
+        return Err(DecodeError::BadLength);
+    }
+    if _known_erasures.len() > _fec.max_erasures(_data_len) {
+        return Err(DecodeError::TooManyErasures);
+    }
+    if _known_erasures
+        .iter()
+        .any(|&_erasure| _erasure as usize >= _packet.len())
+    {
+        return Err(DecodeError::BadLength);
+    }
+
+    let mut _packet_data_full = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+    let _packet_data_full = &mut _packet_data_full[.._packet.len()];
+    _packet_data_full.clone_from_slice(_packet);
+    let mut _inferred: ErasureList<MAX_FEC_PROFILE_BYTES> = ErasureList::new();
+
+    // static bytes can only be restored where the data is sent as-is
+    if _fec.is_systematic() {
+        // the layout ID is restored below along with the rest of the skeleton, but a packet that
+        // clearly carries another layout's ID should not be parsed as this one.
+        match PacketLayout::from_id(_packet[START_HEADER_DATA.len()]) {
+            Some(_received) if _received != _layout => return Err(DecodeError::UnknownLayout),
+            _ => (),
+        }
+
+        let (_packet_bitmask, _bare_packet): (BareMessage, BareMessage) =
+            find_packet_similarities();
+        for i in 0..BARE_MESSAGE_LENGTH_BYTES {
+            if _packet_bitmask[i] == 0 {
+                _packet_data_full[i] = _bare_packet[i];
+            }
+        }
+        _packet_data_full[START_HEADER_DATA.len()] = _layout.id();
+        if let Some(_tag) = _tag {
+            _packet_data_full[BARE_MESSAGE_LENGTH_BYTES] = _tag;
+        }
+
+        // the skeleton carries the telemetry ID, so compare the received ID against that instead,
+        // keeping any bit errors in it
+        let mut _as_telemetry: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+        _as_telemetry.copy_from_slice(&_packet[..BARE_MESSAGE_LENGTH_BYTES]);
+        _as_telemetry[START_HEADER_DATA.len()] ^= _layout.id() ^ PacketLayout::Telemetry.id();
+        _inferred = infer_erasures(&_as_telemetry);
+    }
 
     // now we theoretically have packet that we have reconstructed as well as we can.
 
-    for i in 0..BARE_MESSAGE_LENGTH_BYTES {
-        _packet_data_full[i] = _reconstructed_array[i];
+    let mut _erasures: ErasureList<MAX_FEC_PROFILE_BYTES> = ErasureList::new();
+    for _erasure in _known_erasures {
+        _erasures.push(*_erasure);
+    }
+    let mut _inferred_used: usize = 0;
+    for _erasure in _inferred.as_slice() {
+        if _erasures.len() >= _fec.max_erasures(_data_len) {
+            break;
+        }
+        if _erasures.as_slice().contains(_erasure) {
+            continue;
+        }
+        _erasures.push(*_erasure);
+        _inferred_used += 1;
     }
 
-    let recovery_buffer = Decoder::new(FEC_EXTRA_BYTES)
-        .correct(&mut _packet_data_full, Some(_known_erasures))
-        .unwrap();
-    let recovered = recovery_buffer.data();
+    let mut _data = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+    let _data = &mut _data[.._data_len];
+    match _fec.decode(_packet_data_full, _erasures.as_slice(), _data) {
+        Ok(_) => (),
+        Err(_) if _inferred_used > 0 => {
+            _inferred_used = 0;
+            _fec.decode(_packet_data_full, _known_erasures, _data)
+                .map_err(|_| DecodeError::Uncorrectable)?;
+        }
+        Err(_) => return Err(DecodeError::Uncorrectable),
+    };
+
+    // re-encoding gives the codeword that was (most likely) sent, parity and all
+    let mut _recovered_codeword = [0u8; MAX_FEC_CODEWORD_LENGTH_BYTES];
+    let _recovered_codeword = &mut _recovered_codeword[.._packet.len()];
+    _fec.encode(_data, _recovered_codeword);
+
+    let mut report = CorrectionReport::new();
+    report.damaged_static = _inferred.damaged_static;
+    report.inferred_erasures = _inferred_used;
+    for i in 0.._packet.len() {
+        if _recovered_codeword[i] != _packet[i] {
+            if report.corrected < report.positions.len() {
+                report.positions[report.corrected] = i as u8;
+            }
+            report.corrected += 1;
+        }
+    }
 
     let mut recovered_packet: BareMessage = [0u8; BARE_MESSAGE_LENGTH_BYTES];
+    recovered_packet.copy_from_slice(&_data[..BARE_MESSAGE_LENGTH_BYTES]);
+    _trailer.copy_from_slice(&_data[_data_len - _trailer.len()..]);
+
+    if recovered_packet[..START_HEADER_DATA.len()] != START_HEADER_DATA {
+        return Err(DecodeError::BadHeader);
+    }
+    if !_fec.is_systematic()
+        && PacketLayout::from_id(recovered_packet[START_HEADER_DATA.len()]) != Some(_layout)
+    {
+        return Err(DecodeError::UnknownLayout);
+    }
+    recovered_packet[START_HEADER_DATA.len()] = PacketLayout::Telemetry.id();
+
+    Ok((recovered_packet, report))
+}
 
-    for i in 0..recovered.len() {
-        recovered_packet[i] = recovered[i];
+/// A single block's value, typed according to the [BlockType] it was declared with in [BLOCK_TYPE_STACK].
+///
+/// All multi-byte values are big-endian on the wire, matching how [BlockStackData] is built by the encoder.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlockValue {
+    I32(i32),
+    U32(u32),
+    I16(i16),
+    U16(u16),
+    F32(f32),
+    Bytes([u8; BYTES_DATA_SIZE]),
+    None,
+}
+
+impl BlockValue {
+    /// Interprets `_data` as a value of `_block_type`. `_data` must be at least `_block_type.len()` bytes long.
+    pub fn from_bytes(_block_type: BlockType, _data: &[u8]) -> Self {
+        match _block_type {
+            BlockType::I32 => Self::I32(i32::from_be_bytes(
+                _data[..I32_DATA_SIZE].try_into().unwrap(),
+            )),
+            BlockType::U32 | BlockType::GPSTIME => Self::U32(u32::from_be_bytes(
+                _data[..U32_DATA_SIZE].try_into().unwrap(),
+            )),
+            BlockType::I16 => Self::I16(i16::from_be_bytes(
+                _data[..I16_DATA_SIZE].try_into().unwrap(),
+            )),
+            BlockType::U16 | BlockType::SEQUENCE => Self::U16(u16::from_be_bytes(
+                _data[..U16_DATA_SIZE].try_into().unwrap(),
+            )),
+            BlockType::F32 => Self::F32(f32::from_be_bytes(
+                _data[..F32_DATA_SIZE].try_into().unwrap(),
+            )),
+            BlockType::BYTES => Self::Bytes(_data[..BYTES_DATA_SIZE].try_into().unwrap()),
+            BlockType::CALLSIGN | BlockType::NONE => Self::None,
+        }
+    }
+
+    /// Lossy conversion to an `f32`.
+    ///
+    /// [BlockValue::Bytes] keeps the old behaviour of being reinterpreted as a big-endian `f32`.
+    pub fn as_f32(&self) -> f32 {
+        match *self {
+            Self::I32(x) => x as f32,
+            Self::U32(x) => x as f32,
+            Self::I16(x) => x as f32,
+            Self::U16(x) => x as f32,
+            Self::F32(x) => x,
+            Self::Bytes(x) => f32::from_be_bytes(x),
+            Self::None => 0.0f32,
+        }
+    }
+
+    /// Exact conversion to an `f64`, for scaling.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Self::I32(x) => x as f64,
+            Self::U32(x) => x as f64,
+            Self::I16(x) => x as f64,
+            Self::U16(x) => x as f64,
+            Self::F32(x) => x as f64,
+            Self::Bytes(x) => f32::from_be_bytes(x) as f64,
+            Self::None => 0.0f64,
+        }
+    }
+
+    /// Converts a physical value to the raw value of `_block_type`, according to `_scaling`.
+    ///
+    /// Integer values are rounded to the nearest step and saturate at the bounds of their type.
+    pub fn from_physical(_block_type: BlockType, _scaling: &BlockScaling, _value: f64) -> Self {
+        let _raw = _scaling.to_raw(_value);
+        let _rounded = if _raw < 0.0f64 {
+            _raw - 0.5f64
+        } else {
+            _raw + 0.5f64
+        };
+        match _block_type {
+            BlockType::I32 => Self::I32(_rounded as i32),
+            BlockType::U32 | BlockType::GPSTIME => Self::U32(_rounded as u32),
+            BlockType::I16 => Self::I16(_rounded as i16),
+            BlockType::U16 | BlockType::SEQUENCE => Self::U16(_rounded as u16),
+            BlockType::F32 => Self::F32(_raw as f32),
+            BlockType::BYTES => Self::Bytes((_raw as f32).to_be_bytes()),
+            BlockType::CALLSIGN | BlockType::NONE => Self::None,
+        }
+    }
+
+    /// The physical value this raw value stands for, according to `_scaling`.
+    pub fn to_physical(&self, _scaling: &BlockScaling) -> f64 {
+        _scaling.to_physical(self.as_f64())
+    }
+
+    /// Big-endian wire form of the value, padded with zeroes to [BYTES_DATA_SIZE].
+    /// Only the first `BlockType::len()` bytes are meaningful.
+    pub fn to_be_bytes(&self) -> [u8; BYTES_DATA_SIZE] {
+        let mut output = [0u8; BYTES_DATA_SIZE];
+        match *self {
+            Self::I32(x) => output.copy_from_slice(&x.to_be_bytes()),
+            Self::U32(x) => output.copy_from_slice(&x.to_be_bytes()),
+            Self::I16(x) => output[..I16_DATA_SIZE].copy_from_slice(&x.to_be_bytes()),
+            Self::U16(x) => output[..U16_DATA_SIZE].copy_from_slice(&x.to_be_bytes()),
+            Self::F32(x) => output.copy_from_slice(&x.to_be_bytes()),
+            Self::Bytes(x) => output = x,
+            Self::None => (),
+        }
+        output
     }
+}
+
+/// A decoded data block: its on-air label, its name from [BLOCK_NAME_STACK], its typed raw value,
+/// and the scaling from [BLOCK_SCALING_STACK] that turns it into a physical value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DecodedBlock {
+    pub label: u8,
+    pub name: &'static str,
+    pub value: BlockValue,
+    pub scaling: BlockScaling,
+}
 
-    recovered_packet
-    //_packet_data_full
+impl DecodedBlock {
+    pub const BLANK: Self = Self {
+        label: 0,
+        name: "",
+        value: BlockValue::None,
+        scaling: BlockScaling::IDENTITY,
+    };
 
-    // let _packet_values = DecodedDataPacket { altitude: 0.0f32, voltage: 0.0f32, temperature: 0.0f32, latitude: 0.0f32, longitude: 0.0f32 };
+    /// The block's value in [DecodedBlock::unit].
+    pub fn physical(&self) -> f64 {
+        self.value.to_physical(&self.scaling)
+    }
 
-    // _packet_values
+    pub const fn unit(&self) -> &'static str {
+        self.scaling.unit
+    }
 }
 
-pub fn values_from_packet(_packet: BareMessage) -> PacketDecodedData {
-    let mut packet_decoded_data: PacketDecodedData = [0.0f32; BLOCK_STACK_DATA_COUNT];
+pub type PacketTypedData = [DecodedBlock; BLOCK_STACK_DATA_COUNT];
+
+/// Walks [BLOCK_IDENT_STACK] and decodes each data block of `_packet` according to its [BlockType].
+pub fn typed_values_from_packet(_packet: &BareMessage) -> PacketTypedData {
+    let mut packet_typed_data: PacketTypedData = [DecodedBlock::BLANK; BLOCK_STACK_DATA_COUNT];
     for i in 0..BLOCK_IDENT_STACK.len() {
-        packet_decoded_data[i] = f32::from_be_bytes(
-            _packet[BLOCK_IDENT_STACK[i].beginning_location..BLOCK_IDENT_STACK[i].end_location]
-                .try_into()
-                .unwrap(),
-        );
+        let _ident = &BLOCK_IDENT_STACK[i];
+        packet_typed_data[i] = DecodedBlock {
+            label: _packet[_ident.beginning_location - BLOCK_LABEL_SIZE],
+            name: BLOCK_NAME_STACK[i],
+            value: BlockValue::from_bytes(
+                _ident.block_type,
+                &_packet[_ident.beginning_location.._ident.end_location],
+            ),
+            scaling: BLOCK_SCALING_STACK[i],
+        };
     }
+    packet_typed_data
+}
 
-    // TODO: this can be done with a for loop based on parameters
-    // let _altitude: f32 = f32::from_be_bytes(_packet[BLOCK_IDENT_STACK[0].beginning_location..BLOCK_IDENT_STACK[0].end_location].try_into().unwrap());
-    // let _voltage: f32 = f32::from_be_bytes(_packet[BLOCK_IDENT_STACK[1].beginning_location..BLOCK_IDENT_STACK[1].end_location].try_into().unwrap());
-    // let _temperature: f32 = f32::from_be_bytes(_packet[BLOCK_IDENT_STACK[2].beginning_location..BLOCK_IDENT_STACK[2].end_location].try_into().unwrap());
-    // let _latitude: f32 = f32::from_be_bytes(_packet[BLOCK_IDENT_STACK[3].beginning_location..BLOCK_IDENT_STACK[3].end_location].try_into().unwrap());
-    // let _longitude: f32 = f32::from_be_bytes(_packet[BLOCK_IDENT_STACK[4].beginning_location..BLOCK_IDENT_STACK[4].end_location].try_into().unwrap());
+/// Returns the physical value of each data block, scaled according to [BLOCK_SCALING_STACK].
+pub fn values_from_packet(_packet: BareMessage) -> PacketDecodedData {
+    let mut packet_decoded_data: PacketDecodedData = [0.0f64; BLOCK_STACK_DATA_COUNT];
+    let _typed_data = typed_values_from_packet(&_packet);
+    for i in 0.._typed_data.len() {
+        packet_decoded_data[i] = _typed_data[i].physical();
+    }
 
     packet_decoded_data
 }
 
+/// Converts physical values, in the units of [BLOCK_SCALING_STACK], into a [BlockStackData]
+/// ready for [construct_blocks]. The inverse of [values_from_packet].
+pub fn blocks_from_values(_values: &PacketDecodedData) -> BlockStackData {
+    let mut _data: BlockStackData = [[0u8; 4]; BLOCK_STACK_DATA_COUNT];
+    for i in 0.._values.len() {
+        _data[i] =
+            BlockValue::from_physical(BLOCK_TYPE_STACK[i], &BLOCK_SCALING_STACK[i], _values[i])
+                .to_be_bytes();
+    }
+    _data
+}
+
+/// Index of the first block of `_block_type` in [BLOCK_TYPE_STACK], if the layout has one.
+const fn find_block(_block_type: BlockType) -> Option<usize> {
+    let mut i: usize = 0;
+    while i < BLOCK_TYPE_STACK.len() {
+        if BLOCK_TYPE_STACK[i] as u8 == _block_type as u8 {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+const SEQUENCE_BLOCK: Option<usize> = find_block(BlockType::SEQUENCE);
+const GPS_TIME_BLOCK: Option<usize> = find_block(BlockType::GPSTIME);
+
+/// Builds telemetry packets, filling in the [BlockType::SEQUENCE] and [BlockType::GPSTIME] blocks
+/// itself, as [QPacketEncoder](crate::qpacket::QPacketEncoder) does for QPackets. Whatever the
+/// caller put in those blocks is overwritten. The sequence number wraps around after [u16::MAX].
+#[derive(Debug, Copy, Clone)]
+pub struct TelemetryEncoder {
+    sequence: u16,
+}
+
+impl TelemetryEncoder {
+    pub const fn new() -> Self {
+        Self::with_sequence(0)
+    }
+
+    /// Starts counting from `_sequence`, e.g. to carry on from a value saved before a reset.
+    pub const fn with_sequence(_sequence: u16) -> Self {
+        Self {
+            sequence: _sequence,
+        }
+    }
+
+    /// The sequence number the next packet will carry.
+    pub const fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Like [generate_packet_no_fec], for the next packet. `_gps_time` is in seconds since the
+    /// GPS epoch.
+    pub const fn generate_packet_no_fec(
+        &mut self,
+        _gps_time: u32,
+        _blockstackdata: BlockStackData,
+    ) -> BareMessage {
+        let mut _data = _blockstackdata;
+        if let Some(i) = SEQUENCE_BLOCK {
+            _data[i] = [(self.sequence >> 8) as u8, self.sequence as u8, 0, 0];
+        }
+        if let Some(i) = GPS_TIME_BLOCK {
+            _data[i] = _gps_time.to_be_bytes();
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        generate_packet_no_fec(_data)
+    }
+
+    /// Like [generate_packet], for the next packet.
+    pub fn generate_packet(
+        &mut self,
+        _gps_time: u32,
+        _blockstackdata: BlockStackData,
+    ) -> TotalMessage {
+        encode_packet(&self.generate_packet_no_fec(_gps_time, _blockstackdata))
+    }
+}
+
+impl Default for TelemetryEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The raw data of block `_block` of a bare telemetry packet.
+fn block_data(_packet: &BareMessage, _block: usize) -> &[u8] {
+    &_packet[BLOCK_IDENT_STACK[_block].beginning_location..BLOCK_IDENT_STACK[_block].end_location]
+}
+
+/// Returns the sequence number of a bare telemetry packet, if the layout has one.
+pub fn telemetry_sequence(_packet: &BareMessage) -> Option<u16> {
+    SEQUENCE_BLOCK.map(|i| u16::from_be_bytes(block_data(_packet, i).try_into().unwrap()))
+}
+
+/// Returns the GPS time (seconds since the GPS epoch) of a bare telemetry packet, if the layout
+/// has one.
+pub fn telemetry_gps_time(_packet: &BareMessage) -> Option<u32> {
+    GPS_TIME_BLOCK.map(|i| u32::from_be_bytes(block_data(_packet, i).try_into().unwrap()))
+}
+
 // fn decode_packet_test() -> BareMessage {
 
 //     let mut example_packet: TotalMessage = make_packet_skeleton(true);
@@ -411,6 +1119,315 @@ mod tests {
         for i in 0..18 {
             _torture_packet[i] = 0x00;
         }
-        assert_eq!(decode_packet(_torture_packet, &[0u8]), _packet[0..BARE_MESSAGE_LENGTH_BYTES], "\ndecoded packets were not the same:\nleft    : {:02x?}\nright   : {:02x?}\noriginal: {:02x?}", decode_packet(_packet, &[0u8]), &_packet[0..BARE_MESSAGE_LENGTH_BYTES], _torture_packet);
+        let (_decoded, _report) = decode_packet(&_torture_packet, &[0u8]).unwrap();
+        assert_eq!(_decoded, _packet[0..BARE_MESSAGE_LENGTH_BYTES], "\ndecoded packets were not the same:\nleft    : {:02x?}\nright   : {:02x?}\noriginal: {:02x?}", _decoded, &_packet[0..BARE_MESSAGE_LENGTH_BYTES], _torture_packet);
+        assert_eq!(_report.corrected, 18);
+        assert_eq!(_report.damaged_static, 14);
+    }
+
+    #[test]
+    fn test_decode_packet_with() {
+        use crate::fec::{Golay24, InterleavedHamming74, NoFec};
+
+        let _packet = make_packet_skeleton_nofec(true);
+
+        let mut _golay = [0u8; 162];
+        encode_packet_with(&_packet, &Golay24, &mut _golay);
+        _golay[10] ^= 0x07;
+        let (_decoded, _report) = decode_packet_with(&_golay, &Golay24, &[]).unwrap();
+        assert_eq!(_decoded, _packet);
+        assert_eq!(_report.positions(), &[10]);
+
+        let mut _hamming = [0u8; 140];
+        encode_packet_with(&_packet, &InterleavedHamming74, &mut _hamming);
+        _hamming[50..60].fill(0x00);
+        assert_eq!(
+            decode_packet_with(&_hamming, &InterleavedHamming74, &[])
+                .unwrap()
+                .0,
+            _packet
+        );
+
+        // without FEC, only the static bytes can be repaired
+        let mut _plain = _packet;
+        _plain[1] ^= 0xFF;
+        let (_decoded, _report) = decode_packet_with(&_plain, &NoFec, &[]).unwrap();
+        assert_eq!(_decoded, _packet);
+        assert_eq!(_report.corrected, 1);
+
+        assert_eq!(
+            decode_packet_with(&_golay[..128], &Golay24, &[]).map(|_| ()),
+            Err(DecodeError::BadLength)
+        );
+    }
+
+    #[test]
+    fn test_infer_erasures() {
+        let _packet = make_packet_skeleton(false);
+        let mut _damaged_packet = _packet.clone();
+        // a burst covering the first two data blocks entirely, including their labels and delimiters
+        let _burst = (BLOCK_IDENT_STACK[0].beginning_location - BLOCK_LABEL_SIZE)
+            ..(BLOCK_IDENT_STACK[1].end_location + BLOCK_DELIMITER_SIZE);
+        for i in _burst.clone() {
+            _damaged_packet[i] ^= 0x5A;
+        }
+
+        let _erasures: ErasureList = infer_erasures(&_damaged_packet);
+        assert_eq!(_erasures.damaged_static, 6);
+        assert_eq!(_erasures.len(), 8);
+        for _position in _erasures.as_slice() {
+            assert!(_burst.contains(&(*_position as usize)));
+        }
+
+        // 8 unknown errors would need 16 bytes of parity; as erasures, they fit.
+        let (_decoded, _report) = decode_packet(&_damaged_packet, &[]).unwrap();
+        assert_eq!(_decoded, _packet[..BARE_MESSAGE_LENGTH_BYTES]);
+        assert_eq!(_report.inferred_erasures, 8);
+        assert_eq!(_report.corrected, _burst.len());
+    }
+
+    #[test]
+    fn test_decode_checked_packet() {
+        let _packet = construct_packet(construct_blocks(&blocks_from_values(&[
+            0.0,
+            1337.69,
+            3.3,
+            -21.5,
+            41.9876543,
+            -87.2345678,
+            7.0,
+            1_400_000_000.0,
+        ])));
+        let _checked = encode_packet_with_crc(&_packet);
+
+        let mut _damaged = _checked;
+        for i in 20..24 {
+            _damaged[i] ^= 0xFF;
+        }
+        let (_decoded, _integrity, _report) = decode_checked_packet(&_damaged, &[]).unwrap();
+        assert_eq!(_decoded, _packet);
+        assert_eq!(_integrity, PayloadIntegrity::Verified);
+        assert_eq!(_report.corrected, 4);
+
+        // what a miscorrection looks like: a valid codeword, but not the one that was sent
+        let mut _other_packet = _packet;
+        _other_packet[BLOCK_IDENT_STACK[1].beginning_location] ^= 0x40;
+        let mut _miscorrected = [0u8; BARE_MESSAGE_LENGTH_BYTES + PAYLOAD_CRC_SIZE];
+        _miscorrected[..BARE_MESSAGE_LENGTH_BYTES].copy_from_slice(&_other_packet);
+        _miscorrected[START_HEADER_DATA.len()] = PacketLayout::CheckedTelemetry.id();
+        _miscorrected[BARE_MESSAGE_LENGTH_BYTES..]
+            .copy_from_slice(&_checked[BARE_MESSAGE_LENGTH_BYTES..][..PAYLOAD_CRC_SIZE]);
+        let mut _miscorrected_packet: CheckedMessage = [0u8; CHECKED_MESSAGE_LENGTH_BYTES];
+        DEFAULT_FEC.encode(&_miscorrected, &mut _miscorrected_packet);
+
+        let (_decoded, _integrity, _) = decode_checked_packet(&_miscorrected_packet, &[]).unwrap();
+        assert_eq!(_decoded, _other_packet);
+        assert_eq!(_integrity, PayloadIntegrity::CrcMismatch);
+        assert!(!_integrity.is_verified());
+
+        assert_eq!(
+            decode_checked_packet(&_checked[..TOTAL_MESSAGE_LENGTH_BYTES], &[]).map(|_| ()),
+            Err(DecodeError::BadLength)
+        );
+    }
+
+    #[test]
+    fn test_decode_packet_report() {
+        let _packet = make_packet_skeleton(false);
+        let mut _damaged_packet = _packet.clone();
+        for i in [
+            BLOCK_IDENT_STACK[1].beginning_location,
+            BLOCK_IDENT_STACK[4].end_location - 1,
+        ] {
+            _damaged_packet[i] ^= 0x5A;
+        }
+
+        let (_decoded, _report) = decode_packet(&_damaged_packet, &[]).unwrap();
+        assert_eq!(_decoded, _packet[..BARE_MESSAGE_LENGTH_BYTES]);
+        assert_eq!(
+            _report.positions(),
+            [
+                BLOCK_IDENT_STACK[1].beginning_location as u8,
+                (BLOCK_IDENT_STACK[4].end_location - 1) as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fec_profiles() {
+        for _profile in FEC_PROFILES {
+            for _type in [false, true] {
+                let _packet = make_packet_skeleton_with_profile(_type, _profile);
+                assert_eq!(_packet.as_bytes().len(), _profile.total_len());
+
+                // trailing bytes past the end of the packet should be ignored
+                let mut _damaged_packet: ProfiledMessageArray =
+                    [0xAA; PROFILED_MESSAGE_MAX_LENGTH_BYTES];
+                _damaged_packet[.._profile.total_len()].copy_from_slice(_packet.as_bytes());
+                // one bit error in the tag, and as many data errors as the profile can fix
+                _damaged_packet[BARE_MESSAGE_LENGTH_BYTES] ^= 0x10;
+                let mut _errors: usize = 0;
+                for i in 0..BARE_MESSAGE_LENGTH_BYTES {
+                    if _errors == _profile.extra_bytes() / 2 - 1 {
+                        break;
+                    }
+                    if find_packet_similarities().0[i] != 0 {
+                        _damaged_packet[i] ^= 0x81;
+                        _errors += 1;
+                    }
+                }
+
+                let (_decoded, _decoded_profile, _report) =
+                    decode_profiled_packet(&_damaged_packet, &[]).unwrap();
+                assert_eq!(_decoded, make_packet_skeleton_nofec(_type));
+                assert_eq!(_decoded_profile, _profile);
+                assert_eq!(_report.corrected, _errors + 1);
+            }
+        }
+
+        let mut _packet: ProfiledMessageArray = [0u8; PROFILED_MESSAGE_MAX_LENGTH_BYTES];
+        _packet[..FecProfile::Light.total_len()].copy_from_slice(
+            make_packet_skeleton_with_profile(false, FecProfile::Light).as_bytes(),
+        );
+        _packet[BARE_MESSAGE_LENGTH_BYTES] = 0x00;
+        assert_eq!(
+            decode_profiled_packet(&_packet, &[]),
+            Err(DecodeError::UnknownProfile)
+        );
+        assert_eq!(
+            decode_profiled_packet(
+                &make_packet_skeleton_with_profile(false, FecProfile::Heavy).as_bytes()
+                    [..FecProfile::Standard.total_len()],
+                &[]
+            ),
+            Err(DecodeError::BadLength)
+        );
+    }
+
+    #[test]
+    fn test_decode_packet_errors() {
+        let _packet = make_packet_skeleton(true);
+        assert_eq!(
+            decode_packet(&_packet[1..], &[]),
+            Err(DecodeError::BadLength)
+        );
+        assert_eq!(
+            decode_packet(&_packet, &[0u8; FEC_EXTRA_BYTES + 1]),
+            Err(DecodeError::TooManyErasures)
+        );
+        assert_eq!(
+            decode_packet(&_packet, &[TOTAL_MESSAGE_LENGTH_BYTES as u8]),
+            Err(DecodeError::BadLength)
+        );
+    }
+
+    #[test]
+    fn test_typed_values_from_packet() {
+        let _packet = construct_packet(construct_blocks(&[
+            (-411240910i32).to_be_bytes(),
+            1337.69f32.to_be_bytes(),
+            3.3f32.to_be_bytes(),
+            (-2150i32).to_be_bytes(),
+            691337000i32.to_be_bytes(),
+            (-1412345678i32).to_be_bytes(),
+            BlockValue::U16(513).to_be_bytes(),
+            1_400_000_010u32.to_be_bytes(),
+        ]));
+        let _typed = typed_values_from_packet(&_packet);
+
+        assert_eq!(_typed[0].value, BlockValue::I32(-411240910));
+        assert_eq!(_typed[1].value, BlockValue::F32(1337.69));
+        assert_eq!(_typed[2].value, BlockValue::F32(3.3));
+        assert_eq!(_typed[3].value, BlockValue::I32(-2150));
+        assert_eq!(_typed[4].value, BlockValue::I32(691337000));
+        assert_eq!(_typed[5].value, BlockValue::I32(-1412345678));
+        assert_eq!(_typed[6].value, BlockValue::U16(513));
+        assert_eq!(_typed[7].value, BlockValue::U32(1_400_000_010));
+
+        for i in 0.._typed.len() {
+            assert_eq!(_typed[i].label, 129 + i as u8);
+            assert_eq!(_typed[i].name, BLOCK_NAME_STACK[i]);
+        }
+        assert_eq!(_typed[3].unit(), "degC");
+        assert_eq!(values_from_packet(_packet)[3], -21.5);
+    }
+
+    #[test]
+    fn test_telemetry_encoder() {
+        let mut _encoder = TelemetryEncoder::with_sequence(u16::MAX);
+        let mut _data = blocks_from_values(&[0.0, 1337.69, 3.3, -21.5, 41.98, -87.23, 0.0, 0.0]);
+        // the encoder's own values win
+        _data[6] = [0xAA; 4];
+
+        let _first = _encoder.generate_packet(1_400_000_000, _data);
+        let _second = _encoder.generate_packet_no_fec(1_400_000_010, _data);
+        assert_eq!(_encoder.sequence(), 1);
+
+        let mut _damaged = _first;
+        _damaged[20] ^= 0xFF;
+        let (_first, _) = decode_packet(&_damaged, &[]).unwrap();
+        assert_eq!(telemetry_sequence(&_first), Some(u16::MAX));
+        assert_eq!(telemetry_gps_time(&_first), Some(1_400_000_000));
+        assert_eq!(telemetry_sequence(&_second), Some(0));
+        assert_eq!(telemetry_gps_time(&_second), Some(1_400_000_010));
+
+        let _typed = typed_values_from_packet(&_second);
+        assert_eq!(_typed[6].value, BlockValue::U16(0));
+        assert_eq!(_typed[7].value, BlockValue::U32(1_400_000_010));
+        assert_eq!(_typed[1].value, BlockValue::F32(1337.69));
+    }
+
+    #[test]
+    fn test_scaled_values_roundtrip() {
+        let _values: PacketDecodedData = [
+            5.0,
+            1337.69,
+            3.3,
+            -21.47,
+            69.1337,
+            -141.2345678,
+            65535.0,
+            1_400_000_000.0,
+        ];
+        let _packet = construct_packet(construct_blocks(&blocks_from_values(&_values)));
+        let _typed = typed_values_from_packet(&_packet);
+
+        assert_eq!(_typed[3].value, BlockValue::I32(-2147));
+
+        // the typed path keeps every declared step
+        let _latitude =
+            BlockValue::from_physical(BlockType::I32, &BLOCK_SCALING_STACK[4], 41.9876543);
+        assert_eq!(_latitude, BlockValue::I32(419_876_543));
+        assert_eq!(_latitude.to_physical(&BLOCK_SCALING_STACK[4]), 41.9876543);
+        let _longitude =
+            BlockValue::from_physical(BlockType::I32, &BLOCK_SCALING_STACK[5], -141.2345679);
+        assert_eq!(_longitude, BlockValue::I32(-1_412_345_679));
+        assert_eq!(
+            _longitude.to_physical(&BLOCK_SCALING_STACK[5]),
+            -141.2345679
+        );
+        assert_eq!(_typed[7].physical(), 1_400_000_000.0);
+
+        let _decoded = values_from_packet(_packet);
+        for i in 0.._values.len() {
+            assert!(
+                (_decoded[i] - _values[i]).abs() <= BLOCK_SCALING_STACK[i].scale,
+                "{}: {} != {}",
+                BLOCK_NAME_STACK[i],
+                _decoded[i],
+                _values[i]
+            );
+        }
+
+        let _saturated = BlockValue::from_physical(
+            BlockType::I16,
+            &BlockScaling::new(0.01, 0.0, "degC"),
+            1000.0,
+        );
+        assert_eq!(_saturated, BlockValue::I16(i16::MAX));
+        assert_eq!(
+            BlockValue::from_physical(BlockType::U16, &BlockScaling::new(0.5, -10.0, "V"), -9.0),
+            BlockValue::U16(2)
+        );
     }
 }
@@ -10,6 +10,8 @@ fn make_packet() {
     let _temperature = 420.1337f32.to_be_bytes();
     let _latitude = 69.1337f32.to_be_bytes();
     let _longitude = 69.420f32.to_be_bytes();
+    let _sequence = [0x00, 0x01, 0x00, 0x00];
+    let _gps_time = 1_400_000_000u32.to_be_bytes();
 
     let _blocks = telemetry::construct_blocks(&[
         _status,
@@ -18,6 +20,8 @@ fn make_packet() {
         _temperature,
         _latitude,
         _longitude,
+        _sequence,
+        _gps_time,
     ]);
 
     let _packet = telemetry::construct_packet(_blocks);
@@ -27,8 +27,6 @@ pub enum Message<'a> {
     Return,
 }
 
-// TODO: implement an iterator function/wrapper that delimits a [Message] as it iterates
-
 impl<'a> Message<'a> {
     /// Returns the one-byte header for this message type. The high byte corresponds
     /// to the message type, and the low byte corresponds to the destination port.
@@ -40,13 +38,101 @@ impl<'a> Message<'a> {
             Self::SetTXDelay(_) => CMD_TXDELAY,
             Self::SetP(_) => CMD_P,
             Self::SetSlotTime(_) => CMD_SLOTTIME,
-            Self::SetTXTail(_) => CMD_TXDELAY,
+            Self::SetTXTail(_) => CMD_TXTAIL,
             Self::SetFullDuplex(_) => CMD_FULLDUPLEX,
             Self::SetHardware(_) => CMD_SETHARDWARE,
             Self::Return => CMD_RETURN,
         };
         high_nibble | low_nibble
     }
+
+    /// The bytes sent after the header byte, before escaping.
+    fn payload(&self) -> &[u8] {
+        match self {
+            Self::SendDataFrame(_data) => _data,
+            Self::SetTXDelay(_value)
+            | Self::SetP(_value)
+            | Self::SetSlotTime(_value)
+            | Self::SetTXTail(_value)
+            | Self::SetFullDuplex(_value)
+            | Self::SetHardware(_value) => core::slice::from_ref(_value),
+            Self::Return => &[],
+        }
+    }
+
+    /// The whole KISS frame for this message, escaped as it is iterated.
+    pub fn kiss_bytes(&self, port: u8) -> KissBytes<'_> {
+        KissBytes::new(self.header_byte(port), self.payload())
+    }
+
+    /// Builds the whole KISS frame for this message: FEND, the header byte, the escaped payload
+    /// and a closing FEND. Returns `None` if that would not fit in a [TncFrameBuffer]; use
+    /// [Message::kiss_bytes] for data frames of any length.
+    pub fn to_tnc_frame(&self, port: u8) -> Option<TncFrameBuffer> {
+        if self.kiss_bytes(port).count() > MAX_KISS_FRAME_SIZE {
+            return None;
+        }
+        let mut framebuffer = TncFrameBuffer::empty_new();
+        for _byte in self.kiss_bytes(port) {
+            framebuffer.raw_add_byte(_byte);
+        }
+        Some(framebuffer)
+    }
+}
+
+/// A KISS frame produced a byte at a time: FEND, the escaped header byte, the escaped payload and
+/// a closing FEND. Needs no buffer, so frames of any length can be sent.
+#[derive(Debug, Clone)]
+pub struct KissBytes<'a> {
+    header: u8,
+    payload: &'a [u8],
+    /// Position in the unescaped frame, the opening FEND being 0 and the header byte 1.
+    position: usize,
+    /// The second byte of an escape sequence, still to be sent.
+    pending: Option<u8>,
+}
+
+impl<'a> KissBytes<'a> {
+    pub const fn new(header: u8, payload: &'a [u8]) -> Self {
+        Self {
+            header,
+            payload,
+            position: 0,
+            pending: None,
+        }
+    }
+
+    /// A [Message::SendDataFrame] of `_data` for `port`.
+    pub const fn data_frame(port: u8, _data: &'a [u8]) -> Self {
+        Self::new(Message::SendDataFrame(_data).header_byte(port), _data)
+    }
+}
+
+impl Iterator for KissBytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if let Some(_byte) = self.pending.take() {
+            return Some(_byte);
+        }
+        let _byte = match self.position {
+            // port 12 data frames have a header of FEND
+            1 => self.header,
+            _position if _position >= 2 && _position - 2 < self.payload.len() => {
+                self.payload[_position - 2]
+            }
+            // the delimiters are the only bytes not escaped
+            _position if _position == 0 || _position - 2 == self.payload.len() => {
+                self.position += 1;
+                return Some(FEND);
+            }
+            _ => return None,
+        };
+        self.position += 1;
+        let [_first, _second] = TncFrameBuffer::escape_byte(_byte);
+        self.pending = _second;
+        _first
+    }
 }
 
 // pub struct DelmitingIterator {
@@ -347,6 +433,27 @@ pub mod tnc_frame_encoder {
             assert_eq!(_message.header_byte(0), CMD_TXDELAY | 0b00000000u8);
             assert_eq!(_message.header_byte(1), CMD_TXDELAY | 0b00010000u8);
             assert_eq!(_message.header_byte(15), CMD_TXDELAY | 0b11110000u8);
+            assert_eq!(Message::SetTXTail(10).header_byte(0), CMD_TXTAIL);
+        }
+
+        #[test]
+        pub fn test_message_tnc_frame() {
+            let data_frame = Message::SendDataFrame(&[0x82, FEND, 0x03])
+                .to_tnc_frame(0)
+                .unwrap();
+            assert_eq!(
+                data_frame.data[..data_frame.current_len],
+                [FEND, CMD_DATAFRAME, 0x82, FESC, TFEND, 0x03, FEND]
+            );
+
+            let option_frame = Message::SetTXDelay(30).to_tnc_frame(1).unwrap();
+            assert_eq!(
+                option_frame.data[..option_frame.current_len],
+                [FEND, 0x10 | CMD_TXDELAY, 30, FEND]
+            );
+
+            let _too_long = [FESC; MAX_KISS_FRAME_SIZE / 2];
+            assert!(Message::SendDataFrame(&_too_long).to_tnc_frame(0).is_none());
         }
     }
 }
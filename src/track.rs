@@ -0,0 +1,570 @@
+use crate::parameters::*;
+use crate::telemetry::{telemetry_gps_time, values_from_packet, PacketDecodedData};
+use std::io::{self, Write};
+use std::string::String;
+
+const ALTITUDE_BLOCK: usize = block_index("altitude");
+const VOLTAGE_BLOCK: usize = block_index("voltage");
+const TEMPERATURE_BLOCK: usize = block_index("temperature");
+const LATITUDE_BLOCK: usize = block_index("latitude");
+const LONGITUDE_BLOCK: usize = block_index("longitude");
+
+const SECONDS_PER_DAY: i64 = 86_400;
+/// Days from 0000-03-01 to 1970-01-01, in the proleptic Gregorian calendar.
+const UNIX_EPOCH_DAYS: i64 = 719_468;
+const DAYS_PER_ERA: i64 = 146_097;
+/// 1980-01-06, the GPS epoch, in Unix seconds.
+const GPS_EPOCH_UNIX: i64 = 315_964_800;
+/// Seconds GPS time runs ahead of UTC, as it has since the start of 2017.
+pub const GPS_LEAP_SECONDS: i64 = 18;
+
+const CSV_HEADER: &str = "time,latitude,longitude,altitude,voltage,temperature";
+/// KML colours are `aabbggrr`.
+const KML_LINE_COLOR: &str = "ff00aaff";
+const KML_WALL_COLOR: &str = "4000aaff";
+const GPX_CREATOR: &str = "libapollo";
+
+/// Position of `_name` in [BLOCK_NAME_STACK]; fails to compile if the layout has no such block.
+const fn block_index(_name: &str) -> usize {
+    let mut i = 0;
+    while i < BLOCK_NAME_STACK.len() {
+        if str_eq(BLOCK_NAME_STACK[i], _name) {
+            return i;
+        }
+        i += 1;
+    }
+    panic!("no such block in BLOCK_NAME_STACK");
+}
+
+const fn str_eq(_a: &str, _b: &str) -> bool {
+    let (_a, _b) = (_a.as_bytes(), _b.as_bytes());
+    if _a.len() != _b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < _a.len() {
+        if _a[i] != _b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// One decoded position report. `time` is in seconds since the Unix epoch, if known; the other
+/// fields are in the units of [BLOCK_SCALING_STACK].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub time: Option<i64>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub voltage: f64,
+    pub temperature: f64,
+}
+
+impl TrackPoint {
+    /// Takes the fields out of a decoded telemetry packet, e.g. from
+    /// [values_from_packet](crate::telemetry::values_from_packet).
+    pub const fn from_values(_time: Option<i64>, _values: &PacketDecodedData) -> Self {
+        Self {
+            time: _time,
+            latitude: _values[LATITUDE_BLOCK],
+            longitude: _values[LONGITUDE_BLOCK],
+            altitude: _values[ALTITUDE_BLOCK],
+            voltage: _values[VOLTAGE_BLOCK],
+            temperature: _values[TEMPERATURE_BLOCK],
+        }
+    }
+
+    /// Takes the fields and the time out of a bare telemetry packet. The time comes from its
+    /// [BlockType::GPSTIME] block, and is `None` while the GPS reports 0, before it has a fix.
+    pub fn from_packet(_packet: &BareMessage) -> Self {
+        let _time = telemetry_gps_time(_packet)
+            .filter(|_gps_time| *_gps_time != 0)
+            .map(gps_to_unix);
+        Self::from_values(_time, &values_from_packet(*_packet))
+    }
+
+    /// Whether the point can go on a map. The GPS reports 0, 0 until it has a fix.
+    pub fn has_position(&self) -> bool {
+        self.latitude.is_finite()
+            && self.longitude.is_finite()
+            && self.altitude.is_finite()
+            && (self.latitude != 0.0 || self.longitude != 0.0)
+            && self.latitude.abs() <= 90.0
+            && self.longitude.abs() <= 180.0
+    }
+}
+
+/// Where the flight started, peaked and ended, by index into the track.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FlightEvents {
+    pub launch: usize,
+    pub burst: usize,
+    pub landing: usize,
+}
+
+impl FlightEvents {
+    /// Burst is the highest point, launch and landing the first and last, all among the points
+    /// with a position. `None` if there are none.
+    pub fn find(_points: &[TrackPoint]) -> Option<Self> {
+        let mut _positioned = _points
+            .iter()
+            .enumerate()
+            .filter(|(_, _point)| _point.has_position());
+        let (_launch, _) = _positioned.next()?;
+        let mut _events = Self {
+            launch: _launch,
+            burst: _launch,
+            landing: _launch,
+        };
+        for (i, _point) in _positioned {
+            if _point.altitude > _points[_events.burst].altitude {
+                _events.burst = i;
+            }
+            _events.landing = i;
+        }
+        Some(_events)
+    }
+
+    fn placemarks(&self) -> [(&'static str, usize); 3] {
+        [
+            ("Launch", self.launch),
+            ("Burst", self.burst),
+            ("Landing", self.landing),
+        ]
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrackFormat {
+    Csv,
+    GeoJson,
+    Kml,
+    Gpx,
+}
+
+impl TrackFormat {
+    /// Picks the format from a file name's extension.
+    pub fn from_path(_path: &str) -> Option<Self> {
+        let (_, _extension) = _path.rsplit_once('.')?;
+        match _extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "geojson" | "json" => Some(Self::GeoJson),
+            "kml" => Some(Self::Kml),
+            "gpx" => Some(Self::Gpx),
+            _ => None,
+        }
+    }
+
+    /// Writes the track `_name` in this format. See the `write_*` functions.
+    pub fn write<W: Write>(
+        self,
+        _writer: W,
+        _name: &str,
+        _points: &[TrackPoint],
+    ) -> io::Result<()> {
+        match self {
+            Self::Csv => write_csv(_writer, _points),
+            Self::GeoJson => write_geojson(_writer, _name, _points),
+            Self::Kml => write_kml(_writer, _name, _points),
+            Self::Gpx => write_gpx(_writer, _name, _points),
+        }
+    }
+}
+
+/// Converts seconds since the GPS epoch to Unix seconds, taking off [GPS_LEAP_SECONDS].
+pub const fn gps_to_unix(_gps_time: u32) -> i64 {
+    _gps_time as i64 + GPS_EPOCH_UNIX - GPS_LEAP_SECONDS
+}
+
+/// Formats Unix seconds as an RFC 3339 UTC timestamp, e.g. `2024-06-01T12:00:00Z`.
+pub fn format_time(_time: i64) -> String {
+    let _days = _time.div_euclid(SECONDS_PER_DAY);
+    let _seconds = _time.rem_euclid(SECONDS_PER_DAY);
+    // Howard Hinnant's civil_from_days, with years starting in March
+    let _shifted = _days + UNIX_EPOCH_DAYS;
+    let _era = _shifted.div_euclid(DAYS_PER_ERA);
+    let _day_of_era = _shifted.rem_euclid(DAYS_PER_ERA);
+    let _year_of_era =
+        (_day_of_era - _day_of_era / 1460 + _day_of_era / 36524 - _day_of_era / 146096) / 365;
+    let _day_of_year = _day_of_era - (365 * _year_of_era + _year_of_era / 4 - _year_of_era / 100);
+    let _month_index = (5 * _day_of_year + 2) / 153;
+    let _day = _day_of_year - (153 * _month_index + 2) / 5 + 1;
+    let _month = if _month_index < 10 {
+        _month_index + 3
+    } else {
+        _month_index - 9
+    };
+    let _year = _year_of_era + _era * 400 + i64::from(_month <= 2);
+    std::format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        _year,
+        _month,
+        _day,
+        _seconds / 3600,
+        _seconds / 60 % 60,
+        _seconds % 60
+    )
+}
+
+fn xml_escape(_text: &str) -> String {
+    let mut _escaped = String::with_capacity(_text.len());
+    for _char in _text.chars() {
+        match _char {
+            '&' => _escaped.push_str("&amp;"),
+            '<' => _escaped.push_str("&lt;"),
+            '>' => _escaped.push_str("&gt;"),
+            '"' => _escaped.push_str("&quot;"),
+            '\'' => _escaped.push_str("&apos;"),
+            _ => _escaped.push(_char),
+        }
+    }
+    _escaped
+}
+
+/// `_text` as a quoted JSON string.
+pub fn json_string(_text: &str) -> String {
+    let mut _quoted = String::from("\"");
+    for _char in _text.chars() {
+        match _char {
+            '"' => _quoted.push_str("\\\""),
+            '\\' => _quoted.push_str("\\\\"),
+            _ if (_char as u32) < 0x20 => {
+                _quoted.push_str(&std::format!("\\u{:04x}", _char as u32))
+            }
+            _ => _quoted.push(_char),
+        }
+    }
+    _quoted.push('"');
+    _quoted
+}
+
+/// The shortest text that reads back as `_value`. Values that came from an `f32` block are
+/// printed as that `f32`, so that 1337.69 does not come out as 1337.68994140625.
+pub fn number_text(_value: f64) -> String {
+    if _value as f32 as f64 == _value {
+        std::format!("{}", _value as f32)
+    } else {
+        std::format!("{}", _value)
+    }
+}
+
+/// JSON has no NaN or infinity.
+pub fn json_number(_value: f64) -> String {
+    if _value.is_finite() {
+        number_text(_value)
+    } else {
+        String::from("null")
+    }
+}
+
+/// Writes every point, with or without a position, one row each under a header row. The time
+/// column is RFC 3339, or empty if unknown.
+pub fn write_csv<W: Write>(mut _writer: W, _points: &[TrackPoint]) -> io::Result<()> {
+    writeln!(_writer, "{}", CSV_HEADER)?;
+    for _point in _points {
+        writeln!(
+            _writer,
+            "{},{},{},{},{},{}",
+            _point.time.map(format_time).unwrap_or_default(),
+            number_text(_point.latitude),
+            number_text(_point.longitude),
+            number_text(_point.altitude),
+            number_text(_point.voltage),
+            number_text(_point.temperature)
+        )?;
+    }
+    _writer.flush()
+}
+
+/// Writes a GeoJSON `FeatureCollection`: the flight as a `LineString` of `[lon, lat, alt]`, then
+/// launch, burst and landing `Point`s. Points without a position are left out.
+pub fn write_geojson<W: Write>(
+    mut _writer: W,
+    _name: &str,
+    _points: &[TrackPoint],
+) -> io::Result<()> {
+    let _coordinates = |_point: &TrackPoint| {
+        std::format!(
+            "[{},{},{}]",
+            json_number(_point.longitude),
+            json_number(_point.latitude),
+            json_number(_point.altitude)
+        )
+    };
+    writeln!(_writer, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+    write!(
+        _writer,
+        "{{\"type\":\"Feature\",\"properties\":{{\"name\":{}}},\
+         \"geometry\":{{\"type\":\"LineString\",\"coordinates\":[",
+        json_string(_name)
+    )?;
+    for (i, _point) in _points
+        .iter()
+        .filter(|_point| _point.has_position())
+        .enumerate()
+    {
+        if i > 0 {
+            write!(_writer, ",")?;
+        }
+        write!(_writer, "{}", _coordinates(_point))?;
+    }
+    write!(_writer, "]}}}}")?;
+    for (_label, i) in FlightEvents::find(_points)
+        .iter()
+        .flat_map(FlightEvents::placemarks)
+    {
+        let _point = &_points[i];
+        write!(
+            _writer,
+            ",\n{{\"type\":\"Feature\",\"properties\":{{\"name\":{},\"time\":{},\
+             \"voltage\":{},\"temperature\":{}}},\
+             \"geometry\":{{\"type\":\"Point\",\"coordinates\":{}}}}}",
+            json_string(_label),
+            _point
+                .time
+                .map(|_time| json_string(&format_time(_time)))
+                .unwrap_or_else(|| String::from("null")),
+            json_number(_point.voltage),
+            json_number(_point.temperature),
+            _coordinates(_point)
+        )?;
+    }
+    writeln!(_writer, "\n]}}")?;
+    _writer.flush()
+}
+
+/// Writes a KML document for Google Earth: the flight as a `LineString` at absolute altitude,
+/// extruded down to the ground, with placemarks at launch, burst and landing. Points without a
+/// position are left out.
+pub fn write_kml<W: Write>(mut _writer: W, _name: &str, _points: &[TrackPoint]) -> io::Result<()> {
+    let _name = xml_escape(_name);
+    writeln!(_writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(_writer, "<kml xmlns=\"http://www.opengis.net/kml/2.2\">")?;
+    writeln!(_writer, "<Document>")?;
+    writeln!(_writer, "<name>{}</name>", _name)?;
+    writeln!(
+        _writer,
+        "<Style id=\"track\"><LineStyle><color>{}</color><width>3</width></LineStyle>\
+         <PolyStyle><color>{}</color></PolyStyle></Style>",
+        KML_LINE_COLOR, KML_WALL_COLOR
+    )?;
+    writeln!(_writer, "<Placemark>")?;
+    writeln!(_writer, "<name>{}</name>", _name)?;
+    writeln!(_writer, "<styleUrl>#track</styleUrl>")?;
+    writeln!(_writer, "<LineString>")?;
+    writeln!(_writer, "<extrude>1</extrude>")?;
+    writeln!(_writer, "<altitudeMode>absolute</altitudeMode>")?;
+    writeln!(_writer, "<coordinates>")?;
+    for _point in _points.iter().filter(|_point| _point.has_position()) {
+        writeln!(
+            _writer,
+            "{},{},{}",
+            number_text(_point.longitude),
+            number_text(_point.latitude),
+            number_text(_point.altitude)
+        )?;
+    }
+    writeln!(_writer, "</coordinates>")?;
+    writeln!(_writer, "</LineString>")?;
+    writeln!(_writer, "</Placemark>")?;
+    for (_label, i) in FlightEvents::find(_points)
+        .iter()
+        .flat_map(FlightEvents::placemarks)
+    {
+        let _point = &_points[i];
+        writeln!(_writer, "<Placemark>")?;
+        writeln!(_writer, "<name>{}</name>", _label)?;
+        writeln!(
+            _writer,
+            "<description>{} m, {} V, {} degC</description>",
+            number_text(_point.altitude),
+            number_text(_point.voltage),
+            number_text(_point.temperature)
+        )?;
+        if let Some(_time) = _point.time {
+            writeln!(
+                _writer,
+                "<TimeStamp><when>{}</when></TimeStamp>",
+                format_time(_time)
+            )?;
+        }
+        writeln!(
+            _writer,
+            "<Point><altitudeMode>absolute</altitudeMode>\
+             <coordinates>{},{},{}</coordinates></Point>",
+            number_text(_point.longitude),
+            number_text(_point.latitude),
+            number_text(_point.altitude)
+        )?;
+        writeln!(_writer, "</Placemark>")?;
+    }
+    writeln!(_writer, "</Document>")?;
+    writeln!(_writer, "</kml>")?;
+    _writer.flush()
+}
+
+fn write_gpx_point<W: Write>(
+    _writer: &mut W,
+    _tag: &str,
+    _point: &TrackPoint,
+    _name: Option<&str>,
+) -> io::Result<()> {
+    write!(
+        _writer,
+        "<{} lat=\"{}\" lon=\"{}\"><ele>{}</ele>",
+        _tag,
+        number_text(_point.latitude),
+        number_text(_point.longitude),
+        number_text(_point.altitude)
+    )?;
+    if let Some(_time) = _point.time {
+        write!(_writer, "<time>{}</time>", format_time(_time))?;
+    }
+    if let Some(_name) = _name {
+        write!(_writer, "<name>{}</name>", _name)?;
+    }
+    writeln!(_writer, "</{}>", _tag)
+}
+
+/// Writes a GPX 1.1 file: launch, burst and landing waypoints, then the flight as one track
+/// segment. Points without a position are left out.
+pub fn write_gpx<W: Write>(mut _writer: W, _name: &str, _points: &[TrackPoint]) -> io::Result<()> {
+    let _name = xml_escape(_name);
+    writeln!(_writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        _writer,
+        "<gpx version=\"1.1\" creator=\"{}\" xmlns=\"http://www.topografix.com/GPX/1/1\">",
+        GPX_CREATOR
+    )?;
+    writeln!(_writer, "<metadata><name>{}</name></metadata>", _name)?;
+    // the schema wants waypoints before tracks
+    for (_label, i) in FlightEvents::find(_points)
+        .iter()
+        .flat_map(FlightEvents::placemarks)
+    {
+        write_gpx_point(&mut _writer, "wpt", &_points[i], Some(_label))?;
+    }
+    writeln!(_writer, "<trk><name>{}</name><trkseg>", _name)?;
+    for _point in _points.iter().filter(|_point| _point.has_position()) {
+        write_gpx_point(&mut _writer, "trkpt", _point, None)?;
+    }
+    writeln!(_writer, "</trkseg></trk>")?;
+    writeln!(_writer, "</gpx>")?;
+    _writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{blocks_from_values, TelemetryEncoder};
+    use std::vec::Vec;
+
+    const LAUNCH_TIME: i64 = 1_717_243_200; // 2024-06-01T12:00:00Z
+
+    fn point(_time: i64, _latitude: f64, _longitude: f64, _altitude: f64) -> TrackPoint {
+        TrackPoint {
+            time: Some(_time),
+            latitude: _latitude,
+            longitude: _longitude,
+            altitude: _altitude,
+            voltage: 3.7,
+            temperature: -12.5,
+        }
+    }
+
+    fn flight() -> Vec<TrackPoint> {
+        std::vec![
+            // no fix yet
+            point(LAUNCH_TIME - 60, 0.0, 0.0, 0.0),
+            point(LAUNCH_TIME, 40.5, -88.25, 200.0),
+            point(LAUNCH_TIME + 3600, 40.75, -87.5, 30000.0),
+            point(LAUNCH_TIME + 5400, 41.0, -87.0, 250.0),
+        ]
+    }
+
+    fn render(_format: TrackFormat, _points: &[TrackPoint]) -> String {
+        let mut _output = Vec::new();
+        _format.write(&mut _output, "Apollo <1>", _points).unwrap();
+        String::from_utf8(_output).unwrap()
+    }
+
+    #[test]
+    fn test_track_times_and_events() {
+        assert_eq!(format_time(LAUNCH_TIME), "2024-06-01T12:00:00Z");
+        assert_eq!(format_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(951_782_399), "2000-02-28T23:59:59Z");
+        assert_eq!(format_time(951_868_800), "2000-03-01T00:00:00Z");
+        assert_eq!(format_time(-1), "1969-12-31T23:59:59Z");
+
+        let _points = flight();
+        assert_eq!(
+            FlightEvents::find(&_points),
+            Some(FlightEvents {
+                launch: 1,
+                burst: 2,
+                landing: 3
+            })
+        );
+        assert_eq!(FlightEvents::find(&_points[..1]), None);
+        assert_eq!(TrackFormat::from_path("flight.KML"), Some(TrackFormat::Kml));
+        assert_eq!(TrackFormat::from_path("flight"), None);
+
+        // through a real packet, to check the block lookup
+        let mut _values = [0.0f64; BLOCK_STACK_DATA_COUNT];
+        _values[ALTITUDE_BLOCK] = 1234.0;
+        _values[LATITUDE_BLOCK] = 40.5123457;
+        _values[LONGITUDE_BLOCK] = -88.2500001;
+        _values[TEMPERATURE_BLOCK] = 21.5;
+        let mut _encoder = TelemetryEncoder::new();
+        let _gps_time = (LAUNCH_TIME - GPS_EPOCH_UNIX + GPS_LEAP_SECONDS) as u32;
+        let _packet = _encoder.generate_packet_no_fec(_gps_time, blocks_from_values(&_values));
+        let _point = TrackPoint::from_packet(&_packet);
+        assert_eq!(_point.time, Some(LAUNCH_TIME));
+        assert_eq!(_point.altitude, 1234.0);
+        // every 1e-7 degree step survives
+        assert_eq!(_point.latitude, 40.5123457);
+        assert_eq!(_point.longitude, -88.2500001);
+        assert_eq!(_point.temperature, 21.5);
+
+        let _no_fix = _encoder.generate_packet_no_fec(0, blocks_from_values(&_values));
+        assert_eq!(TrackPoint::from_packet(&_no_fix).time, None);
+    }
+
+    #[test]
+    fn test_track_formats() {
+        let _points = flight();
+
+        let _csv = render(TrackFormat::Csv, &_points);
+        let _rows: Vec<&str> = _csv.lines().collect();
+        assert_eq!(_rows.len(), 5);
+        assert_eq!(_rows[0], CSV_HEADER);
+        assert_eq!(_rows[2], "2024-06-01T12:00:00Z,40.5,-88.25,200,3.7,-12.5");
+
+        let _geojson = render(TrackFormat::GeoJson, &_points);
+        assert!(_geojson
+            .contains("\"coordinates\":[[-88.25,40.5,200],[-87.5,40.75,30000],[-87,41,250]]"));
+        assert!(_geojson.contains("\"name\":\"Burst\""));
+        assert!(_geojson.contains("\"coordinates\":[-87.5,40.75,30000]}}"));
+
+        let _kml = render(TrackFormat::Kml, &_points);
+        assert!(_kml.contains("<name>Apollo &lt;1&gt;</name>"));
+        assert!(_kml.contains("<extrude>1</extrude>"));
+        assert!(!_kml.contains("0,0,0"));
+        let _burst = _kml.find("<name>Burst</name>").unwrap();
+        assert!(_kml[_burst..].starts_with(
+            "<name>Burst</name>\n<description>30000 m, 3.7 V, -12.5 degC</description>\n\
+             <TimeStamp><when>2024-06-01T13:00:00Z</when></TimeStamp>"
+        ));
+
+        let _gpx = render(TrackFormat::Gpx, &_points);
+        assert!(_gpx.contains(
+            "<wpt lat=\"41\" lon=\"-87\"><ele>250</ele>\
+             <time>2024-06-01T13:30:00Z</time><name>Landing</name></wpt>"
+        ));
+        assert_eq!(_gpx.matches("<trkpt ").count(), 3);
+        assert!(_gpx.find("<wpt").unwrap() < _gpx.find("<trk>").unwrap());
+    }
+}
@@ -0,0 +1,619 @@
+use crate::afsk::{modulate_ax25, AfskConfig, AfskDemodulator};
+use crate::demod::{hard_bit, FskDemodulator};
+use crate::fsk::{modulate_padded, FskConfig};
+use crate::hdlc::Ax25Frame;
+use crate::layout::{decode_any_packet, DecodedPacket};
+use crate::sync::{FrameSynchronizer, SyncedFrame};
+use crate::telemetry::DecodeError;
+use core::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::vec::Vec;
+
+const RIFF_HEADER_LEN: usize = 12;
+const CHUNK_HEADER_LEN: usize = 8;
+/// `fmt ` chunk without the extension that some writers add.
+const FMT_CHUNK_LEN: u32 = 16;
+/// Offsets of the two sizes [WavWriter::finish] fills in.
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 =
+    (RIFF_HEADER_LEN + CHUNK_HEADER_LEN) as u64 + FMT_CHUNK_LEN as u64 + 4;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+/// The real format tag is then the first two bytes of the sub-format GUID.
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const EXTENSIBLE_SUBFORMAT_OFFSET: usize = 24;
+
+/// Bytes of alternating bits sent before each packet, for the demodulator's clock to settle.
+pub const PACKET_PREAMBLE_BYTES: usize = 8;
+/// Bytes of alternating bits sent after each packet, so its last bit is not cut short.
+pub const PACKET_TAIL_BYTES: usize = 2;
+/// Sync word bit errors tolerated when finding packets in a recording.
+const SYNC_BIT_ERRORS: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SampleFormat {
+    Pcm16,
+    Float32,
+}
+
+impl SampleFormat {
+    pub const fn bytes(&self) -> u16 {
+        match self {
+            Self::Pcm16 => 2,
+            Self::Float32 => 4,
+        }
+    }
+
+    const fn format_tag(&self) -> u16 {
+        match self {
+            Self::Pcm16 => WAVE_FORMAT_PCM,
+            Self::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+        }
+    }
+}
+
+/// Layout of the samples in a WAV file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WavSpec {
+    /// 1 or 2.
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+}
+
+impl WavSpec {
+    pub const fn mono(_sample_rate: u32, _sample_format: SampleFormat) -> Self {
+        Self {
+            channels: 1,
+            sample_rate: _sample_rate,
+            sample_format: _sample_format,
+        }
+    }
+
+    /// Bytes in one frame: one sample of every channel.
+    pub const fn block_align(&self) -> u16 {
+        self.channels * self.sample_format.bytes()
+    }
+}
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+    /// The file is not RIFF/WAVE, or ends before its `fmt ` or `data` chunk.
+    NotWav,
+    /// A sample format, channel count or sample rate that is not handled.
+    Unsupported,
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(_error) => write!(f, "I/O error: {}", _error),
+            Self::NotWav => write!(f, "Not a WAV file"),
+            Self::Unsupported => write!(f, "Unsupported WAV format"),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<io::Error> for WavError {
+    fn from(_error: io::Error) -> Self {
+        Self::Io(_error)
+    }
+}
+
+fn parse_fmt_chunk(_chunk: &[u8]) -> Result<WavSpec, WavError> {
+    if _chunk.len() < FMT_CHUNK_LEN as usize {
+        return Err(WavError::NotWav);
+    }
+    let mut _format_tag = u16::from_le_bytes([_chunk[0], _chunk[1]]);
+    if _format_tag == WAVE_FORMAT_EXTENSIBLE && _chunk.len() >= EXTENSIBLE_SUBFORMAT_OFFSET + 2 {
+        _format_tag = u16::from_le_bytes([
+            _chunk[EXTENSIBLE_SUBFORMAT_OFFSET],
+            _chunk[EXTENSIBLE_SUBFORMAT_OFFSET + 1],
+        ]);
+    }
+    let _channels = u16::from_le_bytes([_chunk[2], _chunk[3]]);
+    let _sample_rate = u32::from_le_bytes([_chunk[4], _chunk[5], _chunk[6], _chunk[7]]);
+    let _bits = u16::from_le_bytes([_chunk[14], _chunk[15]]);
+
+    let _sample_format = match (_format_tag, _bits) {
+        (WAVE_FORMAT_PCM, 16) => SampleFormat::Pcm16,
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::Float32,
+        _ => return Err(WavError::Unsupported),
+    };
+    if !(1..=2).contains(&_channels) || _sample_rate == 0 {
+        return Err(WavError::Unsupported);
+    }
+    Ok(WavSpec {
+        channels: _channels,
+        sample_rate: _sample_rate,
+        sample_format: _sample_format,
+    })
+}
+
+/// Reads samples from a WAV file, mixed down to mono with full scale at 1.0. Chunks other than
+/// `fmt ` and `data` are skipped. Wrap files in a [std::io::BufReader]; samples are read a frame
+/// at a time.
+pub struct WavReader<R: Read> {
+    reader: R,
+    spec: WavSpec,
+    /// Bytes left in the `data` chunk.
+    remaining: u32,
+}
+
+impl<R: Read> WavReader<R> {
+    pub fn new(mut _reader: R) -> Result<Self, WavError> {
+        let mut _header = [0u8; RIFF_HEADER_LEN];
+        read_or_not_wav(&mut _reader, &mut _header)?;
+        if &_header[0..4] != b"RIFF" || &_header[8..12] != b"WAVE" {
+            return Err(WavError::NotWav);
+        }
+
+        let mut _spec: Option<WavSpec> = None;
+        loop {
+            let mut _chunk_header = [0u8; CHUNK_HEADER_LEN];
+            read_or_not_wav(&mut _reader, &mut _chunk_header)?;
+            let _size = u32::from_le_bytes(_chunk_header[4..8].try_into().unwrap());
+            // chunks are padded to an even length
+            let _padded_size = _size as u64 + (_size & 1) as u64;
+
+            match &_chunk_header[0..4] {
+                b"fmt " => {
+                    let mut _chunk = Vec::new();
+                    _reader
+                        .by_ref()
+                        .take(_padded_size)
+                        .read_to_end(&mut _chunk)?;
+                    _spec = Some(parse_fmt_chunk(&_chunk)?);
+                }
+                b"data" => {
+                    return Ok(Self {
+                        reader: _reader,
+                        spec: _spec.ok_or(WavError::NotWav)?,
+                        remaining: _size,
+                    });
+                }
+                _ => {
+                    io::copy(&mut _reader.by_ref().take(_padded_size), &mut io::sink())?;
+                }
+            }
+        }
+    }
+
+    pub const fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// The next frame, as the average of its channels. `None` at the end of the data, including
+    /// when a recording was cut off before the length in its header.
+    pub fn read_frame(&mut self) -> Result<Option<f32>, WavError> {
+        let _block_align = self.spec.block_align();
+        if self.remaining < _block_align as u32 {
+            return Ok(None);
+        }
+        let mut _frame = [0u8; 8];
+        let _frame = &mut _frame[.._block_align as usize];
+        match self.reader.read_exact(_frame) {
+            Ok(()) => (),
+            Err(_error) if _error.kind() == io::ErrorKind::UnexpectedEof => {
+                self.remaining = 0;
+                return Ok(None);
+            }
+            Err(_error) => return Err(_error.into()),
+        }
+        self.remaining -= _block_align as u32;
+
+        let _sum: f32 = _frame
+            .chunks_exact(self.spec.sample_format.bytes() as usize)
+            .map(|_sample| match self.spec.sample_format {
+                SampleFormat::Pcm16 => {
+                    i16::from_le_bytes([_sample[0], _sample[1]]) as f32 / i16::MAX as f32
+                }
+                SampleFormat::Float32 => f32::from_le_bytes(_sample.try_into().unwrap()),
+            })
+            .sum();
+        Ok(Some(_sum / self.spec.channels as f32))
+    }
+
+    /// Every remaining frame, mixed down to mono.
+    pub fn read_mono(mut self) -> Result<Vec<f32>, WavError> {
+        let mut _samples =
+            Vec::with_capacity((self.remaining / self.spec.block_align() as u32) as usize);
+        while let Some(_sample) = self.read_frame()? {
+            _samples.push(_sample);
+        }
+        Ok(_samples)
+    }
+}
+
+/// `read_exact`, with running out of file meaning it was never a whole WAV header.
+fn read_or_not_wav<R: Read>(_reader: &mut R, _buffer: &mut [u8]) -> Result<(), WavError> {
+    _reader
+        .read_exact(_buffer)
+        .map_err(|_error| match _error.kind() {
+            io::ErrorKind::UnexpectedEof => WavError::NotWav,
+            _ => WavError::Io(_error),
+        })
+}
+
+/// Writes a WAV file. The header goes out first with empty sizes, and [WavWriter::finish] seeks
+/// back to fill them in; to write to something that cannot seek, write to a
+/// [std::io::Cursor] and copy it over.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    data_bytes: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut _writer: W, _spec: WavSpec) -> Result<Self, WavError> {
+        if !(1..=2).contains(&_spec.channels) || _spec.sample_rate == 0 {
+            return Err(WavError::Unsupported);
+        }
+        let _block_align = _spec.block_align();
+        _writer.write_all(b"RIFF")?;
+        _writer.write_all(&0u32.to_le_bytes())?;
+        _writer.write_all(b"WAVEfmt ")?;
+        _writer.write_all(&FMT_CHUNK_LEN.to_le_bytes())?;
+        _writer.write_all(&_spec.sample_format.format_tag().to_le_bytes())?;
+        _writer.write_all(&_spec.channels.to_le_bytes())?;
+        _writer.write_all(&_spec.sample_rate.to_le_bytes())?;
+        _writer.write_all(&(_spec.sample_rate * _block_align as u32).to_le_bytes())?;
+        _writer.write_all(&_block_align.to_le_bytes())?;
+        _writer.write_all(&(_spec.sample_format.bytes() * 8).to_le_bytes())?;
+        _writer.write_all(b"data")?;
+        _writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer: _writer,
+            spec: _spec,
+            data_bytes: 0,
+        })
+    }
+
+    pub const fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// Writes one frame, with `_sample` (full scale at 1.0) on every channel.
+    pub fn write_sample(&mut self, _sample: f32) -> Result<(), WavError> {
+        let mut _frame = [0u8; 8];
+        let _bytes = self.spec.sample_format.bytes() as usize;
+        for _channel in _frame
+            .chunks_exact_mut(_bytes)
+            .take(self.spec.channels as usize)
+        {
+            match self.spec.sample_format {
+                SampleFormat::Pcm16 => _channel.copy_from_slice(
+                    &((_sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16).to_le_bytes(),
+                ),
+                SampleFormat::Float32 => _channel.copy_from_slice(&_sample.to_le_bytes()),
+            }
+        }
+        let _block_align = self.spec.block_align();
+        self.writer.write_all(&_frame[.._block_align as usize])?;
+        self.data_bytes += _block_align as u32;
+        Ok(())
+    }
+
+    /// Writes one frame of a 16-bit sample, as made by the modulators.
+    pub fn write_i16(&mut self, _sample: i16) -> Result<(), WavError> {
+        self.write_sample(_sample as f32 / i16::MAX as f32)
+    }
+
+    /// Fills in the sizes in the header, and hands back the writer.
+    pub fn finish(mut self) -> Result<W, WavError> {
+        let _riff_size = DATA_SIZE_OFFSET as u32 + 4 - 8 + self.data_bytes;
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer.write_all(&_riff_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes `_packets` as 2-FSK at `_config`'s sample rate and with its whitening, each with a
+/// preamble and tail of alternating bits, for [read_packets_wav] (or a radio) to receive. Packets
+/// of any layout can be written.
+pub fn write_packets_wav<W: Write + Seek, P: AsRef<[u8]>>(
+    _writer: W,
+    _config: FskConfig,
+    _packets: &[P],
+    _sample_format: SampleFormat,
+) -> Result<W, WavError> {
+    let mut _wav = WavWriter::new(_writer, WavSpec::mono(_config.sample_rate, _sample_format))?;
+    for _packet in _packets {
+        for _sample in modulate_padded(
+            _config,
+            PACKET_PREAMBLE_BYTES,
+            _packet.as_ref(),
+            PACKET_TAIL_BYTES,
+        ) {
+            _wav.write_i16(_sample)?;
+        }
+    }
+    _wav.finish()
+}
+
+/// A packet found in a recording: as received, and as decoded by [decode_any_packet]. Delta
+/// packets still need a [DeltaDecoder](crate::delta::DeltaDecoder) to become absolute values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReceivedPacket {
+    pub raw: SyncedFrame,
+    pub decoded: Result<DecodedPacket, DecodeError>,
+}
+
+/// Demodulates a 2-FSK recording, using `_config` for everything but the sample rate, which is
+/// taken from the file.
+pub fn read_packets_wav<R: Read>(
+    _reader: R,
+    _config: FskConfig,
+) -> Result<Vec<ReceivedPacket>, WavError> {
+    let mut _wav = WavReader::new(_reader)?;
+    let _config = FskConfig {
+        sample_rate: _wav.spec().sample_rate,
+        .._config
+    };
+    if _config.sample_rate < _config.baud {
+        return Err(WavError::Unsupported);
+    }
+    let mut _demodulator = FskDemodulator::new(_config);
+    let mut _sync = FrameSynchronizer::with_whitening(SYNC_BIT_ERRORS, _config.whitening);
+    let mut _packets = Vec::new();
+
+    while let Some(_sample) = _wav.read_frame()? {
+        if let Some(_raw) = _demodulator
+            .push_real(_sample)
+            .and_then(|_bit| _sync.push_bit(hard_bit(_bit)))
+        {
+            _packets.push(ReceivedPacket {
+                raw: _raw,
+                decoded: decode_any_packet(_raw.as_bytes()),
+            });
+        }
+    }
+    Ok(_packets)
+}
+
+/// Writes AX.25 `_frames` (without FCS) as AFSK1200, one after another, each with `_config`'s
+/// TXDELAY and TXTAIL.
+pub fn write_ax25_wav<W: Write + Seek>(
+    _writer: W,
+    _config: &AfskConfig,
+    _frames: &[&[u8]],
+    _sample_format: SampleFormat,
+) -> Result<W, WavError> {
+    let mut _wav = WavWriter::new(_writer, WavSpec::mono(_config.sample_rate, _sample_format))?;
+    for _frame in _frames {
+        for _sample in modulate_ax25(_config, _frame) {
+            _wav.write_i16(_sample)?;
+        }
+    }
+    _wav.finish()
+}
+
+/// Every AX.25 frame with a good FCS in an AFSK1200 recording.
+pub fn read_ax25_wav<R: Read>(_reader: R) -> Result<Vec<Ax25Frame>, WavError> {
+    let mut _wav = WavReader::new(_reader)?;
+    let mut _demodulator = AfskDemodulator::new(AfskConfig::new(_wav.spec().sample_rate))
+        .ok_or(WavError::Unsupported)?;
+    let mut _frames = Vec::new();
+
+    while let Some(_sample) = _wav.read_frame()? {
+        if let Some(_frame) = _demodulator.push_real(_sample) {
+            _frames.push(_frame);
+        }
+    }
+    Ok(_frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::DeltaEncoder;
+    use crate::qpacket::{encode_qpacket, QPacketEncoder};
+    use crate::telemetry::{make_packet_skeleton, make_packet_skeleton_nofec};
+    use crate::whitening::Whitening;
+    use std::io::Cursor;
+
+    fn write_samples(_spec: WavSpec, _samples: &[f32]) -> Vec<u8> {
+        let mut _wav = WavWriter::new(Cursor::new(Vec::new()), _spec).unwrap();
+        for _sample in _samples {
+            _wav.write_sample(*_sample).unwrap();
+        }
+        _wav.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_wav_roundtrip() {
+        let _samples = [0.0, 0.5, -0.25, 1.0, -1.0];
+
+        let _bytes = write_samples(WavSpec::mono(8000, SampleFormat::Float32), &_samples);
+        assert_eq!(_bytes.len(), 44 + _samples.len() * 4);
+        assert_eq!(
+            u32::from_le_bytes(_bytes[4..8].try_into().unwrap()),
+            _bytes.len() as u32 - 8
+        );
+        let _reader = WavReader::new(&_bytes[..]).unwrap();
+        assert_eq!(*_reader.spec(), WavSpec::mono(8000, SampleFormat::Float32));
+        assert_eq!(_reader.read_mono().unwrap(), _samples);
+
+        let _stereo = WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            sample_format: SampleFormat::Pcm16,
+        };
+        let _bytes = write_samples(_stereo, &_samples);
+        assert_eq!(_bytes.len(), 44 + _samples.len() * 4);
+        let _reader = WavReader::new(&_bytes[..]).unwrap();
+        assert_eq!(*_reader.spec(), _stereo);
+        let _read = _reader.read_mono().unwrap();
+        for (_read, _written) in _read.iter().zip(_samples) {
+            assert!((_read - _written).abs() < 1.0 / i16::MAX as f32);
+        }
+    }
+
+    #[test]
+    fn test_wav_chunks() {
+        let _bytes = write_samples(WavSpec::mono(8000, SampleFormat::Pcm16), &[0.5; 3]);
+
+        // a LIST chunk of odd length (so padded) between fmt and data, and a cut-off recording
+        let mut _edited = _bytes[..36].to_vec();
+        _edited.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        _edited.extend_from_slice(&_bytes[36.._bytes.len() - 1]);
+        assert_eq!(
+            WavReader::new(&_edited[..])
+                .unwrap()
+                .read_mono()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert!(matches!(
+            WavReader::new(&b"RIFF\x00\x00\x00\x00AVI "[..]),
+            Err(WavError::NotWav)
+        ));
+        assert!(matches!(
+            WavReader::new(&_bytes[..30]),
+            Err(WavError::NotWav)
+        ));
+
+        // 8-bit PCM
+        let mut _edited = _bytes.clone();
+        _edited[34] = 8;
+        assert!(matches!(
+            WavReader::new(&_edited[..]),
+            Err(WavError::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn test_packets_wav() {
+        let _config = FskConfig {
+            mark_hz: 1200,
+            space_hz: 2200,
+            baud: 300,
+            sample_rate: 9600,
+            whitening: Whitening::None,
+        };
+        let _qpacket = QPacketEncoder::new().construct_packet_from_values(
+            1_400_000_000,
+            &[41.9876543, -87.2345678, 313.37, 3.6, -40.5],
+        );
+        let mut _deltas = DeltaEncoder::new(1);
+        _deltas.encode(&make_packet_skeleton_nofec(true));
+        let _delta = _deltas.encode(&make_packet_skeleton_nofec(false));
+        let _packets = [
+            make_packet_skeleton(true).to_vec(),
+            make_packet_skeleton(false).to_vec(),
+            encode_qpacket(_qpacket).to_vec(),
+            _delta.as_bytes().to_vec(),
+        ];
+        let _bytes = write_packets_wav(
+            Cursor::new(Vec::new()),
+            _config,
+            &_packets,
+            SampleFormat::Pcm16,
+        )
+        .unwrap()
+        .into_inner();
+
+        // the receiver only needs the tones and baud rate
+        let _received = read_packets_wav(
+            &_bytes[..],
+            FskConfig {
+                sample_rate: 0,
+                .._config
+            },
+        )
+        .unwrap();
+        assert_eq!(_received.len(), _packets.len());
+        for (_received, _packet) in _received.iter().zip(&_packets) {
+            assert_eq!(_received.raw.as_bytes(), &_packet[..]);
+        }
+        for (_received, _type) in _received.iter().zip([true, false]) {
+            assert!(matches!(
+                _received.decoded,
+                Ok(DecodedPacket::Telemetry(_bare, _)) if _bare == make_packet_skeleton_nofec(_type)
+            ));
+        }
+        assert_eq!(_received[2].decoded, Ok(DecodedPacket::QPacket(_qpacket)));
+        assert!(matches!(_received[3].decoded, Ok(DecodedPacket::Delta(_))));
+    }
+
+    #[test]
+    fn test_packets_wav_whitened() {
+        let _packets = [make_packet_skeleton(false), make_packet_skeleton(true)];
+        for _whitening in [Whitening::G3ruh, Whitening::Pn9] {
+            let _config = FskConfig {
+                mark_hz: 1200,
+                space_hz: 2200,
+                baud: 300,
+                sample_rate: 9600,
+                whitening: _whitening,
+            };
+            let _bytes = write_packets_wav(
+                Cursor::new(Vec::new()),
+                _config,
+                &_packets,
+                SampleFormat::Pcm16,
+            )
+            .unwrap()
+            .into_inner();
+
+            let _received = read_packets_wav(&_bytes[..], _config).unwrap();
+            assert_eq!(_received.len(), 2, "{:?}", _whitening);
+            for (_received, _packet) in _received.iter().zip(&_packets) {
+                assert_eq!(_received.raw.as_bytes(), &_packet[..]);
+            }
+
+            let _plain = FskConfig {
+                whitening: Whitening::None,
+                .._config
+            };
+            assert!(read_packets_wav(&_bytes[..], _plain).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_ax25_wav() {
+        let mut _config = AfskConfig::new(22_050);
+        _config.tx_delay = 10;
+        let _frames: [&[u8]; 2] = [
+            b"\x82\xa0\xb4\x9c\x8a\xb0\x60\x96\x88\x72\xa8\x8c\x82\x77\x03\xf0>hello",
+            b"\x82\xa0\xb4\x9c\x8a\xb0\x60\x96\x88\x72\xa8\x8c\x82\x77\x03\xf0>again",
+        ];
+        let _bytes = write_ax25_wav(
+            Cursor::new(Vec::new()),
+            &_config,
+            &_frames,
+            SampleFormat::Float32,
+        )
+        .unwrap()
+        .into_inner();
+
+        let _received = read_ax25_wav(&_bytes[..]).unwrap();
+        assert_eq!(_received.len(), 2);
+        assert_eq!(_received[0].as_bytes(), _frames[0]);
+        assert_eq!(_received[1].information(), b">again");
+
+        // 4.4 kHz is too slow for the 2200 Hz space tone
+        let _bytes = write_ax25_wav(
+            Cursor::new(Vec::new()),
+            &AfskConfig::new(4_400),
+            &_frames,
+            SampleFormat::Pcm16,
+        )
+        .unwrap()
+        .into_inner();
+        assert!(matches!(
+            read_ax25_wav(&_bytes[..]),
+            Err(WavError::Unsupported)
+        ));
+    }
+}
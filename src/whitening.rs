@@ -0,0 +1,323 @@
+/// Taps of the G3RUH scrambler polynomial 1 + x^12 + x^17.
+const G3RUH_TAP_A: u32 = 12;
+const G3RUH_TAP_B: u32 = 17;
+const G3RUH_STATE_MASK: u32 = (1 << G3RUH_TAP_B) - 1;
+
+/// Self-synchronising multiplicative scrambler with polynomial 1 + x^12 + x^17, as used by G3RUH
+/// 9600 baud packet radio. Bits are taken most significant first.
+///
+/// The state carries over between calls, so the same scrambler can be used for a continuous
+/// bitstream. A descrambler that starts out of step recovers after 17 bits; one started from the
+/// same state as the scrambler (see [Whitening]) gets every bit right.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct G3ruhScrambler {
+    state: u32,
+}
+
+impl G3ruhScrambler {
+    /// Starts from an all-ones state. From all-zeroes, a run of zero data would stay all-zeroes.
+    pub const fn new() -> Self {
+        Self::with_state(G3RUH_STATE_MASK)
+    }
+
+    pub const fn with_state(_state: u32) -> Self {
+        Self {
+            state: _state & G3RUH_STATE_MASK,
+        }
+    }
+
+    const fn feedback(&self) -> bool {
+        ((self.state >> (G3RUH_TAP_A - 1)) ^ (self.state >> (G3RUH_TAP_B - 1))) & 1 != 0
+    }
+
+    const fn shift(&mut self, _bit: bool) {
+        self.state = ((self.state << 1) | _bit as u32) & G3RUH_STATE_MASK;
+    }
+
+    pub const fn scramble_bit(&mut self, _bit: bool) -> bool {
+        let output = _bit ^ self.feedback();
+        self.shift(output);
+        output
+    }
+
+    pub const fn descramble_bit(&mut self, _bit: bool) -> bool {
+        let output = _bit ^ self.feedback();
+        self.shift(_bit);
+        output
+    }
+
+    pub const fn scramble_byte(&mut self, _byte: u8) -> u8 {
+        let mut output: u8 = 0;
+        let mut i: usize = 0;
+        while i < 8 {
+            output = (output << 1) | self.scramble_bit((_byte >> (7 - i)) & 1 != 0) as u8;
+            i += 1;
+        }
+        output
+    }
+
+    pub const fn descramble_byte(&mut self, _byte: u8) -> u8 {
+        let mut output: u8 = 0;
+        let mut i: usize = 0;
+        while i < 8 {
+            output = (output << 1) | self.descramble_bit((_byte >> (7 - i)) & 1 != 0) as u8;
+            i += 1;
+        }
+        output
+    }
+
+    pub fn scramble(&mut self, _data: &mut [u8]) {
+        for _byte in _data {
+            *_byte = self.scramble_byte(*_byte);
+        }
+    }
+
+    pub fn descramble(&mut self, _data: &mut [u8]) {
+        for _byte in _data {
+            *_byte = self.descramble_byte(*_byte);
+        }
+    }
+}
+
+const PN9_SEED: u16 = 0x1FF;
+
+/// Additive whitening with the PN9 sequence (x^9 + x^5 + 1, seeded with all ones), as used by
+/// TI's CC1101 and friends. The sequence starts `FF E1 1D 9A`.
+///
+/// Unlike [G3ruhScrambler], a bit error stays a single bit error, but the receiver must start the
+/// sequence on the first byte of the frame; start a new whitener for every frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pn9Whitener {
+    state: u16,
+}
+
+impl Pn9Whitener {
+    pub const fn new() -> Self {
+        Self { state: PN9_SEED }
+    }
+
+    /// The next byte of the sequence.
+    pub const fn next_byte(&mut self) -> u8 {
+        let output = self.state as u8;
+        let mut i: usize = 0;
+        while i < 8 {
+            let _bit = (self.state ^ (self.state >> 5)) & 1;
+            self.state = (self.state >> 1) | (_bit << 8);
+            i += 1;
+        }
+        output
+    }
+
+    /// Whitens or de-whitens `_data`; the two are the same operation.
+    pub fn apply(&mut self, _data: &mut [u8]) {
+        for _byte in _data {
+            *_byte ^= self.next_byte();
+        }
+    }
+}
+
+/// Which whitening a link uses. Applied to a whole frame after FEC encoding, and undone before
+/// FEC decoding, each frame starting from a fresh state. Any preamble is sent as it is.
+///
+/// As every frame starts from the same state, its header is always whitened the same way, so frame
+/// sync (see [crate::sync]) looks for the whitened header and de-whitens the rest of the frame as
+/// it arrives, for [Whitening::G3ruh] as well as [Whitening::Pn9].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Whitening {
+    None,
+    G3ruh,
+    Pn9,
+}
+
+impl Whitening {
+    /// Parses `none`, `g3ruh` or `pn9`, in any case.
+    pub fn from_name(_name: &str) -> Option<Self> {
+        match _name.to_ascii_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "g3ruh" => Some(Self::G3ruh),
+            "pn9" => Some(Self::Pn9),
+            _ => None,
+        }
+    }
+
+    /// Whitens a stream of bits, most significant bit of each byte first, as [Whitening::whiten]
+    /// would whiten their bytes.
+    pub const fn whiten_bits<I: Iterator<Item = bool>>(&self, _bits: I) -> WhitenedBits<I> {
+        WhitenedBits {
+            whitener: BitWhitener::new(*self),
+            bits: _bits,
+        }
+    }
+
+    pub fn whiten(&self, _frame: &mut [u8]) {
+        match self {
+            Self::None => (),
+            Self::G3ruh => G3ruhScrambler::new().scramble(_frame),
+            Self::Pn9 => Pn9Whitener::new().apply(_frame),
+        }
+    }
+
+    pub fn dewhiten(&self, _frame: &mut [u8]) {
+        match self {
+            Self::None => (),
+            Self::G3ruh => G3ruhScrambler::new().descramble(_frame),
+            Self::Pn9 => Pn9Whitener::new().apply(_frame),
+        }
+    }
+}
+
+/// Bit-at-a-time [Whitening] of one frame, for modulators and receivers that see one bit at a time.
+/// Bits are taken most significant first, so that it agrees with [Whitening::whiten].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BitWhitener {
+    whitening: Whitening,
+    g3ruh: G3ruhScrambler,
+    pn9: Pn9Whitener,
+    /// Byte of the PN9 sequence being used up, and how many of its bits have been.
+    pn9_byte: u8,
+    pn9_bits: u8,
+}
+
+impl BitWhitener {
+    /// Starts a new frame.
+    pub const fn new(_whitening: Whitening) -> Self {
+        Self {
+            whitening: _whitening,
+            g3ruh: G3ruhScrambler::new(),
+            pn9: Pn9Whitener::new(),
+            pn9_byte: 0,
+            pn9_bits: 8,
+        }
+    }
+
+    const fn next_pn9_bit(&mut self) -> bool {
+        if self.pn9_bits == 8 {
+            self.pn9_byte = self.pn9.next_byte();
+            self.pn9_bits = 0;
+        }
+        let output = self.pn9_byte & (0x80 >> self.pn9_bits) != 0;
+        self.pn9_bits += 1;
+        output
+    }
+
+    pub const fn whiten_bit(&mut self, _bit: bool) -> bool {
+        match self.whitening {
+            Whitening::None => _bit,
+            Whitening::G3ruh => self.g3ruh.scramble_bit(_bit),
+            Whitening::Pn9 => _bit ^ self.next_pn9_bit(),
+        }
+    }
+
+    pub const fn dewhiten_bit(&mut self, _bit: bool) -> bool {
+        match self.whitening {
+            Whitening::None => _bit,
+            Whitening::G3ruh => self.g3ruh.descramble_bit(_bit),
+            Whitening::Pn9 => _bit ^ self.next_pn9_bit(),
+        }
+    }
+}
+
+/// Iterator over a whitened stream of bits, made by [Whitening::whiten_bits].
+#[derive(Debug, Clone)]
+pub struct WhitenedBits<I: Iterator<Item = bool>> {
+    whitener: BitWhitener,
+    bits: I,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for WhitenedBits<I> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        let _bit = self.bits.next()?;
+        Some(self.whitener.whiten_bit(_bit))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bits.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qpacket::{encode_qpacket, MIN_QPACKET};
+    use crate::telemetry::{decode_packet, encode_packet, make_packet_skeleton_nofec};
+
+    /// Longest run of identical bits in `_data`.
+    fn longest_run(_data: &[u8]) -> usize {
+        let mut _longest: usize = 0;
+        let mut _run: usize = 0;
+        let mut _previous: Option<bool> = None;
+        for i in 0.._data.len() * 8 {
+            let _bit = _data[i / 8] & (0x80 >> (i % 8)) != 0;
+            _run = if _previous == Some(_bit) { _run + 1 } else { 1 };
+            _previous = Some(_bit);
+            _longest = _longest.max(_run);
+        }
+        _longest
+    }
+
+    #[test]
+    fn test_pn9_sequence() {
+        let mut _whitener = Pn9Whitener::new();
+        let _sequence: [u8; 8] = core::array::from_fn(|_| _whitener.next_byte());
+        assert_eq!(_sequence, [0xFF, 0xE1, 0x1D, 0x9A, 0xED, 0x85, 0x33, 0x24]);
+    }
+
+    #[test]
+    fn test_whitening_roundtrip() {
+        let _packet = make_packet_skeleton_nofec(false);
+        for _whitening in [Whitening::None, Whitening::G3ruh, Whitening::Pn9] {
+            let mut _frame = encode_packet(&_packet);
+            _whitening.whiten(&mut _frame);
+            if _whitening != Whitening::None {
+                assert_ne!(_frame, encode_packet(&_packet));
+            }
+            _whitening.dewhiten(&mut _frame);
+            assert_eq!(decode_packet(&_frame, &[]).unwrap().0, _packet);
+        }
+    }
+
+    #[test]
+    fn test_bit_whitener() {
+        let _packet = encode_qpacket(MIN_QPACKET);
+        for _whitening in [Whitening::None, Whitening::G3ruh, Whitening::Pn9] {
+            let mut _frame = _packet;
+            _whitening.whiten(&mut _frame);
+
+            let mut _bits = _whitening.whiten_bits(
+                (0.._packet.len() * 8).map(|i| _packet[i / 8] & (0x80 >> (i % 8)) != 0),
+            );
+            let mut _whitener = BitWhitener::new(_whitening);
+            for i in 0.._frame.len() * 8 {
+                let _bit = _frame[i / 8] & (0x80 >> (i % 8)) != 0;
+                assert_eq!(_bits.next(), Some(_bit), "{:?} bit {}", _whitening, i);
+                let _original = _packet[i / 8] & (0x80 >> (i % 8)) != 0;
+                assert_eq!(_whitener.dewhiten_bit(_bit), _original);
+            }
+            assert_eq!(_bits.next(), None);
+        }
+        assert_eq!(Whitening::from_name("PN9"), Some(Whitening::Pn9));
+        assert_eq!(Whitening::from_name("scrambled"), None);
+    }
+
+    #[test]
+    fn test_whitening_breaks_runs() {
+        let _raw = encode_qpacket(MIN_QPACKET);
+        assert!(longest_run(&_raw) >= 24);
+        for _whitening in [Whitening::G3ruh, Whitening::Pn9] {
+            let mut _frame = _raw;
+            _whitening.whiten(&mut _frame);
+            assert!(longest_run(&_frame) <= 12, "{:?}", _whitening);
+        }
+    }
+
+    #[test]
+    fn test_g3ruh_resynchronises() {
+        let mut _data = [0x5Au8; 16];
+        G3ruhScrambler::new().scramble(&mut _data);
+        // a descrambler that has never seen this stream
+        G3ruhScrambler::with_state(0).descramble(&mut _data);
+        assert!(_data[3..].iter().all(|&_byte| _byte == 0x5A));
+    }
+}